@@ -1,86 +1,665 @@
+use std::f64::consts;
 use std::fmt;
-use std::ops;
+use std::slice;
 use std::str;
+use std::sync::Arc;
 
+use crate::bigint::BigInt;
+use crate::builtins::{self, Arity, Builtin};
+use crate::calculus;
+use crate::complex::Complex;
+use crate::decimal::Decimal;
+use crate::env::{Env, IntType, Mode, Policy};
+use crate::error::{Error, EvalError, EvalErrorKind, Span};
+use crate::function::{self, Function, HigherOrder};
 use crate::lex::Token;
-use crate::parser;
+use crate::list::{self, ListFn};
+use crate::matrix;
+use crate::parser::{self, Assoc, Fixity};
+use crate::radix::{self, Radix};
+use crate::rational::Rational;
+use crate::simplify;
+use crate::solve;
+use crate::units;
+use crate::value::Value;
 
+/// The syntax tree of an expression. Each node is a
+/// token along with the operands it applies to.
+#[derive(Clone)]
 pub struct Ast {
     pub tok: Token,
     pub children: Vec<Ast>,
-    grouped: bool,
+    /// The text this node was parsed from.
+    pub span: Span,
 }
 
 #[doc(hidden)]
-pub fn eval(ast: &Ast) -> f64 {
+pub fn eval(ast: &Ast, env: &mut Env) -> Result<Value, EvalError> {
+    let res = node(ast, env)?;
+    Ok(match env.int() {
+        Some(int) => wrap(res, int),
+        None => res,
+    })
+}
+
+/// Evaluate a node without applying the environment's integer type.
+fn node(ast: &Ast, env: &mut Env) -> Result<Value, EvalError> {
+    match &ast.tok {
+        Token::Func(name) => {
+            if let Some(Value::Function(func)) = env.get(name) {
+                return apply(&func.clone(), ast, env);
+            }
+            if let Some(form) = calculus::lookup(name, ast.children.len()) {
+                return form(&ast.children, env).map_err(|kind| EvalError::new(kind, ast.span));
+            }
+            if let Some(func) = function::lookup(name) {
+                return higher_order(func, ast, env);
+            }
+            if let Some(func) = list::lookup(name).or_else(|| matrix::lookup(name)) {
+                return list_builtin(func, ast, env);
+            }
+            return call(name, ast, env);
+        }
+        Token::List => return list(ast, env),
+        Token::Lambda => return lambda(ast, env).map_err(|kind| EvalError::new(kind, ast.span)),
+        Token::Call => return call_value(ast, env),
+        _ => (),
+    }
     match ast.children.len() {
-        // numeric types
-        0 => match ast.tok {
-            Token::Int(n) => n as f64,
-            Token::Float(f) => f,
-            _ => panic!("zero children should be numeric"),
+        0 => leaf(ast, env),
+        1 => unary(ast, env),
+        2 => binary(ast, env),
+        3 if ast.tok == Token::If => match condition(&ast.children[0], env)? {
+            true => eval(&ast.children[1], env),
+            false => eval(&ast.children[2], env),
         },
-        // unary operators
-        1 => match ast.tok {
-            Token::Op('-') => -1.0 * eval(&ast.children[0]),
-            Token::Op('%') => eval(&ast.children[0]) / 100.0,
-            _ => panic!("invalid unary operator"),
-        },
-        // binary operators
-        2 => match ast.tok {
-            Token::Op(c) => {
-                let left = ast.children[0].clone();
-                let right = &ast.children[1];
-                match c {
-                    '+' => left + right,
-                    '-' => left - right,
-                    '*' => left * right,
-                    '/' => left / right,
-                    '^' => {
-                        let base = eval(&ast.children[0]);
-                        return base.powf(eval(right));
-                    }
-                    '%' => (eval(&ast.children[0]) / 100.0) * eval(&ast.children[1]),
-                    _ => panic!("invalid binary operation"),
+        n => Err(EvalError::new(malformed(&ast.tok, n), ast.span)),
+    }
+}
+
+// The operands are evaluated apart from applying the operators, so
+// that recursive calls to user defined functions only go through the
+// small stack frames of the functions evaluating the operands.
+
+/// Evaluate a number or variable.
+fn leaf(ast: &Ast, env: &Env) -> Result<Value, EvalError> {
+    let err = |kind| Err(EvalError::new(kind, ast.span));
+    Ok(match &ast.tok {
+        Token::Int(n) => literal(Rational::from_int(n.clone()), n.to_f64(), env),
+        Token::Float(d) => {
+            let float = d.to_f64();
+            let exact = match env.mode() {
+                Mode::Exact => d.to_rational(),
+                Mode::Decimal => return Ok(Value::Decimal(d.rounded(&env.context()))),
+                Mode::Float => None,
+            };
+            match exact {
+                Some(r) => Value::Rational(r),
+                // too large for a fraction, or written in float mode
+                None if float.is_infinite() && env.policy() == Policy::Error => {
+                    return err(EvalErrorKind::Overflow(d.to_string()))
                 }
+                None => Value::Float(float),
             }
-            Token::Modulus => eval(&ast.children[0]) % eval(&ast.children[1]),
-            _ => panic!("invalid binary operator"),
+        }
+        Token::Imaginary(f) => Value::complex(Complex::new(0.0, *f)),
+        Token::Ident(name) => match env.get(name) {
+            Some(val) => val.clone(),
+            None => match builtins::constant(name, env).or_else(|| units::lookup(name)) {
+                Some(val) => val,
+                None => return err(EvalErrorKind::UndefinedVariable(name.clone())),
+            },
         },
-        // ternary operators
-        3 => 0.0,
-        _ => 0.0,
+        tok => return err(malformed(tok, 0)),
+    })
+}
+
+fn unary(ast: &Ast, env: &mut Env) -> Result<Value, EvalError> {
+    if ast.tok == Token::Not {
+        return Ok(Value::Bool(!condition(&ast.children[0], env)?));
     }
+    let x = numbers(&ast.children[0], env)?;
+    unary_each(ast, x, env)
 }
 
-impl str::FromStr for Ast {
-    type Err = String;
+/// Apply a unary operator to a number, or to each item of a list.
+fn unary_each(ast: &Ast, x: Value, env: &Env) -> Result<Value, EvalError> {
+    match x {
+        Value::List(items) => items
+            .into_iter()
+            .map(|x| unary_each(ast, x, env))
+            .collect::<Result<_, _>>()
+            .map(Value::List),
+        x => {
+            let x = scalar(x, env).map_err(|kind| EvalError::new(kind, ast.span))?;
+            unary_op(ast, x, env)
+        }
+    }
+}
 
-    /// Constructs an expression by parsing a string.
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        parser::parse(s)
+fn unary_op(ast: &Ast, x: Value, env: &Env) -> Result<Value, EvalError> {
+    let err = |kind| Err(EvalError::new(kind, ast.span));
+    let ctx = env.context();
+    let hundred = Value::Rational(Rational::from_int(100));
+    let res = match ast.tok {
+        Token::Op('-') => Ok(x.neg()),
+        Token::Negation => x.not(&ctx),
+        Token::Op('%') => x.div(&hundred, &ctx),
+        // degrees to radians
+        Token::Op('°') => x.mul(&Value::Float(consts::PI / 180.0), &ctx),
+        Token::Factorial => {
+            if let Err(kind) = units::dimensionless(&x) {
+                return err(kind);
+            }
+            let exact = x.to_rational();
+            let res = match exact.as_ref().and_then(builtins::exact_factorial) {
+                Some(r) => x.exact_result(r, &ctx),
+                None => Value::Float(builtins::factorial(x.as_f64().unwrap_or(f64::NAN))),
+            };
+            return check(
+                env,
+                ast,
+                || format!("{}!", operand(&x)),
+                slice::from_ref(&x),
+                res,
+            );
+        }
+        ref tok => return err(malformed(tok, 1)),
+    };
+    res.map_err(|kind| EvalError::new(kind, ast.span))
+}
+
+fn binary(ast: &Ast, env: &mut Env) -> Result<Value, EvalError> {
+    match ast.tok {
+        Token::Assign => return assign(ast, env),
+        Token::And | Token::Or => return logic(ast, env),
+        Token::Op('<' | '>')
+        | Token::LessEqual
+        | Token::GreaterEqual
+        | Token::EqualEqual
+        | Token::NotEqual => return compare(ast, env),
+        Token::Index => return index(ast, env),
+        Token::Equal => {
+            return solve::equation(ast, env).map_err(|kind| EvalError::new(kind, ast.span))
+        }
+        Token::Op('@') => {
+            let left = eval(&ast.children[0], env)?;
+            let right = eval(&ast.children[1], env)?;
+            return matrix::matmul(&left, &right, &env.context())
+                .map_err(|kind| EvalError::new(kind, ast.span));
+        }
+        Token::Range => {
+            let start = number(&ast.children[0], env)?;
+            let end = number(&ast.children[1], env)?;
+            return list::range(&start, &end, &env.context())
+                .map_err(|kind| EvalError::new(kind, ast.span));
+        }
+        _ => (),
     }
+    if let Some(base) = radix_target(ast, env) {
+        let x = number(&ast.children[0], env)?;
+        return to_radix(x, base, env).map_err(|kind| EvalError::new(kind, ast.span));
+    }
+    let left = numbers(&ast.children[0], env)?;
+    let right = numbers(&ast.children[1], env)?;
+    binary_each(ast, left, right, env)
 }
 
-impl<'a> Clone for Ast {
-    fn clone(&self) -> Self {
-        Self {
-            tok: self.tok,
-            children: self.children.clone(),
-            grouped: self.grouped,
+/// Apply a binary operator to two numbers, or item by item when either
+/// operand is a list. A number is paired with every item of a list,
+/// while two lists are paired item by item and need the same length.
+fn binary_each(ast: &Ast, left: Value, right: Value, env: &Env) -> Result<Value, EvalError> {
+    let err = |kind| EvalError::new(kind, ast.span);
+    let items: Result<Vec<_>, _> = match (left, right) {
+        (Value::List(xs), Value::List(ys)) => {
+            if xs.len() != ys.len() {
+                return Err(err(EvalErrorKind::Shape(format!(
+                    "cannot combine lists of lengths {} and {}",
+                    xs.len(),
+                    ys.len()
+                ))));
+            }
+            xs.into_iter()
+                .zip(ys)
+                .map(|(x, y)| binary_each(ast, x, y, env))
+                .collect()
+        }
+        (Value::List(xs), y) => xs
+            .into_iter()
+            .map(|x| binary_each(ast, x, y.clone(), env))
+            .collect(),
+        (x, Value::List(ys)) => ys
+            .into_iter()
+            .map(|y| binary_each(ast, x.clone(), y, env))
+            .collect(),
+        (x, y) => {
+            let x = scalar(x, env).map_err(err)?;
+            let y = scalar(y, env).map_err(err)?;
+            return binary_op(ast, x, y, env);
+        }
+    };
+    items.map(Value::List)
+}
+
+/// Evaluate `xs[i]`, counting from the end when `i` is negative.
+fn index(ast: &Ast, env: &mut Env) -> Result<Value, EvalError> {
+    let err = |kind| EvalError::new(kind, ast.span);
+    let xs = eval(&ast.children[0], env)?;
+    let i = number(&ast.children[1], env)?;
+    let items = list::items(&xs).map_err(err)?;
+    let i = i.integer().map_err(err)?;
+    let len = BigInt::from(items.len() as i64);
+    let pos = if i.is_negative() {
+        &len + &i
+    } else {
+        i.clone()
+    };
+    match pos.to_i64().filter(|&p| p >= 0 && p < items.len() as i64) {
+        Some(p) => Ok(items[p as usize].clone()),
+        None => Err(err(EvalErrorKind::Overflow(format!("index {}", i)))),
+    }
+}
+
+fn binary_op(ast: &Ast, left: Value, right: Value, env: &Env) -> Result<Value, EvalError> {
+    let err = |kind| Err(EvalError::new(kind, ast.span));
+    let ctx = env.context();
+    let hundred = Value::Rational(Rational::from_int(100));
+    let res = match ast.tok {
+        Token::Op('+') => left.add(&right, &ctx),
+        Token::Op('-') => left.sub(&right, &ctx),
+        Token::Op('*') => left.mul(&right, &ctx),
+        Token::Op('/') | Token::Modulus if right.is_zero() && env.policy() == Policy::Error => {
+            return err(EvalErrorKind::DivisionByZero)
+        }
+        Token::Op('/') => left.div(&right, &ctx),
+        Token::Modulus => left.rem(&right, &ctx),
+        Token::Op('^') => left.pow(&right, &ctx),
+        Token::To | Token::In => units::convert(&left, &right, &ctx),
+        Token::Op('&') => left.bitand(&right, &ctx),
+        Token::Op('|') => left.bitor(&right, &ctx),
+        Token::Xor => left.bitxor(&right, &ctx),
+        Token::ShiftLeft => left.shift(&right, true, &ctx),
+        Token::ShiftRight => left.shift(&right, false, &ctx),
+        Token::RotateLeft => left.rotate(&right, env.width(), true, &ctx),
+        Token::RotateRight => left.rotate(&right, env.width(), false, &ctx),
+        Token::Op('%') => left.div(&hundred, &ctx).and_then(|p| p.mul(&right, &ctx)),
+        // r∠θ = r * e^(iθ)
+        Token::Op('∠') => units::dimensionless(&right).and_then(|_| {
+            let turn = Complex::I.mul(&right.as_complex().unwrap());
+            left.mul(&Value::complex(turn.exp()), &ctx)
+        }),
+        ref tok => return err(malformed(tok, 2)),
+    };
+    let res = res.map_err(|kind| EvalError::new(kind, ast.span))?;
+    let describe = || format!("{} {} {}", operand(&left), ast.tok, operand(&right));
+    // a power of a fraction too small to keep exact is out of
+    // range too, rather than the zero a float rounds it to
+    let underflow = match (&left, &right, &res) {
+        (Value::Rational(a), Value::Rational(_), Value::Float(f)) => !a.is_zero() && *f == 0.0,
+        _ => false,
+    };
+    if ast.tok == Token::Op('^') && underflow && env.policy() == Policy::Error {
+        return err(EvalErrorKind::Overflow(describe()));
+    }
+    check(env, ast, describe, &[left.clone(), right.clone()], res)
+}
+
+fn list(ast: &Ast, env: &mut Env) -> Result<Value, EvalError> {
+    let mut items = Vec::with_capacity(ast.children.len());
+    for item in &ast.children {
+        items.push(eval(item, env)?);
+    }
+    Ok(Value::List(items))
+}
+
+/// Make a function from a lambda node, the parameters followed by the body.
+fn lambda(ast: &Ast, env: &Env) -> Result<Value, EvalErrorKind> {
+    let (body, params) = match ast.children.split_last() {
+        Some(split) => split,
+        None => return Err(malformed(&ast.tok, 0)),
+    };
+    let params = params
+        .iter()
+        .map(|p| match &p.tok {
+            Token::Ident(name) => Ok(name.clone()),
+            tok => Err(EvalErrorKind::Malformed(format!(
+                "'{}' is not a parameter",
+                tok
+            ))),
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(Value::Function(Arc::new(Function::lambda(
+        params,
+        body.clone(),
+        env,
+    ))))
+}
+
+/// Evaluate `and` or `or`, skipping the right hand side
+/// when the left hand side decides the result.
+fn logic(ast: &Ast, env: &mut Env) -> Result<Value, EvalError> {
+    let left = condition(&ast.children[0], env)?;
+    let res = match ast.tok {
+        Token::And if !left => false,
+        Token::Or if left => true,
+        _ => condition(&ast.children[1], env)?,
+    };
+    Ok(Value::Bool(res))
+}
+
+fn compare(ast: &Ast, env: &mut Env) -> Result<Value, EvalError> {
+    let left = eval(&ast.children[0], env)?;
+    let right = eval(&ast.children[1], env)?;
+    let ctx = env.context();
+    let res = match ast.tok {
+        Token::EqualEqual => left.equals(&right, &ctx),
+        Token::NotEqual => left.equals(&right, &ctx).map(|eq| !eq),
+        ref tok => left.compare(&right, &ctx).map(|ord| match (tok, ord) {
+            (_, None) => false,
+            (Token::Op('<'), Some(ord)) => ord.is_lt(),
+            (Token::LessEqual, Some(ord)) => ord.is_le(),
+            (Token::Op('>'), Some(ord)) => ord.is_gt(),
+            (_, Some(ord)) => ord.is_ge(),
+        }),
+    };
+    res.map(Value::Bool)
+        .map_err(|kind| EvalError::new(kind, ast.span))
+}
+
+/// Evaluate a node that has to produce `true` or `false`.
+fn condition(ast: &Ast, env: &mut Env) -> Result<bool, EvalError> {
+    match eval(ast, env)? {
+        Value::Bool(b) => Ok(b),
+        val => Err(EvalError::new(
+            EvalErrorKind::Type(format!("expected true or false but got {}", val)),
+            ast.span,
+        )),
+    }
+}
+
+/// Truncate a result to an integer and wrap it around to fit in `int`
+/// like C does, item by item for a list. Complex numbers, quantities, infinities and numbers
+/// written in another base are left alone.
+fn wrap(res: Value, int: IntType) -> Value {
+    if let Value::List(items) = res {
+        return Value::List(items.into_iter().map(|x| wrap(x, int)).collect());
+    }
+    let n = match &res {
+        Value::Float(f) if f.is_finite() => Rational::from_f64(*f),
+        Value::Rational(_) | Value::Decimal(_) => res.to_rational(),
+        _ => None,
+    };
+    match n {
+        Some(n) => Value::Rational(Rational::from_int(int.wrap(n.trunc().numer()))),
+        None => res,
+    }
+}
+
+/// The value of a number literal in the environment's mode.
+fn literal(exact: Rational, float: f64, env: &Env) -> Value {
+    match env.mode() {
+        Mode::Exact => Value::Rational(exact),
+        Mode::Decimal => Value::Decimal(Decimal::from_rational(&exact, &env.context())),
+        Mode::Float => Value::Float(float),
+    }
+}
+
+/// Evaluate a node that has to produce a number.
+fn number(ast: &Ast, env: &mut Env) -> Result<Value, EvalError> {
+    let val = eval(ast, env)?;
+    scalar(val, env).map_err(|kind| EvalError::new(kind, ast.span))
+}
+
+/// Evaluate a node that has to produce a number, or a list that
+/// operators and functions apply to item by item.
+fn numbers(ast: &Ast, env: &mut Env) -> Result<Value, EvalError> {
+    match eval(ast, env)? {
+        Value::List(items) => Ok(Value::List(items)),
+        val => scalar(val, env).map_err(|kind| EvalError::new(kind, ast.span)),
+    }
+}
+
+/// Check that a value is a number. Integers written
+/// in another base are used as plain integers.
+fn scalar(val: Value, env: &Env) -> Result<Value, EvalErrorKind> {
+    match val {
+        Value::Radix(r) => {
+            let n = r.value();
+            Ok(literal(Rational::from_int(n.clone()), n.to_f64(), env))
+        }
+        val if !val.is_number() => Err(EvalErrorKind::Type(format!(
+            "expected a number but got {}",
+            val
+        ))),
+        val => Ok(val),
+    }
+}
+
+/// The base of a conversion like `255 to hex`, unless
+/// a variable has the name of the base.
+fn radix_target(ast: &Ast, env: &Env) -> Option<u32> {
+    match (&ast.tok, &ast.children[1].tok) {
+        (Token::To | Token::In, Token::Ident(name)) if env.get(name).is_none() => radix::base(name),
+        _ => None,
+    }
+}
+
+fn to_radix(x: Value, base: u32, env: &Env) -> Result<Value, EvalErrorKind> {
+    let n = match &x {
+        Value::Float(f) => Rational::from_f64(*f),
+        x => x.to_rational(),
+    };
+    match n {
+        _ if base == 10 => Ok(x),
+        Some(n) if n.is_integer() => Ok(Value::Radix(Radix::new(
+            n.numer().clone(),
+            base,
+            env.width(),
+        ))),
+        _ => Err(EvalErrorKind::NotInteger(x.to_string())),
+    }
+}
+
+fn assign(ast: &Ast, env: &mut Env) -> Result<Value, EvalError> {
+    let target = &ast.children[0];
+    let name = match &target.tok {
+        Token::Ident(name) => name.clone(),
+        Token::Func(name) => {
+            let params = target.children.iter().map(|p| p.tok.to_string()).collect();
+            let func = Function::new(name, params, ast.children[1].clone());
+            let val = Value::Function(Arc::new(func));
+            env.set(name, val.clone());
+            return Ok(val);
+        }
+        tok => {
+            return Err(EvalError::new(
+                EvalErrorKind::Malformed(format!("cannot assign to '{}'", tok)),
+                ast.children[0].span,
+            ))
+        }
+    };
+    let val = eval(&ast.children[1], env)?;
+    env.set(&name, val.clone());
+    Ok(val)
+}
+
+fn call(name: &str, ast: &Ast, env: &mut Env) -> Result<Value, EvalError> {
+    let func = match builtins::lookup(name) {
+        Some(func) => func,
+        None => {
+            return Err(EvalError::new(
+                EvalErrorKind::UnknownFunction(String::from(name)),
+                ast.span,
+            ))
         }
+    };
+    let mut args = Vec::with_capacity(ast.children.len());
+    for arg in &ast.children {
+        args.push(numbers(arg, env)?);
+    }
+    builtin(func, ast, args, env)
+}
+
+/// Call a builtin on numbers. A list given as the only argument is
+/// spread out as the arguments of a function like `max` that takes
+/// any number of them, and is mapped over by any other function.
+fn builtin(func: &Builtin, ast: &Ast, args: Vec<Value>, env: &Env) -> Result<Value, EvalError> {
+    let err = |kind| EvalError::new(kind, ast.span);
+    if let [Value::List(items)] = args.as_slice() {
+        if let Arity::AtLeast(_) = func.arity {
+            return builtin(func, ast, items.clone(), env);
+        }
+        return items
+            .iter()
+            .map(|x| builtin(func, ast, vec![x.clone()], env))
+            .collect::<Result<_, _>>()
+            .map(Value::List);
+    }
+    let args = args
+        .into_iter()
+        .map(|a| scalar(a, env))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(err)?;
+    let res = func.call(&args, &env.context()).map_err(err)?;
+    let describe = || {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        format!("{}({})", func.name, args.join(", "))
+    };
+    check(env, ast, describe, &args, res)
+}
+
+/// Call a user defined function or lambda. Errors in the body are
+/// reported at the call, since the body's spans point into the text
+/// of the definition.
+fn apply(func: &Function, ast: &Ast, env: &mut Env) -> Result<Value, EvalError> {
+    let mut args = Vec::with_capacity(ast.children.len());
+    for arg in &ast.children {
+        args.push(eval(arg, env)?);
+    }
+    func.apply(args, env)
+        .map_err(|kind| EvalError::new(kind, ast.span))
+}
+
+/// Call the function the first child gives back with the
+/// others as arguments, like `adder(3)(4)`.
+fn call_value(ast: &Ast, env: &mut Env) -> Result<Value, EvalError> {
+    let (callee, args) = match ast.children.split_first() {
+        Some(split) => split,
+        None => return Err(EvalError::new(malformed(&ast.tok, 0), ast.span)),
+    };
+    let func = eval(callee, env)?;
+    let func = function::function(&func).map_err(|kind| EvalError::new(kind, callee.span))?;
+    let mut vals = Vec::with_capacity(args.len());
+    for arg in args {
+        vals.push(eval(arg, env)?);
+    }
+    func.apply(vals, env)
+        .map_err(|kind| EvalError::new(kind, ast.span))
+}
+
+/// Call a builtin like `sum` that takes lists as arguments.
+fn list_builtin(func: ListFn, ast: &Ast, env: &mut Env) -> Result<Value, EvalError> {
+    let mut args = Vec::with_capacity(ast.children.len());
+    for arg in &ast.children {
+        args.push(eval(arg, env)?);
+    }
+    func(&args, &env.context()).map_err(|kind| EvalError::new(kind, ast.span))
+}
+
+/// Call a builtin like `map` that takes functions as arguments.
+fn higher_order(func: HigherOrder, ast: &Ast, env: &mut Env) -> Result<Value, EvalError> {
+    let mut args = Vec::with_capacity(ast.children.len());
+    for arg in &ast.children {
+        match builtin_arg(arg, env) {
+            Some(func) => args.push(func),
+            None => args.push(eval(arg, env)?),
+        }
+    }
+    func(&args, env).map_err(|kind| EvalError::new(kind, ast.span))
+}
+
+/// A builtin passed by name like `sqrt` in `map(sqrt, xs)`, as
+/// a function, unless there is a variable with that name.
+fn builtin_arg(arg: &Ast, env: &Env) -> Option<Value> {
+    match &arg.tok {
+        Token::Ident(name) if arg.children.is_empty() && env.get(name).is_none() => {
+            let func = builtins::lookup(name)?;
+            Some(Value::Function(Arc::new(Function::builtin(func))))
+        }
+        _ => None,
+    }
+}
+
+/// Apply the environment's policy to `res`, the result of an operation
+/// on `args`. NaN and infinity are only reported when the operation
+/// created them, not when they were passed in.
+fn check<F>(
+    env: &Env,
+    ast: &Ast,
+    describe: F,
+    args: &[Value],
+    res: Value,
+) -> Result<Value, EvalError>
+where
+    F: Fn() -> String,
+{
+    let f = match res.magnitude() {
+        Value::Float(f) if env.policy() == Policy::Error => *f,
+        _ => return Ok(res),
+    };
+    let args: Vec<f64> = args.iter().filter_map(|a| a.magnitude().as_f64()).collect();
+    let kind = if f.is_nan() && !args.iter().any(|a| a.is_nan()) {
+        EvalErrorKind::Domain(describe())
+    } else if f.is_infinite() && args.iter().all(|a| a.is_finite()) {
+        EvalErrorKind::Overflow(describe())
+    } else {
+        return Ok(res);
+    };
+    Err(EvalError::new(kind, ast.span))
+}
+
+/// Format a number as the operand of an operator.
+fn operand(x: &Value) -> String {
+    if x.is_negative() {
+        format!("({})", x)
+    } else {
+        x.to_string()
     }
 }
 
-impl<'a> Ast {
+fn malformed(tok: &Token, children: usize) -> EvalErrorKind {
+    EvalErrorKind::Malformed(match children {
+        0 => format!("'{}' is not a value", tok),
+        1 => format!("'{}' is not a unary operator", tok),
+        2 => format!("'{}' is not a binary operator", tok),
+        n => format!("'{}' cannot have {} operands", tok, n),
+    })
+}
+
+impl str::FromStr for Ast {
+    type Err = Error;
+
+    /// Constructs an expression by parsing a string.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parser::parse(s)
+    }
+}
+
+impl Ast {
     pub fn new(t: Token) -> Self {
         Self {
             tok: t,
             children: vec![],
-            grouped: false,
+            span: Span::default(),
         }
     }
 
+    /// Set the span of text this node covers.
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = span;
+        self
+    }
+
     pub fn from(t: Token, children: Vec<Ast>) -> Self {
         let mut ast = Self::new(t);
         for c in children {
@@ -90,117 +669,143 @@ impl<'a> Ast {
     }
 
     pub fn push(&mut self, ast: Ast) {
-        use Token::{Modulus, Op};
-        let grouped = ast.grouped;
-        let tok = ast.tok;
-
         self.children.push(ast);
-
-        // if the ast has been marked as
-        // a group then we don't want to
-        // break that group
-        if grouped {
-            return;
-        }
-        // we only do rotation if we
-        // already have a left child
-        if self.children.len() == 0 {
-            return;
-        }
-
-        match (self.tok, tok) {
-            (Op('+'), ..) | (Op('-'), ..) | (.., Op('+')) | (.., Op('-')) => {}
-            (Op('/'), Op('/'))
-            | (Op('/'), Modulus)
-            | (Op('/'), Op('*'))
-            | (Op('*'), Op('*'))
-            | (Op('*'), Op('/'))
-            | (Op('*'), Modulus)
-            | (Op('^'), Op('*'))
-            | (Op('^'), Op('/'))
-            | (Op('^'), Modulus)
-            | (Op('%'), Op('*'))
-            | (Op('%'), Op('/'))
-            | (Modulus, Modulus)
-            | (Modulus, Op('*'))
-            | (Modulus, Op('/')) => {
-                self.rotate_left();
-            }
-            _ => {}
-        }
     }
 
-    fn rotate_left(&mut self) {
-        if self.children.len() < 2 {
-            return;
-        }
-        let newroot = self.children[1].tok;
-        self.children[0] = Ast::from(
-            self.tok,
-            vec![
-                self.children[0].clone(),
-                self.children[1].children[0].clone(),
-            ],
-        );
-        self.children[1] = self.children[1].children[1].clone();
-        self.tok = newroot;
+    /// An expression with the same value written more simply: parts
+    /// without variables are worked out when the result is exact,
+    /// percentages become multiplications and like terms are collected.
+    pub fn simplify(&self) -> Ast {
+        simplify::simplify(self)
     }
 
-    pub fn as_grouped(&self) -> Self {
-        let mut ast = self.clone();
-        ast.grouped = true;
-        ast
+    /// The derivative with respect to the variable `var`, going through
+    /// the functions defined in `env`.
+    pub fn derivative(&self, var: &str, env: &Env) -> Result<Ast, EvalErrorKind> {
+        calculus::derivative(self, var, env)
     }
 }
 
-impl<'a> ops::Add<&'a Ast> for Ast {
-    type Output = f64;
-    fn add(self, rhs: &'a Ast) -> Self::Output {
-        eval(&self) + eval(rhs)
+/// How tightly the node binds its operands when written out, so
+/// that operands binding looser can be put in parentheses.
+fn binding(ast: &Ast) -> u8 {
+    let negative = match &ast.tok {
+        Token::Int(n) => n.is_negative(),
+        Token::Float(d) => d.is_negative(),
+        Token::Imaginary(f) => *f < 0.0,
+        _ => false,
+    };
+    // a negative number is written with a minus sign
+    let (tok, n) = match negative {
+        true => (&Token::Op('-'), 1),
+        false => (&ast.tok, ast.children.len()),
+    };
+    match (tok, n) {
+        (_, 0) | (Token::Func(_) | Token::List | Token::Call, _) => u8::MAX,
+        (tok, n) => parser::operator(tok, n).map_or(0, |op| op.bp),
     }
 }
 
-impl<'a> ops::Sub<&'a Ast> for Ast {
-    type Output = f64;
-    fn sub(self, rhs: &'a Ast) -> Self::Output {
-        eval(&self) - eval(rhs)
+/// Write `ast` as an operand that binds at least `min`
+/// tightly, in parentheses if it binds looser.
+fn operand_source(ast: &Ast, min: u8, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match binding(ast) < min {
+        true => write!(f, "({})", ast),
+        false => write!(f, "{}", ast),
     }
 }
 
-impl<'a> ops::Mul<&'a Ast> for Ast {
-    type Output = f64;
-    fn mul(self, rhs: &'a Ast) -> Self::Output {
-        eval(&self) * eval(rhs)
-    }
-}
-
-impl<'a> ops::Div<&'a Ast> for Ast {
-    type Output = f64;
-    fn div(self, rhs: &'a Ast) -> Self::Output {
-        eval(&self) / eval(rhs)
-    }
-}
-
-fn _format(ast: &Ast, f: &mut fmt::Formatter) -> fmt::Result {
-    let len = ast.children.len();
-    write!(f, "Ast({:?}: [", ast.tok)?;
-    if len > 0 {
-        for i in 0..(len - 1) {
-            write!(f, "{:?}, ", ast.children[i].tok)?;
+fn comma_separated(items: &[Ast], f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
         }
-        write!(f, "{:?}])", ast.children[len - 1].tok)
-    } else {
-        write!(f, "])")
+        write!(f, "{}", item)?;
     }
+    Ok(())
 }
 
-impl<'a> fmt::Display for Ast {
+impl fmt::Display for Ast {
+    /// Written as source text that parses back to the same tree,
+    /// like `x^2 + 2 * (x - 1)`.
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        _format(self, f)
+        let children = &self.children;
+        match (&self.tok, children.len()) {
+            (Token::Func(name), _) => {
+                write!(f, "{}(", name)?;
+                comma_separated(children, f)?;
+                write!(f, ")")
+            }
+            (Token::List, _) => {
+                write!(f, "[")?;
+                comma_separated(children, f)?;
+                write!(f, "]")
+            }
+            (Token::Index, 2) => {
+                let bp = parser::operator(&Token::Index, 2).map_or(0, |op| op.bp);
+                operand_source(&children[0], bp, f)?;
+                write!(f, "[{}]", children[1])
+            }
+            (Token::Call, n) if n > 0 => {
+                match children[0].tok {
+                    Token::Func(_) | Token::Call => write!(f, "{}(", children[0])?,
+                    _ => write!(f, "({})(", children[0])?,
+                }
+                comma_separated(&children[1..], f)?;
+                write!(f, ")")
+            }
+            (Token::Lambda, 2) => write!(f, "{} -> {}", children[0], children[1]),
+            (Token::Lambda, n) => {
+                write!(f, "(")?;
+                comma_separated(&children[..n - 1], f)?;
+                write!(f, ") -> {}", children[n - 1])
+            }
+            (tok, 0) => write!(f, "{}", tok),
+            (Token::Assign, 2) => write!(f, "{} = {}", children[0], children[1]),
+            (Token::If, 3) => write!(
+                f,
+                "if {} then {} else {}",
+                children[0], children[1], children[2]
+            ),
+            (tok, n) => {
+                let op = match parser::operator(tok, n) {
+                    Some(op) => op,
+                    None => return write!(f, "{:?}", self),
+                };
+                let lhs = &children[0];
+                match op.fixity {
+                    Fixity::Prefix if *tok == Token::Not => write!(f, "not ")?,
+                    Fixity::Prefix => write!(f, "{}", tok)?,
+                    _ => (),
+                }
+                let min = match (op.fixity, op.assoc) {
+                    (Fixity::Infix, Assoc::Right) => op.bp + 1,
+                    _ => op.bp,
+                };
+                operand_source(lhs, min, f)?;
+                match op.fixity {
+                    Fixity::Prefix => Ok(()),
+                    Fixity::Postfix => write!(f, "{}", tok),
+                    Fixity::Infix => {
+                        match tok {
+                            Token::Op('^') => write!(f, "^")?,
+                            Token::Op('%') => write!(f, "% of ")?,
+                            tok => write!(f, " {} ", tok)?,
+                        }
+                        let rhs = &children[1];
+                        let min = match op.assoc {
+                            Assoc::Left => op.bp + 1,
+                            Assoc::Right => op.bp,
+                        };
+                        operand_source(rhs, min, f)
+                    }
+                }
+            }
+        }
     }
 }
 
-impl<'a> fmt::Debug for Ast {
+impl fmt::Debug for Ast {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         let len = self.children.len();
         write!(f, "Ast({:?}: [", self.tok)?;
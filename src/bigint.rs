@@ -0,0 +1,754 @@
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::{Add, BitAnd, BitOr, BitXor, Mul, Neg, Not, Sub};
+
+/// An arbitrary-precision integer stored as a sign and a magnitude of
+/// base 2^32 digits, least significant first. The magnitude never has
+/// trailing zero digits and zero is never negative.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct BigInt {
+    neg: bool,
+    mag: Vec<u32>,
+}
+
+/// The largest power of ten that fits in a digit.
+const DECIMAL_BASE: u32 = 1_000_000_000;
+const DECIMAL_DIGITS: usize = 9;
+
+impl BigInt {
+    pub fn zero() -> Self {
+        Self::default()
+    }
+
+    pub fn one() -> Self {
+        Self::from(1)
+    }
+
+    fn from_parts(neg: bool, mut mag: Vec<u32>) -> Self {
+        trim(&mut mag);
+        Self {
+            neg: neg && !mag.is_empty(),
+            mag,
+        }
+    }
+
+    /// Read a string of decimal digits with an optional leading minus.
+    pub fn from_decimal(s: &str) -> Option<Self> {
+        let (neg, digits) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let mut mag = Vec::with_capacity(digits.len() / DECIMAL_DIGITS + 1);
+        let first = digits.len() % DECIMAL_DIGITS;
+        let mut chunks = vec![&digits[..first]];
+        chunks.extend(
+            digits.as_bytes()[first..]
+                .chunks(DECIMAL_DIGITS)
+                .map(|c| std::str::from_utf8(c).unwrap()),
+        );
+        for chunk in chunks.into_iter().filter(|c| !c.is_empty()) {
+            let scale = 10_u32.pow(chunk.len() as u32);
+            mul_small_add(&mut mag, scale, chunk.parse().unwrap());
+        }
+        Some(Self::from_parts(neg, mag))
+    }
+
+    /// Parse the digits of a nonnegative integer in base `radix`,
+    /// which is at most 36.
+    pub fn from_radix(digits: &str, radix: u32) -> Option<Self> {
+        if digits.is_empty() {
+            return None;
+        }
+        let mut mag = Vec::new();
+        for c in digits.chars() {
+            mul_small_add(&mut mag, radix, c.to_digit(radix)?);
+        }
+        Some(Self::from_parts(false, mag))
+    }
+
+    /// The digits of the magnitude in base `radix`, in upper case.
+    pub fn to_radix(&self, radix: u32) -> String {
+        if self.is_zero() {
+            return String::from("0");
+        }
+        let mut n = self.abs();
+        let mut digits = Vec::new();
+        while !n.is_zero() {
+            let d = std::char::from_digit(n.div_small(radix), radix).unwrap();
+            digits.push(d.to_ascii_uppercase());
+        }
+        digits.iter().rev().collect()
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.mag.is_empty()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.neg
+    }
+
+    pub fn is_one(&self) -> bool {
+        !self.neg && self.mag == [1]
+    }
+
+    pub fn is_even(&self) -> bool {
+        self.mag.first().is_none_or(|d| d % 2 == 0)
+    }
+
+    pub fn abs(&self) -> Self {
+        Self {
+            neg: false,
+            mag: self.mag.clone(),
+        }
+    }
+
+    /// The number of bits in the magnitude.
+    pub fn bits(&self) -> u64 {
+        match self.mag.last() {
+            Some(top) => self.mag.len() as u64 * 32 - top.leading_zeros() as u64,
+            None => 0,
+        }
+    }
+
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.mag.len() > 2 {
+            return None;
+        }
+        let mag = self
+            .mag
+            .iter()
+            .rev()
+            .fold(0_u64, |acc, &d| acc << 32 | d as u64);
+        if self.neg {
+            0_i64.checked_sub_unsigned(mag)
+        } else {
+            i64::try_from(mag).ok()
+        }
+    }
+
+    /// The nearest float, or an infinity if it is out of range.
+    pub fn to_f64(&self) -> f64 {
+        let bits = self.bits();
+        // keep the top 64 bits, then scale back up
+        let shift = bits.saturating_sub(64);
+        let top = self.abs().shr(shift);
+        let mag = top
+            .mag
+            .iter()
+            .rev()
+            .fold(0_u64, |acc, &d| acc << 32 | d as u64);
+        let f = scale(mag as f64, shift as i64);
+        if self.neg {
+            -f
+        } else {
+            f
+        }
+    }
+
+    /// Shift the magnitude left by `n` bits, keeping the sign.
+    pub fn shl(&self, n: u64) -> Self {
+        if self.is_zero() {
+            return self.clone();
+        }
+        let mut mag = vec![0; (n / 32) as usize];
+        mag.extend_from_slice(&self.mag);
+        mag.push(0);
+        shl_bits(&mut mag[(n / 32) as usize..], (n % 32) as u32);
+        Self::from_parts(self.neg, mag)
+    }
+
+    /// Shift the magnitude right by `n` bits, keeping the sign.
+    /// This rounds the magnitude toward zero.
+    pub fn shr(&self, n: u64) -> Self {
+        let limbs = (n / 32) as usize;
+        if limbs >= self.mag.len() {
+            return Self::zero();
+        }
+        let mut mag = self.mag[limbs..].to_vec();
+        shr_bits(&mut mag, (n % 32) as u32);
+        Self::from_parts(self.neg, mag)
+    }
+
+    /// Shift left by `n` bits, or right when `n` is negative. Shifting
+    /// right rounds toward negative infinity like an arithmetic shift
+    /// on a two's complement number, so `-1 >> 1` is still -1.
+    pub fn shift(&self, n: i64) -> Self {
+        if n >= 0 {
+            return self.shl(n as u64);
+        }
+        let n = n.unsigned_abs();
+        match self.neg {
+            true => &-&(&-self - &Self::one()).shr(n) - &Self::one(),
+            false => self.shr(n),
+        }
+    }
+
+    /// Truncated division, so the remainder has the same sign as `self`.
+    /// Returns `None` when dividing by zero.
+    pub fn div_rem(&self, other: &Self) -> Option<(Self, Self)> {
+        if other.is_zero() {
+            return None;
+        }
+        let (q, r) = div_rem_mag(&self.mag, &other.mag);
+        Some((
+            Self::from_parts(self.neg != other.neg, q),
+            Self::from_parts(self.neg, r),
+        ))
+    }
+
+    /// Division rounding toward negative infinity.
+    pub fn div_floor(&self, other: &Self) -> Option<Self> {
+        let (q, r) = self.div_rem(other)?;
+        if !r.is_zero() && r.neg != other.neg {
+            Some(&q - &Self::one())
+        } else {
+            Some(q)
+        }
+    }
+
+    pub fn pow(&self, mut exp: u64) -> Self {
+        let mut base = self.clone();
+        let mut acc = Self::one();
+        while exp > 0 {
+            if exp & 1 == 1 {
+                acc = &acc * &base;
+            }
+            exp >>= 1;
+            if exp > 0 {
+                base = &base * &base;
+            }
+        }
+        acc
+    }
+
+    pub fn gcd(&self, other: &Self) -> Self {
+        let (mut a, mut b) = (self.abs(), other.abs());
+        while !b.is_zero() {
+            let (_, r) = a.div_rem(&b).unwrap();
+            a = b;
+            b = r;
+        }
+        a
+    }
+
+    /// The square root rounded down, or `None` for negative numbers.
+    pub fn sqrt(&self) -> Option<Self> {
+        if self.neg {
+            return None;
+        }
+        if self.is_zero() {
+            return Some(Self::zero());
+        }
+        // Newton's method from a first guess that is too big
+        let mut x = Self::one().shl(self.bits().div_ceil(2));
+        loop {
+            let (q, _) = self.div_rem(&x)?;
+            let y = (&x + &q).shr(1);
+            if y >= x {
+                return Some(x);
+            }
+            x = y;
+        }
+    }
+
+    /// Divide the magnitude by a small divisor in place,
+    /// giving back the remainder.
+    fn div_small(&mut self, d: u32) -> u32 {
+        let rem = div_small_mag(&mut self.mag, d);
+        trim(&mut self.mag);
+        if self.mag.is_empty() {
+            self.neg = false;
+        }
+        rem
+    }
+
+    /// The number of times `d` divides `self` exactly, and the
+    /// quotient left over after dividing by it that many times.
+    pub fn remove_factor(&self, d: u32) -> (u64, Self) {
+        let mut n = self.clone();
+        let mut count = 0;
+        if n.is_zero() {
+            return (0, n);
+        }
+        loop {
+            let mut q = n.clone();
+            if q.div_small(d) != 0 {
+                return (count, n);
+            }
+            n = q;
+            count += 1;
+        }
+    }
+}
+
+/// Multiply `f` by two to the power of `exp` without
+/// overflowing or underflowing along the way.
+pub(crate) fn scale(mut f: f64, mut exp: i64) -> f64 {
+    while exp > 1000 {
+        f *= 2_f64.powi(1000);
+        exp -= 1000;
+    }
+    while exp < -1000 {
+        f *= 2_f64.powi(-1000);
+        exp += 1000;
+    }
+    f * 2_f64.powi(exp as i32)
+}
+
+/// The limbs of a number in two's complement, sign extended to `len`.
+fn twos(n: &BigInt, len: usize) -> Vec<u32> {
+    if !n.neg {
+        let mut mag = n.mag.clone();
+        mag.resize(len, 0);
+        return mag;
+    }
+    // -n = !(n - 1)
+    let mut mag = (&n.abs() - &BigInt::one()).mag;
+    mag.resize(len, 0);
+    mag.iter().map(|d| !d).collect()
+}
+
+/// The number with the given limbs in two's complement.
+fn from_twos(limbs: Vec<u32>) -> BigInt {
+    match limbs.last() {
+        Some(top) if top >> 31 == 1 => {
+            let mag = limbs.iter().map(|d| !d).collect();
+            &-&BigInt::from_parts(false, mag) - &BigInt::one()
+        }
+        _ => BigInt::from_parts(false, limbs),
+    }
+}
+
+/// Apply a bitwise operation to the two's complement forms of `a` and
+/// `b`, as if both were sign extended forever.
+fn bitwise<F: Fn(u32, u32) -> u32>(a: &BigInt, b: &BigInt, f: F) -> BigInt {
+    let len = a.mag.len().max(b.mag.len()) + 1;
+    let limbs = twos(a, len)
+        .into_iter()
+        .zip(twos(b, len))
+        .map(|(x, y)| f(x, y))
+        .collect();
+    from_twos(limbs)
+}
+
+fn trim(mag: &mut Vec<u32>) {
+    while mag.last() == Some(&0) {
+        mag.pop();
+    }
+}
+
+fn cmp_mag(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (a, b) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    let mut out = Vec::with_capacity(a.len() + 1);
+    let mut carry = 0_u64;
+    for (i, &x) in a.iter().enumerate() {
+        let sum = x as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+        out.push(sum as u32);
+        carry = sum >> 32;
+    }
+    if carry > 0 {
+        out.push(carry as u32);
+    }
+    out
+}
+
+/// Subtract `b` from `a`, where `a` is at least as large as `b`.
+fn sub_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut out = Vec::with_capacity(a.len());
+    let mut borrow = 0_i64;
+    for (i, &x) in a.iter().enumerate() {
+        let diff = x as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        out.push(diff as u32);
+        borrow = (diff < 0) as i64;
+    }
+    trim(&mut out);
+    out
+}
+
+fn mul_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    if a.is_empty() || b.is_empty() {
+        return Vec::new();
+    }
+    let mut out = vec![0_u32; a.len() + b.len()];
+    for (i, &x) in a.iter().enumerate() {
+        let mut carry = 0_u64;
+        for (j, &y) in b.iter().enumerate() {
+            let t = x as u64 * y as u64 + out[i + j] as u64 + carry;
+            out[i + j] = t as u32;
+            carry = t >> 32;
+        }
+        out[i + b.len()] = carry as u32;
+    }
+    trim(&mut out);
+    out
+}
+
+/// `mag = mag * m + a`
+fn mul_small_add(mag: &mut Vec<u32>, m: u32, a: u32) {
+    let mut carry = a as u64;
+    for d in mag.iter_mut() {
+        let t = *d as u64 * m as u64 + carry;
+        *d = t as u32;
+        carry = t >> 32;
+    }
+    if carry > 0 {
+        mag.push(carry as u32);
+    }
+}
+
+fn div_small_mag(mag: &mut [u32], d: u32) -> u32 {
+    let mut rem = 0_u64;
+    for digit in mag.iter_mut().rev() {
+        let cur = rem << 32 | *digit as u64;
+        *digit = (cur / d as u64) as u32;
+        rem = cur % d as u64;
+    }
+    rem as u32
+}
+
+/// Shift left by fewer than 32 bits. The top digit has
+/// to have room for the bits shifted out of it.
+fn shl_bits(mag: &mut [u32], s: u32) {
+    if s == 0 {
+        return;
+    }
+    for i in (0..mag.len()).rev() {
+        let lower = if i > 0 { mag[i - 1] >> (32 - s) } else { 0 };
+        mag[i] = mag[i] << s | lower;
+    }
+}
+
+fn shr_bits(mag: &mut Vec<u32>, s: u32) {
+    if s != 0 {
+        for i in 0..mag.len() {
+            let upper = mag.get(i + 1).map_or(0, |d| d << (32 - s));
+            mag[i] = mag[i] >> s | upper;
+        }
+    }
+    trim(mag);
+}
+
+/// Long division of magnitudes, Knuth's algorithm D.
+fn div_rem_mag(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if cmp_mag(a, b) == Ordering::Less {
+        return (Vec::new(), a.to_vec());
+    }
+    if b.len() == 1 {
+        let mut q = a.to_vec();
+        let r = div_small_mag(&mut q, b[0]);
+        trim(&mut q);
+        let mut r = vec![r];
+        trim(&mut r);
+        return (q, r);
+    }
+    // normalize so the top digit of the divisor has its high bit set
+    let s = b[b.len() - 1].leading_zeros();
+    let mut v = b.to_vec();
+    shl_bits(&mut v, s);
+    let mut u = a.to_vec();
+    u.push(0);
+    shl_bits(&mut u, s);
+
+    let n = v.len();
+    let m = a.len() - n;
+    let base = 1_u64 << 32;
+    let mut q = vec![0_u32; m + 1];
+    for j in (0..=m).rev() {
+        let num = (u[j + n] as u64) << 32 | u[j + n - 1] as u64;
+        let mut qhat = num / v[n - 1] as u64;
+        let mut rhat = num % v[n - 1] as u64;
+        while qhat >= base || qhat * v[n - 2] as u64 > (rhat << 32 | u[j + n - 2] as u64) {
+            qhat -= 1;
+            rhat += v[n - 1] as u64;
+            if rhat >= base {
+                break;
+            }
+        }
+        // multiply and subtract
+        let mut borrow = 0_i64;
+        for i in 0..n {
+            let p = qhat * v[i] as u64;
+            let t = u[i + j] as i64 - borrow - (p & 0xffff_ffff) as i64;
+            u[i + j] = t as u32;
+            borrow = (p >> 32) as i64 - (t >> 32);
+        }
+        let t = u[j + n] as i64 - borrow;
+        u[j + n] = t as u32;
+        if t < 0 {
+            // qhat was one too big, add the divisor back
+            qhat -= 1;
+            let mut carry = 0_u64;
+            for i in 0..n {
+                let sum = u[i + j] as u64 + v[i] as u64 + carry;
+                u[i + j] = sum as u32;
+                carry = sum >> 32;
+            }
+            u[j + n] = u[j + n].wrapping_add(carry as u32);
+        }
+        q[j] = qhat as u32;
+    }
+    trim(&mut q);
+    u.truncate(n);
+    shr_bits(&mut u, s);
+    (q, u)
+}
+
+macro_rules! from_primitive {
+    ($($t:ty),*) => {$(
+        impl From<$t> for BigInt {
+            fn from(n: $t) -> Self {
+                let neg = n < 0;
+                let mut mag = n.unsigned_abs() as u128;
+                let mut digits = Vec::new();
+                while mag > 0 {
+                    digits.push(mag as u32);
+                    mag >>= 32;
+                }
+                Self::from_parts(neg, digits)
+            }
+        }
+    )*};
+}
+
+from_primitive!(i32, i64, i128);
+
+impl From<u64> for BigInt {
+    fn from(n: u64) -> Self {
+        Self::from_parts(false, vec![n as u32, (n >> 32) as u32])
+    }
+}
+
+impl Add for &BigInt {
+    type Output = BigInt;
+
+    fn add(self, other: &BigInt) -> BigInt {
+        if self.neg == other.neg {
+            return BigInt::from_parts(self.neg, add_mag(&self.mag, &other.mag));
+        }
+        match cmp_mag(&self.mag, &other.mag) {
+            Ordering::Less => BigInt::from_parts(other.neg, sub_mag(&other.mag, &self.mag)),
+            _ => BigInt::from_parts(self.neg, sub_mag(&self.mag, &other.mag)),
+        }
+    }
+}
+
+impl Sub for &BigInt {
+    type Output = BigInt;
+
+    fn sub(self, other: &BigInt) -> BigInt {
+        self + &-other
+    }
+}
+
+impl Mul for &BigInt {
+    type Output = BigInt;
+
+    fn mul(self, other: &BigInt) -> BigInt {
+        BigInt::from_parts(self.neg != other.neg, mul_mag(&self.mag, &other.mag))
+    }
+}
+
+impl Neg for &BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        BigInt::from_parts(!self.neg, self.mag.clone())
+    }
+}
+
+impl BitAnd for &BigInt {
+    type Output = BigInt;
+
+    fn bitand(self, other: &BigInt) -> BigInt {
+        bitwise(self, other, |a, b| a & b)
+    }
+}
+
+impl BitOr for &BigInt {
+    type Output = BigInt;
+
+    fn bitor(self, other: &BigInt) -> BigInt {
+        bitwise(self, other, |a, b| a | b)
+    }
+}
+
+impl BitXor for &BigInt {
+    type Output = BigInt;
+
+    fn bitxor(self, other: &BigInt) -> BigInt {
+        bitwise(self, other, |a, b| a ^ b)
+    }
+}
+
+impl Not for &BigInt {
+    type Output = BigInt;
+
+    /// The bitwise complement, which is `-n - 1` in two's complement.
+    fn not(self) -> BigInt {
+        &-self - &BigInt::one()
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.neg, other.neg) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_mag(&self.mag, &other.mag),
+            (true, true) => cmp_mag(&other.mag, &self.mag),
+        }
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+        let mut n = self.abs();
+        let mut chunks = Vec::new();
+        while !n.is_zero() {
+            chunks.push(n.div_small(DECIMAL_BASE));
+        }
+        if self.neg {
+            write!(f, "-")?;
+        }
+        let mut chunks = chunks.iter().rev();
+        write!(f, "{}", chunks.next().unwrap())?;
+        for c in chunks {
+            write!(f, "{:09}", c)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::BigInt;
+
+    fn big(s: &str) -> BigInt {
+        BigInt::from_decimal(s).unwrap()
+    }
+
+    #[test]
+    fn test_decimal() {
+        for s in [
+            "0",
+            "1",
+            "-1",
+            "4294967296",
+            "1000000000",
+            "123456789012345678901234567890",
+            "-98765432109876543210987654321098765432109876543210",
+        ] {
+            assert_eq!(big(s).to_string(), s);
+        }
+        assert_eq!(big("-0"), BigInt::zero());
+        assert_eq!(big("007").to_string(), "7");
+        assert!(BigInt::from_decimal("").is_none());
+        assert!(BigInt::from_decimal("12a").is_none());
+        assert_eq!(BigInt::from(i128::MIN).to_string(), i128::MIN.to_string());
+        assert_eq!(BigInt::from(u64::MAX).to_string(), u64::MAX.to_string());
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let a = big("123456789012345678901234567890");
+        let b = big("-987654321098765432109876543210");
+        assert_eq!((&a + &b).to_string(), "-864197532086419753208641975320");
+        assert_eq!((&a - &b).to_string(), "1111111110111111111011111111100");
+        assert_eq!(
+            (&a * &b).to_string(),
+            "-121932631137021795226185032733622923332237463801111263526900"
+        );
+        assert_eq!(&a - &a, BigInt::zero());
+        assert_eq!(
+            BigInt::from(2).pow(100).to_string(),
+            "1267650600228229401496703205376"
+        );
+        assert!(a > b && -&a > b && BigInt::zero() > b);
+    }
+
+    #[test]
+    fn test_bitwise() {
+        let n = |x: i64| BigInt::from(x);
+        for (a, b) in [(12, 10), (-12, 10), (12, -10), (-12, -10), (0, -1)] {
+            assert_eq!(&n(a) & &n(b), n(a & b), "{} & {}", a, b);
+            assert_eq!(&n(a) | &n(b), n(a | b), "{} | {}", a, b);
+            assert_eq!(&n(a) ^ &n(b), n(a ^ b), "{} ^ {}", a, b);
+        }
+        assert_eq!(!&n(0), n(-1));
+        assert_eq!(!&n(-256), n(255));
+        let big = BigInt::from(1).shl(100);
+        assert_eq!(&(&big - &n(1)) & &n(-1), &big - &n(1));
+        assert_eq!(&-&big & &n(0xFF), n(0));
+        assert_eq!(&-&big | &n(1), &n(1) - &big);
+    }
+
+    #[test]
+    fn test_div_rem() {
+        let a = big("1267650600228229401496703205376");
+        let b = big("-4294967311");
+        let (q, r) = a.div_rem(&b).unwrap();
+        assert_eq!(&(&q * &b) + &r, a);
+        assert!(r.abs() < b.abs() && !r.is_negative());
+        let c = big("18446744073709551629");
+        let (q, r) = (-&a).div_rem(&c).unwrap();
+        assert_eq!(&(&q * &c) + &r, -&a);
+        assert!(r.is_negative() && r.abs() < c);
+        assert!(a.div_rem(&BigInt::zero()).is_none());
+        assert_eq!(
+            BigInt::from(-7).div_floor(&BigInt::from(2)),
+            Some(BigInt::from(-4))
+        );
+        assert_eq!(BigInt::from(12).gcd(&BigInt::from(-18)), BigInt::from(6));
+        // a quotient digit that needs the add back step
+        let u = big("340282366920938463463374607431768211455");
+        let v = big("18446744073709551617");
+        let (q, r) = u.div_rem(&v).unwrap();
+        assert_eq!(&(&q * &v) + &r, u);
+        assert!(r < v);
+    }
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!(BigInt::from(1).shl(100), BigInt::from(2).pow(100));
+        assert_eq!(BigInt::from(2).pow(100).shr(98), BigInt::from(4));
+        assert_eq!(BigInt::from(2).pow(100).to_f64(), 2_f64.powi(100));
+        assert_eq!(BigInt::from(-12345).to_f64(), -12345.0);
+        assert!(BigInt::from(10).pow(400).to_f64().is_infinite());
+        assert_eq!(BigInt::from(i64::MIN).to_i64(), Some(i64::MIN));
+        assert_eq!(BigInt::from(2).pow(64).to_i64(), None);
+        assert_eq!(BigInt::from(144).sqrt(), Some(BigInt::from(12)));
+        assert_eq!(
+            BigInt::from(2).pow(101).sqrt(),
+            Some(big("1592262918131443"))
+        );
+        assert_eq!(BigInt::from(40).remove_factor(2), (3, BigInt::from(5)));
+        assert_eq!(BigInt::from_radix("ff", 16), Some(BigInt::from(255)));
+        assert_eq!(BigInt::from(-12).shift(-2), BigInt::from(-3));
+        assert_eq!(BigInt::from(-13).shift(-2), BigInt::from(-4));
+        assert_eq!(BigInt::from(-1).shift(-70), BigInt::from(-1));
+        assert_eq!(BigInt::from(3).shift(65), BigInt::from(3).shl(65));
+        assert_eq!(BigInt::from_radix("102", 2), None);
+        assert_eq!(BigInt::from(-493).to_radix(8), "755");
+        assert_eq!(BigInt::from(2).pow(70).to_radix(16), "400000000000000000");
+    }
+}
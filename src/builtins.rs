@@ -0,0 +1,535 @@
+use std::f64::consts;
+use std::fmt;
+
+use crate::bigint::BigInt;
+use crate::complex::Complex;
+use crate::decimal::{Context, Decimal};
+use crate::env::{Env, Mode};
+use crate::error::EvalErrorKind;
+use crate::rational::{Rational, MAX_BITS};
+use crate::units;
+use crate::value::Value;
+
+/// The number of arguments a builtin function accepts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Arity {
+    Exact(usize),
+    Range(usize, usize),
+    AtLeast(usize),
+}
+
+/// The exact version of a builtin, see [`Builtin::call`].
+type Exact = fn(&[Rational]) -> Option<Rational>;
+
+/// The decimal version of a builtin, worked out to the precision of
+/// the context, giving back `None` if it can't be.
+type DecimalFn = fn(&[Decimal], &Context) -> Option<Decimal>;
+
+/// The complex version of a builtin.
+type ComplexFn = fn(&[Complex]) -> Complex;
+
+/// A function that is built into the calculator.
+pub struct Builtin {
+    pub name: &'static str,
+    pub arity: Arity,
+    func: fn(&[f64]) -> f64,
+    /// An exact version of the function for when every argument
+    /// is rational, giving back `None` if the result isn't.
+    exact: Option<Exact>,
+    /// A version of the function for decimals, used when an argument
+    /// is one. Otherwise the float result is rounded to a decimal.
+    decimal: Option<DecimalFn>,
+    /// A version of the function over the complex numbers, used for
+    /// complex arguments and for real ones with no real result.
+    complex: Option<ComplexFn>,
+}
+
+impl Builtin {
+    /// Call the function after checking the number of arguments. The
+    /// result stays exact when every argument is a fraction or decimal
+    /// and the function has an exact version that can represent it,
+    /// and is a decimal when an argument is one.
+    pub fn call(&self, args: &[Value], ctx: &Context) -> Result<Value, EvalErrorKind> {
+        if !self.arity.accepts(args.len()) {
+            return Err(EvalErrorKind::Arity(format!(
+                "{} takes {} but {} {} given",
+                self.name,
+                self.arity,
+                args.len(),
+                if args.len() == 1 { "was" } else { "were" },
+            )));
+        }
+        if args.iter().any(|a| matches!(a, Value::Quantity(_))) {
+            return units::call(self, args, ctx);
+        }
+        if args.iter().any(|a| matches!(a, Value::Complex(_))) {
+            return match self.complex {
+                Some(complex) => Ok(self.call_complex(complex, args)),
+                None => Err(EvalErrorKind::Domain(format!(
+                    "{}({})",
+                    self.name,
+                    args.iter()
+                        .map(|a| a.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ))),
+            };
+        }
+        if let Some(exact) = self.exact {
+            let fractions: Option<Vec<Rational>> = args.iter().map(Value::to_rational).collect();
+            if let Some(res) = fractions.as_deref().and_then(exact) {
+                let decimal = args.iter().find(|a| matches!(a, Value::Decimal(_)));
+                return Ok(match decimal {
+                    Some(d) => d.exact_result(res, ctx),
+                    None => Value::Rational(res),
+                });
+            }
+        }
+        let decimals: Option<Vec<Decimal>> = args
+            .iter()
+            .map(|a| match a {
+                Value::Decimal(d) => Some(d.clone()),
+                Value::Rational(r) => Some(Decimal::from_rational(r, ctx)),
+                _ => None,
+            })
+            .collect();
+        let decimal = args.iter().any(|a| matches!(a, Value::Decimal(_)));
+        let decimals = decimals.filter(|_| decimal);
+        if let (Some(f), Some(decimals)) = (self.decimal, &decimals) {
+            if let Some(res) = f(decimals, ctx) {
+                return Ok(Value::Decimal(res));
+            }
+        }
+        let floats: Vec<f64> = args.iter().filter_map(Value::as_f64).collect();
+        let res = (self.func)(&floats);
+        match self.complex {
+            // no real result, like sqrt(-1)
+            Some(complex) if res.is_nan() && !floats.iter().any(|x| x.is_nan()) => {
+                Ok(self.call_complex(complex, args))
+            }
+            _ => Ok(match Decimal::from_f64(res) {
+                Some(res) if decimals.is_some() => Value::Decimal(res.rounded(ctx)),
+                _ => Value::Float(res),
+            }),
+        }
+    }
+
+    fn call_complex(&self, complex: ComplexFn, args: &[Value]) -> Value {
+        let args: Vec<Complex> = args.iter().filter_map(Value::as_complex).collect();
+        Value::complex(complex(&args))
+    }
+}
+
+impl Arity {
+    fn accepts(&self, n: usize) -> bool {
+        match *self {
+            Arity::Exact(a) => n == a,
+            Arity::Range(lo, hi) => lo <= n && n <= hi,
+            Arity::AtLeast(lo) => lo <= n,
+        }
+    }
+}
+
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let plural = |n: usize| if n == 1 { "argument" } else { "arguments" };
+        match *self {
+            Arity::Exact(n) => write!(f, "{} {}", n, plural(n)),
+            Arity::Range(lo, hi) => write!(f, "{} to {} arguments", lo, hi),
+            Arity::AtLeast(n) => write!(f, "at least {} {}", n, plural(n)),
+        }
+    }
+}
+
+macro_rules! unary {
+    (@exact) => {
+        None
+    };
+    (@exact $exact:expr) => {
+        Some(|args| $exact(&args[0]))
+    };
+    (@decimal) => {
+        None
+    };
+    (@decimal $decimal:expr) => {
+        Some(|args, ctx| $decimal(&args[0], ctx))
+    };
+    (@complex) => {
+        None
+    };
+    (@complex $complex:expr) => {
+        Some(|args| $complex(&args[0]))
+    };
+    (
+        $name:expr,
+        $f:expr
+        $(, exact: $exact:expr)?
+        $(, decimal: $decimal:expr)?
+        $(, complex: $complex:expr)?
+    ) => {
+        Builtin {
+            name: $name,
+            arity: Arity::Exact(1),
+            func: |args| $f(args[0]),
+            exact: unary!(@exact $($exact)?),
+            decimal: unary!(@decimal $($decimal)?),
+            complex: unary!(@complex $($complex)?),
+        }
+    };
+}
+
+const BUILTINS: &[Builtin] = &[
+    // trigonometry
+    unary!("sin", f64::sin, decimal: Decimal::sin),
+    unary!("cos", f64::cos, decimal: Decimal::cos),
+    unary!("tan", f64::tan, decimal: Decimal::tan),
+    unary!("asin", f64::asin),
+    unary!("acos", f64::acos),
+    unary!("atan", f64::atan),
+    Builtin {
+        name: "atan2",
+        arity: Arity::Exact(2),
+        func: |args| args[0].atan2(args[1]),
+        exact: None,
+        decimal: None,
+        complex: None,
+    },
+    // hyperbolic
+    unary!("sinh", f64::sinh),
+    unary!("cosh", f64::cosh),
+    unary!("tanh", f64::tanh),
+    unary!("asinh", f64::asinh),
+    unary!("acosh", f64::acosh),
+    unary!("atanh", f64::atanh),
+    // logarithms and exponentials
+    Builtin {
+        name: "log",
+        arity: Arity::Range(1, 2),
+        func: |args| match args {
+            [x, base] => x.log(*base),
+            _ => args[0].log10(),
+        },
+        exact: None,
+        decimal: Some(|args, ctx| match args {
+            [x, base] => x.log(base, ctx),
+            _ => args[0].log(&Decimal::new(BigInt::from(10), 0), ctx),
+        }),
+        complex: None,
+    },
+    unary!("ln", f64::ln, decimal: Decimal::ln, complex: Complex::ln),
+    unary!(
+        "log2",
+        f64::log2,
+        decimal: |x: &Decimal, ctx| x.log(&Decimal::new(BigInt::from(2), 0), ctx)
+    ),
+    unary!(
+        "log10",
+        f64::log10,
+        decimal: |x: &Decimal, ctx| x.log(&Decimal::new(BigInt::from(10), 0), ctx)
+    ),
+    unary!("exp", f64::exp, decimal: Decimal::exp, complex: Complex::exp),
+    unary!(
+        "sqrt",
+        f64::sqrt,
+        exact: Rational::sqrt,
+        decimal: Decimal::sqrt,
+        complex: Complex::sqrt
+    ),
+    unary!("cbrt", f64::cbrt),
+    unary!("gamma", gamma),
+    unary!("fact", factorial, exact: exact_factorial),
+    // rounding
+    unary!(
+        "abs",
+        f64::abs,
+        exact: |x: &Rational| Some(x.abs()),
+        complex: |z: &Complex| Complex::from(z.abs())
+    ),
+    unary!("floor", f64::floor, exact: |x: &Rational| Some(x.floor())),
+    unary!("ceil", f64::ceil, exact: |x: &Rational| Some(x.ceil())),
+    unary!("trunc", f64::trunc, exact: |x: &Rational| Some(x.trunc())),
+    Builtin {
+        name: "round",
+        arity: Arity::Range(1, 2),
+        func: |args| match args {
+            [x, digits] => {
+                let scale = 10_f64.powf(digits.trunc());
+                (x * scale).round() / scale
+            }
+            _ => args[0].round(),
+        },
+        exact: Some(|args| match args {
+            [x, digits] => {
+                let scale = Rational::from_int(10).checked_pow(digits.trunc().numer().to_i64()?)?;
+                (x * &scale).round().checked_div(&scale)
+            }
+            _ => Some(args[0].round()),
+        }),
+        decimal: None,
+        complex: None,
+    },
+    // complex numbers
+    unary!(
+        "arg",
+        |x: f64| if x < 0.0 { consts::PI } else { 0.0 },
+        complex: |z: &Complex| Complex::from(z.arg())
+    ),
+    unary!(
+        "conj",
+        |x| x,
+        exact: |x: &Rational| Some(x.clone()),
+        complex: Complex::conj
+    ),
+    unary!(
+        "re",
+        |x| x,
+        exact: |x: &Rational| Some(x.clone()),
+        complex: |z: &Complex| Complex::from(z.re)
+    ),
+    unary!(
+        "im",
+        |_| 0.0,
+        exact: |_| Some(Rational::from_int(0)),
+        complex: |z: &Complex| Complex::from(z.im)
+    ),
+    // comparison
+    Builtin {
+        name: "min",
+        arity: Arity::AtLeast(1),
+        func: |args| args.iter().copied().fold(f64::INFINITY, f64::min),
+        exact: Some(|args| args.iter().min().cloned()),
+        decimal: None,
+        complex: None,
+    },
+    Builtin {
+        name: "max",
+        arity: Arity::AtLeast(1),
+        func: |args| args.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        exact: Some(|args| args.iter().max().cloned()),
+        decimal: None,
+        complex: None,
+    },
+];
+
+/// The factorial of `x`. Integers are multiplied out exactly for as
+/// long as the result fits in a `u128`, anything else goes through the
+/// gamma function.
+pub fn factorial(x: f64) -> f64 {
+    if x.fract() != 0.0 || !(0.0..=170.0).contains(&x) {
+        return gamma(x + 1.0);
+    }
+    let n = x as u32;
+    let mut acc: u128 = 1;
+    for i in 2..=n {
+        match acc.checked_mul(i as u128) {
+            Some(next) => acc = next,
+            None => return (i..=n).fold(acc as f64, |acc, i| acc * i as f64),
+        }
+    }
+    acc as f64
+}
+
+/// The factorial of a non-negative integer, or `None` if `x` isn't
+/// one or the result would have more than [`MAX_BITS`] bits.
+pub fn exact_factorial(x: &Rational) -> Option<Rational> {
+    if !x.is_integer() || x.is_negative() {
+        return None;
+    }
+    let n = x.numer().to_i64()?;
+    // Stirling's approximation of log2(n!), so that we
+    // don't multiply for ages before finding out
+    let f = n as f64;
+    if f > 1.0 && (f * f.ln() - f) / consts::LN_2 > MAX_BITS as f64 {
+        return None;
+    }
+    let mut acc = BigInt::one();
+    for i in 2..=n {
+        acc = &acc * &BigInt::from(i);
+        if acc.bits() > MAX_BITS {
+            return None;
+        }
+    }
+    Some(Rational::from_int(acc))
+}
+
+/// Lanczos approximation coefficients for g = 7, n = 9.
+const LANCZOS_G: f64 = 7.0;
+const LANCZOS: [f64; 9] = [
+    0.999_999_999_999_809_9,
+    676.520_368_121_885_1,
+    -1_259.139_216_722_402_8,
+    771.323_428_777_653_1,
+    -176.615_029_162_140_6,
+    12.507_343_278_686_905,
+    -0.138_571_095_265_720_12,
+    9.984_369_578_019_572e-6,
+    1.505_632_735_149_311_6e-7,
+];
+
+/// The gamma function, using the reflection formula
+/// for everything below one half.
+pub fn gamma(x: f64) -> f64 {
+    if x < 0.5 {
+        if x.fract() == 0.0 {
+            // poles at zero and the negative integers
+            return f64::NAN;
+        }
+        return consts::PI / ((consts::PI * x).sin() * gamma(1.0 - x));
+    }
+    if x > 172.0 {
+        // the approximation would give inf * 0
+        return f64::INFINITY;
+    }
+    let x = x - 1.0;
+    let t = x + LANCZOS_G + 0.5;
+    let mut a = LANCZOS[0];
+    for (i, c) in LANCZOS.iter().enumerate().skip(1) {
+        a += c / (x + i as f64);
+    }
+    (2.0 * consts::PI).sqrt() * t.powf(x + 0.5) * (-t).exp() * a
+}
+
+/// Find a builtin function by name.
+pub fn lookup(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|b| b.name == name)
+}
+
+/// Find a builtin constant by name. In decimal mode `pi`, `e`
+/// and `tau` are worked out to the precision of `env`.
+pub fn constant(name: &str, env: &Env) -> Option<Value> {
+    let ctx = env.context();
+    let decimal = env.mode() == Mode::Decimal;
+    match name {
+        "pi" if decimal => Some(Value::Decimal(Decimal::pi(&ctx))),
+        "e" if decimal => Decimal::new(BigInt::from(1), 0)
+            .exp(&ctx)
+            .map(Value::Decimal),
+        "tau" if decimal => Some(Value::Decimal(Decimal::tau(&ctx))),
+        "pi" => Some(Value::Float(consts::PI)),
+        "e" => Some(Value::Float(consts::E)),
+        "tau" => Some(Value::Float(consts::TAU)),
+        "i" => Some(Value::Complex(Complex::I)),
+        "true" => Some(Value::Bool(true)),
+        "false" => Some(Value::Bool(false)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{factorial, gamma, lookup, Arity};
+    use crate::decimal::{Context, Decimal};
+    use crate::error::EvalErrorKind;
+    use crate::rational::Rational;
+    use crate::value::Value;
+    use std::f64::consts;
+
+    #[test]
+    fn test_arity() {
+        assert!(Arity::Exact(1).accepts(1));
+        assert!(!Arity::Exact(1).accepts(2));
+        assert!(Arity::Range(1, 2).accepts(2));
+        assert!(!Arity::Range(1, 2).accepts(0));
+        assert!(Arity::AtLeast(1).accepts(10));
+        assert!(!Arity::AtLeast(1).accepts(0));
+    }
+
+    #[test]
+    fn test_factorial() {
+        assert_eq!(factorial(0.0), 1.0);
+        assert_eq!(factorial(5.0), 120.0);
+        assert_eq!(factorial(20.0), 2432902008176640000.0);
+        assert_eq!(factorial(25.0), 15511210043330985984000000.0);
+        assert!(factorial(170.0).is_finite());
+        assert!(factorial(171.0).is_infinite());
+        assert!(factorial(-1.0).is_nan());
+        assert!((factorial(2.5) - 3.323350970447843).abs() < 1e-12);
+        assert!((factorial(-0.5) - consts::PI.sqrt()).abs() < 1e-12);
+        assert!((gamma(0.5) - consts::PI.sqrt()).abs() < 1e-12);
+    }
+
+    fn floats(args: &[f64]) -> Vec<Value> {
+        args.iter().copied().map(Value::from).collect()
+    }
+
+    fn exact(num: i128, den: i128) -> Value {
+        Value::Rational(Rational::new(num, den).unwrap())
+    }
+
+    #[test]
+    fn test_call() {
+        let ctx = Context::default();
+        let sqrt = lookup("sqrt").unwrap();
+        assert_eq!(sqrt.call(&floats(&[4.0]), &ctx), Ok(Value::from(2.0)));
+        assert_eq!(
+            sqrt.call(&floats(&[4.0, 2.0]), &ctx),
+            Err(EvalErrorKind::Arity(String::from(
+                "sqrt takes 1 argument but 2 were given"
+            )))
+        );
+        let max = lookup("max").unwrap();
+        assert_eq!(
+            max.call(&floats(&[1.0, 3.0, 2.0]), &ctx),
+            Ok(Value::from(3.0))
+        );
+        assert_eq!(
+            max.call(&[], &ctx),
+            Err(EvalErrorKind::Arity(String::from(
+                "max takes at least 1 argument but 0 were given"
+            )))
+        );
+        assert_eq!(
+            lookup("log").unwrap().call(&floats(&[100.0, 10.0]), &ctx),
+            Ok(Value::from(2.0))
+        );
+        assert_eq!(
+            lookup("round")
+                .unwrap()
+                .call(&floats(&[1.23456, 2.0]), &ctx),
+            Ok(Value::from(1.23))
+        );
+        assert!(lookup("nope").is_none());
+    }
+
+    #[test]
+    fn test_call_exact() {
+        let ctx = Context::default();
+        let sqrt = lookup("sqrt").unwrap();
+        assert_eq!(sqrt.call(&[exact(9, 4)], &ctx), Ok(exact(3, 2)));
+        assert_eq!(
+            sqrt.call(&[exact(2, 1)], &ctx),
+            Ok(Value::from(2_f64.sqrt()))
+        );
+        assert_eq!(
+            lookup("round")
+                .unwrap()
+                .call(&[exact(123456, 100000), exact(2, 1)], &ctx),
+            Ok(exact(123, 100))
+        );
+        assert_eq!(
+            lookup("min")
+                .unwrap()
+                .call(&[exact(1, 2), exact(1, 3)], &ctx),
+            Ok(exact(1, 3))
+        );
+        assert_eq!(
+            lookup("fact").unwrap().call(&[exact(30, 1)], &ctx),
+            Ok(exact(265252859812191058636308480000000, 1))
+        );
+        // mixing in a float gives a float
+        assert_eq!(
+            lookup("max")
+                .unwrap()
+                .call(&[exact(1, 2), Value::from(0.25)], &ctx),
+            Ok(Value::from(0.5))
+        );
+        // and a decimal gives a decimal
+        let half = Value::Decimal(Decimal::from_decimal("0.5").unwrap());
+        assert_eq!(
+            lookup("max")
+                .unwrap()
+                .call(&[exact(1, 3), half.clone()], &ctx),
+            Ok(half)
+        );
+    }
+}
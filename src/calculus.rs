@@ -0,0 +1,330 @@
+use std::sync::Arc;
+
+use crate::ast::{self, Ast};
+use crate::env::Env;
+use crate::error::EvalErrorKind;
+use crate::function::{arity, Function};
+use crate::lex::Token;
+use crate::series;
+use crate::simplify;
+use crate::solve;
+use crate::value::Value;
+
+/// A builtin whose arguments are expressions rather than
+/// values, like the `x^2` in `diff(x^2, x)`.
+pub(crate) type Form = fn(&[Ast], &mut Env) -> Result<Value, EvalErrorKind>;
+
+/// Find a builtin that takes expressions as arguments, when it's
+/// called with `args` arguments. `sum` of a single list is the one
+/// that adds up the items instead.
+pub(crate) fn lookup(name: &str, args: usize) -> Option<Form> {
+    match name {
+        "diff" => Some(diff),
+        "solve" => Some(solve::solve),
+        "sum" if args != 1 => Some(series::sum),
+        "prod" => Some(series::prod),
+        _ => None,
+    }
+}
+
+/// The name of the variable `ast` is, like the `x` in `diff(x^2, x)`.
+pub(crate) fn variable(ast: &Ast) -> Result<String, EvalErrorKind> {
+    match &ast.tok {
+        Token::Ident(name) if ast.children.is_empty() => Ok(name.clone()),
+        _ => Err(EvalErrorKind::Type(format!(
+            "expected a variable but got {}",
+            ast
+        ))),
+    }
+}
+
+/// `diff(f, x)`, the derivative of `f` with respect to `x` as a lambda
+/// of `x`, or `diff(f, x, a)`, the derivative at `x = a`.
+fn diff(args: &[Ast], env: &mut Env) -> Result<Value, EvalErrorKind> {
+    let (expr, var, at) = match args {
+        [expr, var] => (expr, var, None),
+        [expr, var, at] => (expr, var, Some(at)),
+        _ => {
+            return Err(EvalErrorKind::Arity(format!(
+                "diff takes 2 to 3 arguments but {} {} given",
+                args.len(),
+                if args.len() == 1 { "was" } else { "were" },
+            )))
+        }
+    };
+    let var = variable(var)?;
+    let body = derivative(expr, &var, env)?;
+    let func = Function::lambda(vec![var], body, env);
+    match at {
+        None => Ok(Value::Function(Arc::new(func))),
+        Some(at) => {
+            let at = ast::eval(at, env).map_err(|err| err.kind)?;
+            func.apply(vec![at], env)
+        }
+    }
+}
+
+/// The simplified derivative of `ast` with respect to `var`.
+/// Calls to functions defined in `env` are differentiated through their
+/// bodies, and every other name is taken to be a constant.
+pub(crate) fn derivative(ast: &Ast, var: &str, env: &Env) -> Result<Ast, EvalErrorKind> {
+    let mut diff = Differentiate {
+        var,
+        env,
+        calls: vec![],
+    };
+    diff.derivative(ast).map(|d| simplify::simplify(&d))
+}
+
+struct Differentiate<'a> {
+    var: &'a str,
+    env: &'a Env,
+    /// The user defined functions being differentiated through, to
+    /// stop at recursive ones.
+    calls: Vec<String>,
+}
+
+impl Differentiate<'_> {
+    fn derivative(&mut self, ast: &Ast) -> Result<Ast, EvalErrorKind> {
+        let args = &ast.children;
+        Ok(match (&ast.tok, args.len()) {
+            (Token::Ident(name), 0) => match name == self.var {
+                true => int(1),
+                false => int(0),
+            },
+            (Token::Int(_) | Token::Float(_) | Token::Imaginary(_), 0) => int(0),
+            (Token::Op('+'), 2) => add(self.derivative(&args[0])?, self.derivative(&args[1])?),
+            (Token::Op('-'), 2) => sub(self.derivative(&args[0])?, self.derivative(&args[1])?),
+            (Token::Op('-'), 1) => neg(self.derivative(&args[0])?),
+            // (uv)' = u'v + uv'
+            (Token::Op('*'), 2) => {
+                let (u, v) = (&args[0], &args[1]);
+                let du = mul(self.derivative(u)?, v.clone());
+                add(du, mul(u.clone(), self.derivative(v)?))
+            }
+            (Token::Op('/'), 2) if !self.depends(&args[1]) => {
+                div(self.derivative(&args[0])?, args[1].clone())
+            }
+            // (u/v)' = (u'v - uv') / v^2
+            (Token::Op('/'), 2) => {
+                let (u, v) = (&args[0], &args[1]);
+                let du = mul(self.derivative(u)?, v.clone());
+                let top = sub(du, mul(u.clone(), self.derivative(v)?));
+                div(top, pow(v.clone(), int(2)))
+            }
+            (Token::Op('^'), 2) => self.power(ast)?,
+            (Token::Op('%'), 1) => div(self.derivative(&args[0])?, int(100)),
+            // "u% of v" is u / 100 * v
+            (Token::Op('%'), 2) => {
+                let percent = Ast::from(Token::Op('/'), vec![args[0].clone(), int(100)]);
+                self.derivative(&Ast::from(Token::Op('*'), vec![percent, args[1].clone()]))?
+            }
+            (Token::Op('°'), 1) => match simplify::simplify(&self.derivative(&args[0])?) {
+                du if is_int(&du, 0) => du,
+                du => Ast::from(Token::Op('°'), vec![du]),
+            },
+            // u mod v = u - v * floor(u / v)
+            (Token::Modulus, 2) => {
+                let (u, v) = (&args[0], &args[1]);
+                let quotient = Ast::from(Token::Op('/'), vec![u.clone(), v.clone()]);
+                let floor = call("floor", vec![quotient]);
+                sub(self.derivative(u)?, mul(self.derivative(v)?, floor))
+            }
+            (Token::If, 3) => Ast::from(
+                Token::If,
+                vec![
+                    args[0].clone(),
+                    self.derivative(&args[1])?,
+                    self.derivative(&args[2])?,
+                ],
+            ),
+            (Token::Func(name), _) => self.call(name, ast)?,
+            _ => return Err(cannot(ast)),
+        })
+    }
+
+    /// Whether `ast` could change with the variable. Calls to user
+    /// defined functions might, through the variables they use.
+    fn depends(&self, ast: &Ast) -> bool {
+        let uses = match &ast.tok {
+            Token::Ident(name) => name == self.var,
+            Token::Func(name) => self.env.get(name).is_some(),
+            _ => false,
+        };
+        uses || ast.children.iter().any(|child| self.depends(child))
+    }
+
+    fn power(&mut self, ast: &Ast) -> Result<Ast, EvalErrorKind> {
+        let (u, v) = (&ast.children[0], &ast.children[1]);
+        // (u^n)' = n * u^(n - 1) * u'
+        if !self.depends(v) {
+            let outer = mul(v.clone(), pow(u.clone(), sub(v.clone(), int(1))));
+            return Ok(mul(outer, self.derivative(u)?));
+        }
+        let ln = self.ln(u);
+        // (a^v)' = a^v * ln(a) * v'
+        if !self.depends(u) {
+            return Ok(mul(mul(ast.clone(), ln), self.derivative(v)?));
+        }
+        // (u^v)' = u^v * (v' * ln(u) + v * u' / u)
+        let dv = mul(self.derivative(v)?, ln);
+        let du = div(mul(v.clone(), self.derivative(u)?), u.clone());
+        Ok(mul(ast.clone(), add(dv, du)))
+    }
+
+    /// `ln(u)`, which is 1 for the constant `e`.
+    fn ln(&self, u: &Ast) -> Ast {
+        match &u.tok {
+            Token::Ident(name) if name == "e" && self.env.get(name).is_none() => int(1),
+            _ => call("ln", vec![u.clone()]),
+        }
+    }
+
+    /// The derivative of a call to a builtin or user defined function,
+    /// by the chain rule.
+    fn call(&mut self, name: &str, ast: &Ast) -> Result<Ast, EvalErrorKind> {
+        let args = &ast.children;
+        if let Some(Value::Function(func)) = self.env.get(name) {
+            return self.inline(name, &func.clone(), args);
+        }
+        let u = match (name, args.as_slice()) {
+            ("diff", [expr, var]) => {
+                let inner = derivative(expr, &variable(var)?, self.env)?;
+                return self.derivative(&inner);
+            }
+            // term by term, when the bounds don't change with the variable
+            ("sum", [k, a, b, term])
+                if variable(k).is_ok_and(|k| k != self.var)
+                    && !self.depends(a)
+                    && !self.depends(b) =>
+            {
+                let dterm = self.derivative(term)?;
+                return Ok(call("sum", vec![k.clone(), a.clone(), b.clone(), dterm]));
+            }
+            // log(u, b) = ln(u) / ln(b)
+            ("log", [u, b]) => {
+                let quotient = div(self.ln(u), self.ln(b));
+                return self.derivative(&quotient);
+            }
+            // atan2(y, x)' = (x * y' - y * x') / (x^2 + y^2)
+            ("atan2", [y, x]) => {
+                let dy = mul(x.clone(), self.derivative(y)?);
+                let top = sub(dy, mul(y.clone(), self.derivative(x)?));
+                let square = |a: &Ast| pow(a.clone(), int(2));
+                return Ok(div(top, add(square(x), square(y))));
+            }
+            (_, [u]) => u.clone(),
+            _ => return Err(cannot(ast)),
+        };
+        let square = || pow(u.clone(), int(2));
+        let f = |name: &str| call(name, vec![u.clone()]);
+        let outer = match name {
+            "sin" => f("cos"),
+            "cos" => neg(f("sin")),
+            "tan" => div(int(1), pow(f("cos"), int(2))),
+            "asin" => div(int(1), call("sqrt", vec![sub(int(1), square())])),
+            "acos" => neg(div(int(1), call("sqrt", vec![sub(int(1), square())]))),
+            "atan" => div(int(1), add(int(1), square())),
+            "sinh" => f("cosh"),
+            "cosh" => f("sinh"),
+            "tanh" => div(int(1), pow(f("cosh"), int(2))),
+            "asinh" => div(int(1), call("sqrt", vec![add(square(), int(1))])),
+            "acosh" => div(int(1), call("sqrt", vec![sub(square(), int(1))])),
+            "atanh" => div(int(1), sub(int(1), square())),
+            "exp" => f("exp"),
+            "ln" => div(int(1), u.clone()),
+            "log" | "log10" => div(int(1), mul(u.clone(), call("ln", vec![int(10)]))),
+            "log2" => div(int(1), mul(u.clone(), call("ln", vec![int(2)]))),
+            "sqrt" => div(int(1), mul(int(2), f("sqrt"))),
+            "cbrt" => div(int(1), mul(int(3), pow(f("cbrt"), int(2)))),
+            "abs" => div(u.clone(), f("abs")),
+            // flat everywhere except at the steps
+            "floor" | "ceil" | "trunc" | "round" => return Ok(int(0)),
+            _ => return Err(cannot(ast)),
+        };
+        Ok(mul(outer, self.derivative(&u)?))
+    }
+
+    /// Differentiate a call to a user defined function through its body
+    /// with the arguments put in place of the parameters.
+    fn inline(&mut self, name: &str, func: &Function, args: &[Ast]) -> Result<Ast, EvalErrorKind> {
+        if self.calls.iter().any(|call| call == name) {
+            return Err(EvalErrorKind::Symbolic(format!(
+                "cannot differentiate the recursive function {}",
+                name
+            )));
+        }
+        if func.is_closure() {
+            return Err(EvalErrorKind::Symbolic(format!(
+                "cannot differentiate the closure {}",
+                func
+            )));
+        }
+        if args.len() != func.params().len() {
+            return Err(arity(name, func.params().len(), args.len()));
+        }
+        let body = substitute(func.body(), func.params(), args);
+        self.calls.push(String::from(name));
+        let res = self.derivative(&body);
+        self.calls.pop();
+        res
+    }
+}
+
+fn cannot(ast: &Ast) -> EvalErrorKind {
+    EvalErrorKind::Symbolic(format!("cannot differentiate {}", ast))
+}
+
+/// `ast` with each of the variables `params` replaced by its `args`.
+fn substitute(ast: &Ast, params: &[String], args: &[Ast]) -> Ast {
+    if let (Token::Ident(name), true) = (&ast.tok, ast.children.is_empty()) {
+        if let Some(i) = params.iter().position(|p| p == name) {
+            return args[i].clone();
+        }
+    }
+    let mut res = Ast::new(ast.tok.clone()).with_span(ast.span);
+    for child in &ast.children {
+        res.push(substitute(child, params, args));
+    }
+    res
+}
+
+// Shorthands for writing the rules. They build the derivative as it
+// comes, like `3 * x^(3 - 1) * 1` for `x^3`, and it's simplified at
+// the end.
+
+fn int(n: i64) -> Ast {
+    Ast::new(Token::int(n))
+}
+
+fn is_int(ast: &Ast, n: i64) -> bool {
+    ast.tok == Token::int(n) && ast.children.is_empty()
+}
+
+fn call(name: &str, args: Vec<Ast>) -> Ast {
+    Ast::from(Token::Func(String::from(name)), args)
+}
+
+fn neg(a: Ast) -> Ast {
+    Ast::from(Token::Op('-'), vec![a])
+}
+
+fn add(a: Ast, b: Ast) -> Ast {
+    Ast::from(Token::Op('+'), vec![a, b])
+}
+
+fn sub(a: Ast, b: Ast) -> Ast {
+    Ast::from(Token::Op('-'), vec![a, b])
+}
+
+fn mul(a: Ast, b: Ast) -> Ast {
+    Ast::from(Token::Op('*'), vec![a, b])
+}
+
+fn div(a: Ast, b: Ast) -> Ast {
+    Ast::from(Token::Op('/'), vec![a, b])
+}
+
+fn pow(a: Ast, b: Ast) -> Ast {
+    Ast::from(Token::Op('^'), vec![a, b])
+}
@@ -0,0 +1,213 @@
+use std::f64::consts::PI;
+use std::fmt;
+
+/// A complex number with floating point parts.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Complex {
+    pub re: f64,
+    pub im: f64,
+}
+
+impl Complex {
+    pub const I: Complex = Complex { re: 0.0, im: 1.0 };
+
+    pub fn new(re: f64, im: f64) -> Self {
+        Self { re, im }
+    }
+
+    /// The complex number with magnitude `r` and angle `theta` in radians.
+    pub fn from_polar(r: f64, theta: f64) -> Self {
+        Self::new(r * theta.cos(), r * theta.sin())
+    }
+
+    pub fn is_real(&self) -> bool {
+        self.im == 0.0
+    }
+
+    /// The magnitude.
+    pub fn abs(&self) -> f64 {
+        self.re.hypot(self.im)
+    }
+
+    /// The angle from the positive real axis in radians.
+    pub fn arg(&self) -> f64 {
+        self.im.atan2(self.re)
+    }
+
+    pub fn conj(&self) -> Self {
+        Self::new(self.re, -self.im)
+    }
+
+    pub fn add(&self, other: &Self) -> Self {
+        Self::new(self.re + other.re, self.im + other.im)
+    }
+
+    pub fn sub(&self, other: &Self) -> Self {
+        Self::new(self.re - other.re, self.im - other.im)
+    }
+
+    pub fn mul(&self, other: &Self) -> Self {
+        Self::new(
+            self.re * other.re - self.im * other.im,
+            self.re * other.im + self.im * other.re,
+        )
+    }
+
+    pub fn div(&self, other: &Self) -> Self {
+        let den = other.re * other.re + other.im * other.im;
+        Self::new(
+            (self.re * other.re + self.im * other.im) / den,
+            (self.im * other.re - self.re * other.im) / den,
+        )
+    }
+
+    pub fn neg(&self) -> Self {
+        Self::new(-self.re, -self.im)
+    }
+
+    pub fn exp(&self) -> Self {
+        Self::from_polar(self.re.exp(), self.im)
+    }
+
+    /// The principal natural logarithm.
+    pub fn ln(&self) -> Self {
+        Self::new(self.abs().ln(), self.arg())
+    }
+
+    /// The principal square root.
+    pub fn sqrt(&self) -> Self {
+        if self.is_real() && self.re < 0.0 {
+            // exact for negative reals, where ln would leave a tiny real part
+            return Self::new(0.0, (-self.re).sqrt());
+        }
+        Self::from_polar(self.abs().sqrt(), self.arg() / 2.0)
+    }
+
+    /// The principal value of `self` raised to `exp`.
+    pub fn pow(&self, exp: &Self) -> Self {
+        if self.re == 0.0 && self.im == 0.0 {
+            return match exp.re == 0.0 && exp.im == 0.0 {
+                true => Self::new(1.0, 0.0),
+                false => Self::default(),
+            };
+        }
+        if exp.is_real() && exp.re.fract() == 0.0 && exp.re.abs() <= 64.0 {
+            // repeated multiplication keeps small integer powers exact
+            let mut acc = Self::new(1.0, 0.0);
+            for _ in 0..exp.re.abs() as u32 {
+                acc = acc.mul(self);
+            }
+            return match exp.re < 0.0 {
+                true => Self::new(1.0, 0.0).div(&acc),
+                false => acc,
+            };
+        }
+        if self.is_real() && self.re < 0.0 && exp.is_real() {
+            // the angle is a multiple of pi, so avoid the rounding
+            // error of cos(pi / 2) and the like
+            let (cos, sin) = cis_pi(exp.re);
+            let r = (-self.re).powf(exp.re);
+            return Self::new(r * cos, r * sin);
+        }
+        self.ln().mul(exp).exp()
+    }
+}
+
+/// The cosine and sine of `t * pi`, exact when `t` is a multiple of a half.
+fn cis_pi(t: f64) -> (f64, f64) {
+    let t = t.rem_euclid(2.0);
+    match t {
+        0.0 => (1.0, 0.0),
+        0.5 => (0.0, 1.0),
+        1.0 => (-1.0, 0.0),
+        1.5 => (0.0, -1.0),
+        t => ((t * PI).cos(), (t * PI).sin()),
+    }
+}
+
+impl From<f64> for Complex {
+    fn from(re: f64) -> Self {
+        Self::new(re, 0.0)
+    }
+}
+
+impl fmt::Display for Complex {
+    /// Written as `a + bi`, leaving out a zero real part.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let im = |f: &mut fmt::Formatter<'_>, x: f64| match x {
+            1.0 => write!(f, "i"),
+            -1.0 => write!(f, "-i"),
+            x => write!(f, "{}i", x),
+        };
+        if self.im == 0.0 {
+            write!(f, "{}", self.re)
+        } else if self.re == 0.0 {
+            im(f, self.im)
+        } else {
+            let sign = if self.im < 0.0 { '-' } else { '+' };
+            write!(f, "{} {} ", self.re, sign)?;
+            im(f, self.im.abs())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Complex;
+    use std::f64::consts;
+
+    fn close(a: Complex, b: Complex) -> bool {
+        (a.re - b.re).abs() < 1e-12 && (a.im - b.im).abs() < 1e-12
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let a = Complex::new(3.0, 4.0);
+        let b = Complex::new(1.0, -2.0);
+        assert_eq!(a.mul(&b), Complex::new(11.0, -2.0));
+        assert_eq!(a.add(&b), Complex::new(4.0, 2.0));
+        assert_eq!(a.sub(&b), Complex::new(2.0, 6.0));
+        assert_eq!(a.div(&b), Complex::new(-1.0, 2.0));
+        assert_eq!(a.abs(), 5.0);
+        assert_eq!(a.conj(), Complex::new(3.0, -4.0));
+        assert_eq!(Complex::I.mul(&Complex::I), Complex::from(-1.0));
+    }
+
+    #[test]
+    fn test_functions() {
+        assert_eq!(Complex::from(-4.0).sqrt(), Complex::new(0.0, 2.0));
+        assert!(close(
+            Complex::from(-1.0).ln(),
+            Complex::new(0.0, consts::PI)
+        ));
+        assert!(close(
+            Complex::new(0.0, consts::PI).exp(),
+            Complex::from(-1.0)
+        ));
+        assert!(close(
+            Complex::from(-8.0).pow(&Complex::from(1.0 / 3.0)),
+            Complex::new(1.0, 3_f64.sqrt())
+        ));
+        assert_eq!(
+            Complex::from(-4.0).pow(&Complex::from(0.5)),
+            Complex::new(0.0, 2.0)
+        );
+        assert_eq!(
+            Complex::new(1.0, 1.0).pow(&Complex::from(2.0)),
+            Complex::new(0.0, 2.0)
+        );
+        assert!(close(
+            Complex::from_polar(2.0, consts::FRAC_PI_2),
+            Complex::new(0.0, 2.0)
+        ));
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Complex::new(11.0, -2.0).to_string(), "11 - 2i");
+        assert_eq!(Complex::new(0.5, 1.0).to_string(), "0.5 + i");
+        assert_eq!(Complex::new(0.0, -1.0).to_string(), "-i");
+        assert_eq!(Complex::new(0.0, 2.5).to_string(), "2.5i");
+        assert_eq!(Complex::new(3.0, 0.0).to_string(), "3");
+    }
+}
@@ -0,0 +1,607 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use crate::bigint::BigInt;
+use crate::rational::{Rational, MAX_BITS};
+
+/// How a decimal is rounded when it has more
+/// digits than the precision allows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Rounding {
+    /// Round to the nearest digit, and ties to the even one.
+    #[default]
+    HalfEven,
+    /// Round to the nearest digit, and ties away from zero.
+    HalfUp,
+    /// Drop the extra digits.
+    TowardZero,
+}
+
+/// The precision and rounding used for decimal arithmetic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Context {
+    /// The number of significant digits kept, at least one.
+    pub precision: usize,
+    pub rounding: Rounding,
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        Self {
+            precision: 28,
+            rounding: Rounding::default(),
+        }
+    }
+}
+
+/// A decimal floating point number, `coef * 10^exp`. Results are
+/// rounded to the precision of a [`Context`] and never keep trailing
+/// zeros in the coefficient.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decimal {
+    coef: BigInt,
+    exp: i64,
+}
+
+fn ten_pow(n: u64) -> BigInt {
+    BigInt::from(10).pow(n)
+}
+
+/// Whether `10^exp` fits in the [`MAX_BITS`] that exact
+/// arithmetic allows, so it can be worked out in full.
+fn in_range(exp: i64) -> bool {
+    exp.unsigned_abs() as f64 * std::f64::consts::LOG2_10 <= MAX_BITS as f64
+}
+
+/// A context with `extra` more digits than `ctx`, to soak
+/// up the rounding errors of a longer calculation.
+fn guarded(ctx: &Context, extra: usize) -> Context {
+    Context {
+        precision: ctx.precision.max(1) + extra,
+        rounding: Rounding::HalfEven,
+    }
+}
+
+/// The number of decimal digits in `n`.
+fn digits(n: &BigInt) -> u64 {
+    if n.is_zero() {
+        return 1;
+    }
+    // a guess from the number of bits, then fix it up
+    let mut d = (n.bits() as f64 * std::f64::consts::LOG10_2) as u64;
+    let abs = n.abs();
+    while abs >= ten_pow(d) {
+        d += 1;
+    }
+    d.max(1)
+}
+
+impl Decimal {
+    pub fn new(coef: BigInt, exp: i64) -> Self {
+        let mut d = Self { coef, exp };
+        d.strip();
+        d
+    }
+
+    /// Read a plain decimal number like `-12.375`.
+    pub fn from_decimal(s: &str) -> Option<Self> {
+        let (int, frac) = match s.find('.') {
+            Some(i) => (&s[..i], &s[i + 1..]),
+            None => (s, ""),
+        };
+        if frac.starts_with(['-', '+']) {
+            return None;
+        }
+        let coef = BigInt::from_decimal(&format!("{}{}", int, frac))?;
+        Some(Self::new(coef, -(frac.len() as i64)))
+    }
+
+    /// The number times `10^places`, or `None` if the
+    /// exponent would overflow.
+    pub fn shifted(&self, places: i64) -> Option<Self> {
+        Some(Self::new(self.coef.clone(), self.exp.checked_add(places)?))
+    }
+
+    /// Convert a float through its shortest decimal representation.
+    pub fn from_f64(f: f64) -> Option<Self> {
+        if !f.is_finite() {
+            return None;
+        }
+        Self::from_decimal(&format!("{}", f))
+    }
+
+    /// The decimal nearest to a fraction.
+    pub fn from_rational(r: &Rational, ctx: &Context) -> Self {
+        let num = Self::new(r.numer().clone(), 0);
+        let den = Self::new(r.denom().clone(), 0);
+        num.checked_div(&den, ctx).unwrap()
+    }
+
+    /// The exact value as a fraction, or `None` if the exponent is
+    /// too large for a fraction to hold it.
+    pub fn to_rational(&self) -> Option<Rational> {
+        if !in_range(self.exp) {
+            return None;
+        }
+        if self.exp >= 0 {
+            Some(Rational::from_int(&self.coef * &ten_pow(self.exp as u64)))
+        } else {
+            Rational::new(self.coef.clone(), ten_pow(self.exp.unsigned_abs()))
+        }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        format!("{}e{}", self.coef, self.exp)
+            .parse()
+            .unwrap_or(f64::NAN)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.coef.is_zero()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.coef.is_negative()
+    }
+
+    /// Move trailing zeros of the coefficient into the exponent.
+    fn strip(&mut self) {
+        if self.coef.is_zero() {
+            self.exp = 0;
+            return;
+        }
+        let (zeros, rest) = self.coef.remove_factor(10);
+        if zeros > 0 {
+            self.coef = rest;
+            self.exp += zeros as i64;
+        }
+    }
+
+    /// Round to the context's precision. `sticky` says whether there
+    /// were nonzero digits beyond the coefficient that were already
+    /// dropped, which only matters to break ties.
+    fn round(mut self, ctx: &Context, sticky: bool) -> Self {
+        let precision = ctx.precision.max(1) as u64;
+        if sticky {
+            // a nonzero digit past the end pushes ties up
+            self.coef = &(&self.coef * &BigInt::from(10)) + &BigInt::from(1);
+            self.exp -= 1;
+        }
+        let n = digits(&self.coef);
+        if n <= precision {
+            self.strip();
+            return self;
+        }
+        let drop = n - precision;
+        let (q, r) = self.coef.div_rem(&ten_pow(drop)).unwrap();
+        let half = &BigInt::from(5) * &ten_pow(drop - 1);
+        let up = match ctx.rounding {
+            Rounding::TowardZero => false,
+            Rounding::HalfUp => r.abs() >= half,
+            Rounding::HalfEven => match r.abs().cmp(&half) {
+                Ordering::Greater => true,
+                Ordering::Equal => !q.is_even(),
+                Ordering::Less => false,
+            },
+        };
+        let step = BigInt::from(if self.is_negative() { -1 } else { 1 });
+        let coef = if up { &q + &step } else { q };
+        Self::new(coef, self.exp + drop as i64).round(ctx, false)
+    }
+
+    /// Both coefficients scaled to the smaller exponent.
+    fn align(&self, other: &Self) -> (BigInt, BigInt, i64) {
+        let exp = self.exp.min(other.exp);
+        let a = &self.coef * &ten_pow((self.exp - exp) as u64);
+        let b = &other.coef * &ten_pow((other.exp - exp) as u64);
+        (a, b, exp)
+    }
+
+    pub fn add(&self, other: &Self, ctx: &Context) -> Self {
+        let (a, b, exp) = self.align(other);
+        Self::new(&a + &b, exp).round(ctx, false)
+    }
+
+    pub fn sub(&self, other: &Self, ctx: &Context) -> Self {
+        self.add(&other.neg(), ctx)
+    }
+
+    pub fn mul(&self, other: &Self, ctx: &Context) -> Self {
+        Self::new(&self.coef * &other.coef, self.exp + other.exp).round(ctx, false)
+    }
+
+    pub fn neg(&self) -> Self {
+        Self {
+            coef: -&self.coef,
+            exp: self.exp,
+        }
+    }
+
+    pub fn checked_div(&self, other: &Self, ctx: &Context) -> Option<Self> {
+        if other.is_zero() {
+            return None;
+        }
+        // scale the dividend so the quotient has enough digits
+        let wanted = ctx.precision.max(1) as i64 + 1;
+        let shift = (wanted + digits(&other.coef) as i64 - digits(&self.coef) as i64).max(0);
+        let num = &self.coef * &ten_pow(shift as u64);
+        let (q, r) = num.div_rem(&other.coef)?;
+        Some(Self::new(q, self.exp - other.exp - shift).round(ctx, !r.is_zero()))
+    }
+
+    /// The remainder of truncated division, which is exact.
+    pub fn checked_rem(&self, other: &Self, ctx: &Context) -> Option<Self> {
+        let (a, b, exp) = self.align(other);
+        let (_, r) = a.div_rem(&b)?;
+        Some(Self::new(r, exp).round(ctx, false))
+    }
+
+    /// Raise to an integer power with a few guard digits. Gives back
+    /// `None` for `0^-n` and for results that are out of the range
+    /// exact arithmetic allows.
+    pub fn checked_pow(&self, exp: i64, ctx: &Context) -> Option<Self> {
+        let guard = guarded(ctx, 10);
+        let mut base = self.clone();
+        let mut acc = Self::new(BigInt::one(), 0);
+        let mut n = exp.unsigned_abs();
+        while n > 0 {
+            if n & 1 == 1 {
+                acc = acc.mul(&base, &guard);
+            }
+            n >>= 1;
+            if n > 0 {
+                base = base.mul(&base, &guard);
+            }
+            if !in_range(base.exp) || !in_range(acc.exp) {
+                return None;
+            }
+        }
+        if exp < 0 {
+            return Self::new(BigInt::one(), 0).checked_div(&acc, ctx);
+        }
+        Some(acc.round(ctx, false))
+    }
+
+    /// Round to the context's precision.
+    pub fn rounded(&self, ctx: &Context) -> Self {
+        self.clone().round(ctx, false)
+    }
+
+    fn from_int(n: i64) -> Self {
+        Self::new(BigInt::from(n), 0)
+    }
+
+    /// The exponent of the leading digit, like 2 for `123.4`.
+    fn adjusted(&self) -> i64 {
+        self.exp + digits(&self.coef) as i64 - 1
+    }
+
+    /// Sum a series from its first term, where `next` makes the nth
+    /// term from the one before it, until the terms are too small to
+    /// change the sum.
+    fn series<F>(first: Self, ctx: &Context, next: F) -> Self
+    where
+        F: Fn(&Self, i64) -> Self,
+    {
+        let mut sum = first.clone();
+        let mut term = first;
+        for n in 1.. {
+            term = next(&term, n);
+            if term.is_zero() || term.adjusted() < sum.adjusted() - ctx.precision as i64 - 1 {
+                break;
+            }
+            sum = sum.add(&term, ctx);
+        }
+        sum
+    }
+
+    /// The square root, or `None` for negative numbers.
+    pub fn sqrt(&self, ctx: &Context) -> Option<Self> {
+        // scale the coefficient so its root has a digit more than
+        // needed, keeping the exponent even so it can be halved
+        let wanted = ctx.precision.max(1) as i64 + 1;
+        let mut shift = (2 * wanted - digits(&self.coef) as i64).max(0);
+        if (self.exp - shift) % 2 != 0 {
+            shift += 1;
+        }
+        let n = &self.coef * &ten_pow(shift as u64);
+        let root = n.sqrt()?;
+        let inexact = &root * &root != n;
+        Some(Self::new(root, (self.exp - shift) / 2).round(ctx, inexact))
+    }
+
+    /// `e` to the power of `self`, or `None` if that is out of
+    /// the range exact arithmetic allows.
+    pub fn exp(&self, ctx: &Context) -> Option<Self> {
+        if self.to_f64().abs() > MAX_BITS as f64 * std::f64::consts::LN_2 {
+            return None;
+        }
+        // every squaring below doubles the error, and there are at
+        // most 17 of them
+        let guard = guarded(ctx, 20);
+        Some(self.exp_guarded(&guard).round(ctx, false))
+    }
+
+    fn exp_guarded(&self, ctx: &Context) -> Self {
+        // halve until the series converges quickly,
+        // then square the result back up
+        let half = Self::new(BigInt::from(5), -1);
+        let mut x = self.clone();
+        let mut halvings = 0;
+        while !x.is_zero() && x.adjusted() >= 0 {
+            x = x.mul(&half, ctx);
+            halvings += 1;
+        }
+        let mut res = Self::series(Self::from_int(1), ctx, |term, n| {
+            term.mul(&x, ctx)
+                .checked_div(&Self::from_int(n), ctx)
+                .unwrap()
+        });
+        for _ in 0..halvings {
+            res = res.mul(&res, ctx);
+        }
+        res
+    }
+
+    /// The natural logarithm, or `None` unless `self` is positive.
+    pub fn ln(&self, ctx: &Context) -> Option<Self> {
+        let guard = guarded(ctx, 10);
+        Some(self.ln_guarded(&guard)?.round(ctx, false))
+    }
+
+    /// The logarithm to a base.
+    pub fn log(&self, base: &Self, ctx: &Context) -> Option<Self> {
+        let guard = guarded(ctx, 10);
+        let res = self
+            .ln_guarded(&guard)?
+            .checked_div(&base.ln_guarded(&guard)?, &guard)?;
+        Some(res.round(ctx, false))
+    }
+
+    fn ln_guarded(&self, ctx: &Context) -> Option<Self> {
+        if self.is_zero() || self.is_negative() {
+            return None;
+        }
+        // ln(m * 10^k) is ln(m) + k ln(10) for m between 1 and 10
+        let k = self.adjusted();
+        let m = Self {
+            coef: self.coef.clone(),
+            exp: self.exp - k,
+        };
+        let mut res = m.ln_newton(ctx);
+        if k != 0 {
+            let ln10 = Self::from_int(10).ln_newton(ctx);
+            res = res.add(&ln10.mul(&Self::from_int(k), ctx), ctx);
+        }
+        Some(res)
+    }
+
+    /// The logarithm of a number between 1 and 10 with Newton's method,
+    /// starting from the float logarithm. Each step of `y + x / e^y - 1`
+    /// doubles the number of correct digits.
+    fn ln_newton(&self, ctx: &Context) -> Self {
+        let mut y = Self::from_f64(self.to_f64().ln()).unwrap();
+        let mut correct = 15;
+        loop {
+            let step = self.checked_div(&y.exp_guarded(ctx), ctx).unwrap();
+            y = y.add(&step.sub(&Self::from_int(1), ctx), ctx);
+            if correct > ctx.precision {
+                return y;
+            }
+            correct *= 2;
+        }
+    }
+
+    /// π to the precision of the context.
+    pub fn pi(ctx: &Context) -> Self {
+        Self::machin(&guarded(ctx, 10)).round(ctx, false)
+    }
+
+    /// τ, which is 2π, to the precision of the context.
+    pub fn tau(ctx: &Context) -> Self {
+        let guard = guarded(ctx, 10);
+        let tau = Self::machin(&guard).mul(&Self::from_int(2), &guard);
+        tau.round(ctx, false)
+    }
+
+    /// π from Machin's formula, `16 atan(1/5) - 4 atan(1/239)`,
+    /// with the rounding errors of `ctx`.
+    fn machin(ctx: &Context) -> Self {
+        let atan_recip = |n: i64| {
+            let x = Self::from_int(1)
+                .checked_div(&Self::from_int(n), ctx)
+                .unwrap();
+            let x2 = x.mul(&x, ctx).neg();
+            Self::series(x, ctx, |term, k| {
+                let ratio = Self::from_int(2 * k - 1).checked_div(&Self::from_int(2 * k + 1), ctx);
+                term.mul(&x2, ctx).mul(&ratio.unwrap(), ctx)
+            })
+        };
+        let a = atan_recip(5).mul(&Self::from_int(16), ctx);
+        a.sub(&atan_recip(239).mul(&Self::from_int(4), ctx), ctx)
+    }
+
+    /// The sine, or `None` when `self` is so large that
+    /// it isn't worth working out π to enough digits.
+    pub fn sin(&self, ctx: &Context) -> Option<Self> {
+        let guard = guarded(ctx, 10);
+        Some(self.trig(&guard, false)?.round(ctx, false))
+    }
+
+    /// The cosine, see [`Decimal::sin`].
+    pub fn cos(&self, ctx: &Context) -> Option<Self> {
+        let guard = guarded(ctx, 10);
+        Some(self.trig(&guard, true)?.round(ctx, false))
+    }
+
+    /// The tangent, see [`Decimal::sin`].
+    pub fn tan(&self, ctx: &Context) -> Option<Self> {
+        let guard = guarded(ctx, 10);
+        let res = self
+            .trig(&guard, false)?
+            .checked_div(&self.trig(&guard, true)?, &guard)?;
+        Some(res.round(ctx, false))
+    }
+
+    /// The sine, or the cosine when `cos` is set, from their series
+    /// after taking out whole turns.
+    fn trig(&self, ctx: &Context, cos: bool) -> Option<Self> {
+        // the digits before the point cancel out when whole
+        // turns are taken out, so π needs that many more
+        let extra = self.adjusted().max(0) as usize;
+        if extra > 100 {
+            return None;
+        }
+        let guard = guarded(ctx, extra);
+        let tau = Self::machin(&guard).mul(&Self::from_int(2), &guard);
+        let turns = self.checked_div(&tau, &guard)?.to_rational()?.round();
+        let turns = Self::new(turns.numer().clone(), 0);
+        let x = self.sub(&tau.mul(&turns, &guard), &guard);
+        let x2 = x.mul(&x, &guard).neg();
+        let (first, offset) = match cos {
+            true => (Self::from_int(1), 0),
+            false => (x, 1),
+        };
+        Some(Self::series(first, &guard, |term, n| {
+            let div = Self::from_int((2 * n - 1 + offset) * (2 * n + offset));
+            term.mul(&x2, &guard).checked_div(&div, &guard).unwrap()
+        }))
+    }
+}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let (a, b, _) = self.align(other);
+        a.cmp(&b)
+    }
+}
+
+impl fmt::Display for Decimal {
+    /// Written out in full unless the exponent is large, in
+    /// which case scientific notation is used like `1.5e100`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.is_negative() { "-" } else { "" };
+        let digits = self.coef.abs().to_string();
+        let n = digits.len() as i64;
+        // the exponent of the leading digit
+        let adjusted = self.exp + n - 1;
+        if !(-7..=40).contains(&adjusted) {
+            let (first, rest) = digits.split_at(1);
+            let dot = if rest.is_empty() { "" } else { "." };
+            return write!(f, "{}{}{}{}e{}", sign, first, dot, rest, adjusted);
+        }
+        if self.exp >= 0 {
+            write!(f, "{}{}{}", sign, digits, "0".repeat(self.exp as usize))
+        } else if adjusted >= 0 {
+            let (int, frac) = digits.split_at((adjusted + 1) as usize);
+            write!(f, "{}{}.{}", sign, int, frac)
+        } else {
+            let zeros = "0".repeat((-adjusted - 1) as usize);
+            write!(f, "{}0.{}{}", sign, zeros, digits)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Context, Decimal, Rounding};
+    use crate::bigint::BigInt;
+    use crate::rational::Rational;
+
+    fn d(s: &str) -> Decimal {
+        Decimal::from_decimal(s).unwrap()
+    }
+
+    fn ctx(precision: usize, rounding: Rounding) -> Context {
+        Context {
+            precision,
+            rounding,
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        for s in ["0", "1.5", "-0.001", "120", "123.456", "0.0000001"] {
+            assert_eq!(d(s).to_string(), s);
+        }
+        assert_eq!(d("1.500").to_string(), "1.5");
+        assert_eq!(d("0.00000001").to_string(), "1e-8");
+        let big = Decimal::new(BigInt::from(15), 99);
+        assert_eq!(big.to_string(), "1.5e100");
+    }
+
+    #[test]
+    fn test_rounding() {
+        let c = Context::default();
+        let third = d("1").checked_div(&d("3"), &c).unwrap();
+        assert_eq!(third.to_string(), "0.3333333333333333333333333333");
+        let two_thirds = d("2").checked_div(&d("3"), &c).unwrap();
+        assert_eq!(two_thirds.to_string(), "0.6666666666666666666666666667");
+
+        let round = |s: &str, rounding| d(s).add(&d("0"), &ctx(2, rounding)).to_string();
+        assert_eq!(round("2.5", Rounding::HalfEven), "2.5");
+        assert_eq!(round("0.125", Rounding::HalfEven), "0.12");
+        assert_eq!(round("0.135", Rounding::HalfEven), "0.14");
+        assert_eq!(round("0.125", Rounding::HalfUp), "0.13");
+        assert_eq!(round("-0.125", Rounding::HalfUp), "-0.13");
+        assert_eq!(round("0.129", Rounding::TowardZero), "0.12");
+        assert_eq!(round("-0.129", Rounding::TowardZero), "-0.12");
+        assert_eq!(round("9.99", Rounding::HalfEven), "10");
+        // a tie after a division isn't a tie
+        let c = ctx(1, Rounding::HalfEven);
+        assert_eq!(
+            d("2.5").checked_div(&d("1.0001"), &c).unwrap().to_string(),
+            "2"
+        );
+        assert_eq!(d("2.51").checked_div(&d("1"), &c).unwrap().to_string(), "3");
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let c = Context::default();
+        assert_eq!(d("0.1").add(&d("0.2"), &c), d("0.3"));
+        assert_eq!(d("1.10").mul(&d("3"), &c), d("3.3"));
+        assert_eq!(d("5.5").checked_rem(&d("2"), &c), Some(d("1.5")));
+        assert_eq!(d("1").checked_div(&d("0"), &c), None);
+        assert_eq!(d("1.1").checked_pow(2, &c), Some(d("1.21")));
+        assert_eq!(d("2").checked_pow(-2, &c), Some(d("0.25")));
+        assert_eq!(d("1.5").to_rational(), Rational::new(3, 2));
+        assert_eq!(d("10").checked_pow(1_000_000, &c), None);
+        assert_eq!(d("10").checked_pow(-1_000_000, &c), None);
+        assert_eq!(Decimal::new(BigInt::one(), 1_000_000).to_rational(), None);
+        assert_eq!(
+            Decimal::from_rational(&Rational::new(1, 8).unwrap(), &c),
+            d("0.125")
+        );
+        assert!(d("0.1") < d("0.11") && d("-2") < d("-1.5"));
+    }
+
+    #[test]
+    fn test_functions() {
+        let c = ctx(20, Rounding::HalfEven);
+        let f = |x: Option<Decimal>| x.unwrap().to_string();
+        assert_eq!(f(d("2").sqrt(&c)), "1.4142135623730950488");
+        assert_eq!(f(d("0.0004").sqrt(&c)), "0.02");
+        assert_eq!(d("-1").sqrt(&c), None);
+        assert_eq!(f(d("1").exp(&c)), "2.7182818284590452354");
+        assert_eq!(f(d("-20.5").exp(&c)), "1.2501528663867426289e-9");
+        assert_eq!(f(d("0").exp(&c)), "1");
+        assert_eq!(f(d("10").ln(&c)), "2.302585092994045684");
+        assert_eq!(f(d("0.001").ln(&c)), "-6.9077552789821370521");
+        assert_eq!(f(d("1").ln(&c)), "0");
+        assert_eq!(d("0").ln(&c), None);
+        assert_eq!(f(d("8").log(&d("2"), &c)), "3");
+        let pi = Decimal::pi(&c).rounded(&ctx(18, Rounding::HalfEven));
+        assert_eq!(pi.to_string(), "3.14159265358979324");
+        assert_eq!(f(d("1").sin(&c)), "0.84147098480789650665");
+        assert_eq!(f(d("-7").cos(&c)), "0.75390225434330463814");
+        assert_eq!(f(d("0.5").tan(&c)), "0.54630248984379051326");
+        assert_eq!(f(d("0").sin(&c)), "0");
+    }
+}
@@ -0,0 +1,378 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str;
+
+use crate::ast::{self, Ast};
+use crate::bigint::BigInt;
+use crate::decimal::{Context, Decimal, Rounding};
+use crate::error::{Error, EvalErrorKind};
+use crate::function::Function;
+use crate::parser;
+use crate::radix::Radix;
+use crate::rational::Rational;
+use crate::value::Value;
+
+/// What to do when a floating point operation divides
+/// by zero, leaves the real numbers or overflows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Policy {
+    /// Report an error.
+    #[default]
+    Error,
+    /// Give back infinity or NaN like IEEE 754 does.
+    Ieee,
+}
+
+/// How number literals are evaluated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Mode {
+    /// Keep numbers as exact fractions for as long as only rational
+    /// operations are applied, falling back to floating point for
+    /// irrational functions and fractions that grow too large.
+    #[default]
+    Exact,
+    /// Evaluate with decimals that are rounded to the precision
+    /// and rounding mode set on the environment.
+    Decimal,
+    /// Evaluate everything in floating point.
+    Float,
+}
+
+/// How results are written out by [`Env::show`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Output {
+    /// Write exact results as fractions like `1/3` unless
+    /// they have a finite decimal expansion.
+    #[default]
+    Exact,
+    /// Write every result as a decimal, rounded to the precision
+    /// of the environment when its digits don't end.
+    Decimal,
+}
+
+/// A fixed-width integer type like C's `uint8_t` or `int32_t`. When
+/// one is set on an [`Env`], every result is truncated to an integer
+/// and wraps around to fit in it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IntType {
+    pub bits: u32,
+    pub signed: bool,
+}
+
+impl IntType {
+    /// The widest type that can be used.
+    pub const MAX_BITS: u32 = 4096;
+
+    /// Wrap an integer around to the range of the type, keeping the low
+    /// bits like a two's complement machine does.
+    pub fn wrap(&self, n: &BigInt) -> BigInt {
+        let modulus = BigInt::one().shl(self.bits.into());
+        let low = n & &(&modulus - &BigInt::one());
+        if self.signed && low.bits() == u64::from(self.bits) {
+            &low - &modulus
+        } else {
+            low
+        }
+    }
+}
+
+impl str::FromStr for IntType {
+    type Err = String;
+
+    /// Parse a type name like `u8` or `i32`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || format!("invalid integer type '{}'", s);
+        let signed = match s.chars().next() {
+            Some('i') => true,
+            Some('u') => false,
+            _ => return Err(err()),
+        };
+        match s[1..].parse::<u32>() {
+            Ok(bits) if (1..=Self::MAX_BITS).contains(&bits) => Ok(Self { bits, signed }),
+            _ => Err(err()),
+        }
+    }
+}
+
+impl fmt::Display for IntType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.signed { 'i' } else { 'u' };
+        write!(f, "{}{}", sign, self.bits)
+    }
+}
+
+/// An environment that holds named variables
+/// between calls to [`Env::exec`].
+///
+/// # Examples
+///
+/// ```
+/// use calc::{Env, Output};
+///
+/// let mut env = Env::new();
+/// env.exec("let rate = 0.07").unwrap();
+/// assert_eq!(env.exec("rate * 1200").unwrap(), 84.0);
+///
+/// let third = env.exec("1/3").unwrap();
+/// assert_eq!(env.show(&third), "1/3");
+/// env.set_output(Output::Decimal);
+/// assert_eq!(env.show(&third), "0.3333333333333333333333333333");
+/// ```
+#[derive(Debug, Clone)]
+pub struct Env {
+    vars: HashMap<String, Value>,
+    policy: Policy,
+    mode: Mode,
+    output: Output,
+    context: Context,
+    /// The base integers are written in by [`Env::show`].
+    base: u32,
+    /// The number of bits used for negative numbers in other bases.
+    width: u32,
+    int: Option<IntType>,
+    /// How many calls to user defined functions are being evaluated.
+    depth: usize,
+    max_depth: usize,
+    /// Where the stack was when the outermost call started.
+    stack: usize,
+}
+
+impl Default for Env {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Env {
+    /// The deepest that calls to user defined functions can be set to go.
+    pub const MAX_DEPTH: usize = 10_000;
+
+    /// The widest that negative numbers in other bases can be written.
+    pub const MAX_WIDTH: u32 = 4096;
+
+    /// How much stack calls to user defined functions can use before
+    /// they stop, whatever the depth limit. It leaves room to spare in
+    /// the 2 MiB that threads get by default.
+    const STACK_LIMIT: usize = 1536 * 1024;
+
+    pub fn new() -> Self {
+        Self {
+            vars: HashMap::new(),
+            policy: Policy::default(),
+            mode: Mode::default(),
+            output: Output::default(),
+            context: Context::default(),
+            base: 10,
+            width: 64,
+            int: None,
+            depth: 0,
+            max_depth: 256,
+            stack: 0,
+        }
+    }
+
+    /// Execute the code given as a raw string, keeping
+    /// any variables it assigns.
+    pub fn exec(&mut self, text: &str) -> Result<Value, Error> {
+        let ast = parser::parse(text)?;
+        self.eval(&ast)
+    }
+
+    /// Evaluate an expression that has already been parsed.
+    pub fn eval(&mut self, ast: &Ast) -> Result<Value, Error> {
+        Ok(ast::eval(ast, self)?)
+    }
+
+    /// Get the value of a variable.
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.vars.get(name)
+    }
+
+    /// Set the value of a variable.
+    pub fn set<V: Into<Value>>(&mut self, name: &str, val: V) {
+        self.vars.insert(String::from(name), val.into());
+    }
+
+    /// Remove a variable or function, giving back its value.
+    pub fn remove(&mut self, name: &str) -> Option<Value> {
+        self.vars.remove(name)
+    }
+
+    /// The functions defined in the environment like `f(x) = ...`,
+    /// sorted by name. Lambdas stored in variables aren't included.
+    pub fn functions(&self) -> Vec<&Function> {
+        let mut funcs: Vec<&Function> = self
+            .vars
+            .iter()
+            .filter_map(|(name, val)| match val {
+                Value::Function(func) if func.name() == name => Some(func.as_ref()),
+                _ => None,
+            })
+            .collect();
+        funcs.sort_by(|a, b| a.name().cmp(b.name()));
+        funcs
+    }
+
+    /// The definitions of the functions in the environment as
+    /// source text, one per line, which [`Env::exec`] can run
+    /// to define them again.
+    pub fn export(&self) -> String {
+        self.functions()
+            .iter()
+            .map(|func| format!("{}\n", func))
+            .collect()
+    }
+
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    /// Set how deep calls to user defined functions can go, which limits
+    /// how far they can recurse. It is kept between 1 and [`Env::MAX_DEPTH`].
+    /// Calls that use too much stack stop before that with the same error.
+    pub fn set_max_depth(&mut self, depth: usize) {
+        self.max_depth = depth.clamp(1, Self::MAX_DEPTH);
+    }
+
+    /// Start evaluating a call to a user defined function,
+    /// unless it would go past the depth limit or use too much stack.
+    pub(crate) fn enter(&mut self) -> Result<(), EvalErrorKind> {
+        let marker = 0u8;
+        let here = &marker as *const u8 as usize;
+        if self.depth == 0 {
+            self.stack = here;
+        }
+        if self.depth >= self.max_depth || self.stack.abs_diff(here) > Self::STACK_LIMIT {
+            return Err(EvalErrorKind::Recursion(self.depth));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    /// Finish evaluating a call started by [`Env::enter`].
+    pub(crate) fn leave(&mut self) {
+        self.depth -= 1;
+    }
+
+    pub fn policy(&self) -> Policy {
+        self.policy
+    }
+
+    /// Set what happens when an operation divides by
+    /// zero, leaves the real numbers or overflows.
+    pub fn set_policy(&mut self, policy: Policy) {
+        self.policy = policy;
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Set whether numbers are kept as exact fractions,
+    /// decimals or floats.
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+    }
+
+    /// The precision and rounding used in [`Mode::Decimal`].
+    pub fn context(&self) -> Context {
+        self.context
+    }
+
+    /// Set the number of significant digits kept by decimals.
+    pub fn set_precision(&mut self, precision: usize) {
+        self.context.precision = precision.max(1);
+    }
+
+    /// Set how decimals are rounded.
+    pub fn set_rounding(&mut self, rounding: Rounding) {
+        self.context.rounding = rounding;
+    }
+
+    pub fn output(&self) -> Output {
+        self.output
+    }
+
+    /// Set how [`Env::show`] writes out results.
+    pub fn set_output(&mut self, output: Output) {
+        self.output = output;
+    }
+
+    pub fn base(&self) -> u32 {
+        self.base
+    }
+
+    /// Set the base integer results are written in, which
+    /// has to be 2, 8, 10 or 16.
+    pub fn set_base(&mut self, base: u32) -> Result<(), String> {
+        if !matches!(base, 2 | 8 | 10 | 16) {
+            return Err(format!("unsupported base '{}'", base));
+        }
+        self.base = base;
+        Ok(())
+    }
+
+    /// The number of bits negative numbers are written with in other
+    /// bases and that `rol` and `ror` rotate, which is the width of the
+    /// integer type if one is set.
+    pub fn width(&self) -> u32 {
+        self.int.map_or(self.width, |int| int.bits)
+    }
+
+    /// Set the number of bits negative numbers are written with in
+    /// two's complement when they are shown in base 2, 8 or 16,
+    /// which has to be between 1 and [`Env::MAX_WIDTH`].
+    pub fn set_width(&mut self, width: u32) -> Result<(), String> {
+        if !(1..=Self::MAX_WIDTH).contains(&width) {
+            return Err(format!("unsupported width '{}'", width));
+        }
+        self.width = width;
+        Ok(())
+    }
+
+    pub fn int(&self) -> Option<IntType> {
+        self.int
+    }
+
+    /// Set the integer type that results wrap around to fit in,
+    /// or `None` to stop wrapping.
+    pub fn set_int(&mut self, int: Option<IntType>) {
+        self.int = int;
+    }
+
+    /// Format a value according to the output and base settings.
+    pub fn show(&self, val: &Value) -> String {
+        let int = match val {
+            Value::Float(f) => Rational::from_f64(*f),
+            val => val.to_rational(),
+        };
+        let int = int.filter(Rational::is_integer);
+        match int {
+            Some(n) if self.base != 10 && !matches!(val, Value::Radix(_)) => {
+                let radix = Radix::new(n.numer().clone(), self.base, self.width);
+                return radix.to_string();
+            }
+            _ => (),
+        }
+        match (self.output, val) {
+            (Output::Decimal, Value::Rational(r)) => {
+                // a fraction whose digits end is written in full,
+                // others are rounded to the precision
+                let ctx = match r.is_terminating() {
+                    true => Context {
+                        precision: (r.numer().bits() + r.denom().bits()) as usize + 1,
+                        ..self.context
+                    },
+                    false => self.context,
+                };
+                Decimal::from_rational(r, &ctx).to_string()
+            }
+            (_, Value::Quantity(q)) => format!("{} {}", self.show(q.value()), q.unit()),
+            (_, Value::List(items)) => {
+                let items: Vec<String> = items.iter().map(|item| self.show(item)).collect();
+                format!("[{}]", items.join(", "))
+            }
+            _ => val.to_string(),
+        }
+    }
+}
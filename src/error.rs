@@ -0,0 +1,237 @@
+use std::error;
+use std::fmt;
+
+/// A range of bytes in the source text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    /// The smallest span that covers both spans.
+    pub fn join(self, other: Span) -> Self {
+        Self {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+}
+
+/// An error from reading or evaluating an expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The text contains something that is not a valid token.
+    Lex { msg: String, span: Span },
+    /// The tokens do not make up a valid expression.
+    Parse { msg: String, span: Span },
+    /// The expression could not be evaluated.
+    Eval(EvalError),
+}
+
+/// An error from evaluating an expression.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvalError {
+    pub kind: EvalErrorKind,
+    /// The part of the expression that failed.
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvalErrorKind {
+    /// Dividing or taking the remainder by zero.
+    DivisionByZero,
+    /// The result is not a real number, like `(-8)^0.5`.
+    Domain(String),
+    /// The result is too large to be represented.
+    Overflow(String),
+    UndefinedVariable(String),
+    UnknownFunction(String),
+    /// A function was given the wrong number of arguments.
+    Arity(String),
+    /// The syntax tree does not describe a valid expression.
+    Malformed(String),
+    /// The units of the operands don't fit together, like in `3 m + 2 s`.
+    Dimension(String),
+    /// An operation that needs an integer got something else.
+    NotInteger(String),
+    /// A value of the wrong kind, like the boolean in `(1 < 2) + 1`.
+    Type(String),
+    /// Lists whose lengths don't fit together, like in `[1, 2] + [1, 2, 3]`.
+    Shape(String),
+    /// A matrix with no inverse was inverted or solved with.
+    Singular,
+    /// An expression that can't be worked with symbolically,
+    /// like differentiating `x!`.
+    Symbolic(String),
+    /// An equation without a solution, like `x^2 = -1`,
+    /// which has no real roots.
+    NoSolution(String),
+    /// A numeric result that couldn't be made as accurate as asked,
+    /// like integrating `1 / x^2` close to zero.
+    Tolerance(String),
+    /// Calls to user defined functions went deeper than the
+    /// environment's limit, which is given.
+    Recursion(usize),
+}
+
+impl Error {
+    pub fn lex<S: Into<String>>(msg: S, span: Span) -> Self {
+        Error::Lex {
+            msg: msg.into(),
+            span,
+        }
+    }
+
+    pub fn parse<S: Into<String>>(msg: S, span: Span) -> Self {
+        Error::Parse {
+            msg: msg.into(),
+            span,
+        }
+    }
+
+    /// The part of the source text the error points at.
+    pub fn span(&self) -> Span {
+        match self {
+            Error::Lex { span, .. } | Error::Parse { span, .. } => *span,
+            Error::Eval(err) => err.span,
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            Error::Lex { msg, .. } | Error::Parse { msg, .. } => msg.clone(),
+            Error::Eval(err) => err.kind.to_string(),
+        }
+    }
+
+    /// Render the error along with the line of `src` that it
+    /// points at, underlining the offending text.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let src = "1 + nope(2)";
+    /// let err = calc::exec(src).unwrap_err();
+    /// assert_eq!(
+    ///     err.render(src),
+    ///     "Error: unknown function 'nope'\n  1 + nope(2)\n      ^^^^^^^"
+    /// );
+    /// ```
+    pub fn render(&self, src: &str) -> String {
+        let span = self.span();
+        let mut start = span.start.min(src.len());
+        while !src.is_char_boundary(start) {
+            start -= 1;
+        }
+        let line_start = src[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = src[start..].find('\n').map_or(src.len(), |i| start + i);
+        let mut end = span.end.clamp(start, line_end);
+        while !src.is_char_boundary(end) {
+            end += 1;
+        }
+
+        // keep tabs so the caret lines up with the text above it
+        let indent: String = src[line_start..start]
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let width = src[start..end].chars().count().max(1);
+        format!(
+            "Error: {}\n  {}\n  {}{}",
+            self,
+            src[line_start..line_end].trim_end(),
+            indent,
+            "^".repeat(width)
+        )
+    }
+}
+
+impl EvalError {
+    pub fn new(kind: EvalErrorKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
+
+impl From<EvalError> for Error {
+    fn from(err: EvalError) -> Self {
+        Error::Eval(err)
+    }
+}
+
+impl fmt::Display for EvalErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalErrorKind::DivisionByZero => write!(f, "division by zero"),
+            EvalErrorKind::Domain(expr) => write!(f, "{} is not a real number", expr),
+            EvalErrorKind::Overflow(expr) => write!(f, "{} is out of range", expr),
+            EvalErrorKind::UndefinedVariable(name) => write!(f, "undefined variable '{}'", name),
+            EvalErrorKind::UnknownFunction(name) => write!(f, "unknown function '{}'", name),
+            EvalErrorKind::NotInteger(expr) => write!(f, "{} is not an integer", expr),
+            EvalErrorKind::Singular => write!(f, "matrix is singular"),
+            EvalErrorKind::Recursion(depth) => {
+                write!(f, "recursion is deeper than {} calls", depth)
+            }
+            EvalErrorKind::Arity(msg)
+            | EvalErrorKind::Malformed(msg)
+            | EvalErrorKind::Dimension(msg)
+            | EvalErrorKind::Type(msg)
+            | EvalErrorKind::Shape(msg)
+            | EvalErrorKind::Symbolic(msg)
+            | EvalErrorKind::NoSolution(msg)
+            | EvalErrorKind::Tolerance(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)
+    }
+}
+
+impl error::Error for EvalError {}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message())
+    }
+}
+
+impl error::Error for Error {}
+
+#[cfg(test)]
+mod test {
+    use super::{Error, Span};
+
+    #[test]
+    fn test_join() {
+        let span = Span::new(4, 6).join(Span::new(1, 2));
+        assert_eq!(span, Span::new(1, 6));
+    }
+
+    #[test]
+    fn test_render() {
+        let err = Error::parse("unexpected token '3'", Span::new(2, 3));
+        assert_eq!(
+            err.render("2 3 4"),
+            "Error: unexpected token '3'\n  2 3 4\n    ^"
+        );
+        // the end of the input gets a single caret
+        let err = Error::parse("unexpected end of input", Span::new(3, 3));
+        assert_eq!(
+            err.render("1 +"),
+            "Error: unexpected end of input\n  1 +\n     ^"
+        );
+        // only the line containing the error is shown
+        let err = Error::lex("invalid character '#'", Span::new(7, 8));
+        assert_eq!(
+            err.render("1 +\n\t2 # 3"),
+            "Error: invalid character '#'\n  \t2 # 3\n  \t  ^"
+        );
+    }
+}
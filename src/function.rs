@@ -0,0 +1,276 @@
+use std::fmt;
+use std::sync::Arc;
+
+use crate::ast::{self, Ast};
+use crate::builtins::{Arity, Builtin};
+use crate::env::Env;
+use crate::error::EvalErrorKind;
+use crate::integrate;
+use crate::lex::Token;
+use crate::list;
+use crate::value::Value;
+
+/// A function defined in a session, like `f(x, y) = x^2 + y^2`,
+/// or a lambda like `x -> x^2`.
+#[derive(Debug, Clone)]
+pub struct Function {
+    /// Empty for a lambda.
+    name: String,
+    params: Vec<String>,
+    body: Ast,
+    /// The variables a lambda uses from where it was written, with
+    /// their values at that time.
+    captured: Vec<(String, Value)>,
+}
+
+impl Function {
+    pub fn new(name: &str, params: Vec<String>, body: Ast) -> Self {
+        Self {
+            name: String::from(name),
+            params,
+            body,
+            captured: vec![],
+        }
+    }
+
+    /// A lambda that captures the values its body uses from `env`.
+    pub(crate) fn lambda(params: Vec<String>, body: Ast, env: &Env) -> Self {
+        let mut names = vec![];
+        free_names(&body, &params, &mut names);
+        let captured = names
+            .into_iter()
+            .filter_map(|name| env.get(&name).cloned().map(|val| (name, val)))
+            .collect();
+        Self {
+            name: String::new(),
+            params,
+            body,
+            captured,
+        }
+    }
+
+    /// A lambda that calls a builtin, like `x -> sqrt(x)`, so the builtin
+    /// can be passed by name to a function like `map`. A builtin that
+    /// takes any number of arguments is given two, as `reduce` needs.
+    pub(crate) fn builtin(builtin: &Builtin) -> Self {
+        let count = match builtin.arity {
+            Arity::Exact(n) | Arity::Range(n, _) => n,
+            Arity::AtLeast(n) => n.max(2),
+        };
+        let params: Vec<String> = match count {
+            1 => vec![String::from("x")],
+            n => (b'a'..)
+                .take(n)
+                .map(|c| char::from(c).to_string())
+                .collect(),
+        };
+        let args = params
+            .iter()
+            .map(|p| Ast::new(Token::Ident(p.clone())))
+            .collect();
+        let body = Ast::from(Token::Func(String::from(builtin.name)), args);
+        Self {
+            name: String::new(),
+            params,
+            body,
+            captured: vec![],
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn params(&self) -> &[String] {
+        &self.params
+    }
+
+    pub fn body(&self) -> &Ast {
+        &self.body
+    }
+
+    pub fn is_lambda(&self) -> bool {
+        self.name.is_empty()
+    }
+
+    /// Whether the function is a lambda that captured variables.
+    pub(crate) fn is_closure(&self) -> bool {
+        !self.captured.is_empty()
+    }
+
+    /// Call the function. The parameters, and the captured variables of
+    /// a lambda, are bound as variables while the body is evaluated and
+    /// restored afterwards.
+    pub(crate) fn apply(&self, args: Vec<Value>, env: &mut Env) -> Result<Value, EvalErrorKind> {
+        if args.len() != self.params.len() {
+            return Err(arity(&self.describe(), self.params.len(), args.len()));
+        }
+        env.enter()?;
+        let bindings = self
+            .captured
+            .iter()
+            .cloned()
+            .chain(self.params.iter().cloned().zip(args));
+        let mut saved = vec![];
+        for (name, val) in bindings {
+            saved.push((name.clone(), env.remove(&name)));
+            env.set(&name, val);
+        }
+        let res = ast::eval(&self.body, env);
+        // in reverse, in case a parameter has the name of a captured variable
+        for (name, old) in saved.into_iter().rev() {
+            match old {
+                Some(val) => env.set(&name, val),
+                None => drop(env.remove(&name)),
+            }
+        }
+        env.leave();
+        res.map_err(|err| err.kind)
+    }
+
+    /// Name the function in an error message.
+    pub(crate) fn describe(&self) -> String {
+        match self.is_lambda() {
+            true => format!("({})", self),
+            false => self.name.clone(),
+        }
+    }
+}
+
+/// Collect the variables and functions used in `ast` that
+/// aren't one of `params`.
+fn free_names(ast: &Ast, params: &[String], names: &mut Vec<String>) {
+    match &ast.tok {
+        Token::Ident(name) | Token::Func(name)
+            if !params.contains(name) && !names.contains(name) =>
+        {
+            names.push(name.clone())
+        }
+        _ => (),
+    }
+    for child in &ast.children {
+        free_names(child, params, names);
+    }
+}
+
+pub(crate) fn arity(name: &str, params: usize, args: usize) -> EvalErrorKind {
+    EvalErrorKind::Arity(format!(
+        "{} takes {} {} but {} {} given",
+        name,
+        params,
+        if params == 1 { "argument" } else { "arguments" },
+        args,
+        if args == 1 { "was" } else { "were" },
+    ))
+}
+
+impl PartialEq for Function {
+    /// Functions are equal when they are defined the same way.
+    fn eq(&self, other: &Self) -> bool {
+        self.to_string() == other.to_string() && self.captured == other.captured
+    }
+}
+
+impl fmt::Display for Function {
+    /// Written as the definition or lambda it came from,
+    /// which can be parsed again to get the same function.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let params = self.params.join(", ");
+        match (self.is_lambda(), self.params.len()) {
+            (false, _) => write!(f, "{}({}) = {}", self.name, params, self.body),
+            (true, 1) => write!(f, "{} -> {}", params, self.body),
+            (true, _) => write!(f, "({}) -> {}", params, self.body),
+        }
+    }
+}
+
+/// A builtin that takes functions as arguments, which
+/// unlike the others needs the environment to call them.
+pub(crate) type HigherOrder = fn(&[Value], &mut Env) -> Result<Value, EvalErrorKind>;
+
+/// Find a builtin that takes functions as arguments.
+pub(crate) fn lookup(name: &str) -> Option<HigherOrder> {
+    match name {
+        "map" => Some(map),
+        "filter" => Some(filter),
+        "reduce" => Some(reduce),
+        "integrate" => Some(integrate::integrate),
+        _ => None,
+    }
+}
+
+/// The function `val` is, or an error if it isn't one.
+pub(crate) fn function(val: &Value) -> Result<Arc<Function>, EvalErrorKind> {
+    match val {
+        Value::Function(func) => Ok(func.clone()),
+        val => Err(EvalErrorKind::Type(format!(
+            "expected a function but got {}",
+            val
+        ))),
+    }
+}
+
+/// `map(f, xs)`, the list of `f(x)` for each `x` in `xs`.
+fn map(args: &[Value], env: &mut Env) -> Result<Value, EvalErrorKind> {
+    let [f, xs] = args else {
+        return Err(arity("map", 2, args.len()));
+    };
+    let (f, xs) = (function(f)?, list::items(xs)?);
+    let mut res = Vec::with_capacity(xs.len());
+    for x in xs {
+        res.push(f.apply(vec![x.clone()], env)?);
+    }
+    Ok(Value::List(res))
+}
+
+/// `filter(p, xs)`, the items of `xs` that `p` is true for.
+fn filter(args: &[Value], env: &mut Env) -> Result<Value, EvalErrorKind> {
+    let [p, xs] = args else {
+        return Err(arity("filter", 2, args.len()));
+    };
+    let (p, xs) = (function(p)?, list::items(xs)?);
+    let mut res = vec![];
+    for x in xs {
+        match p.apply(vec![x.clone()], env)? {
+            Value::Bool(true) => res.push(x.clone()),
+            Value::Bool(false) => (),
+            val => {
+                return Err(EvalErrorKind::Type(format!(
+                    "expected true or false but got {}",
+                    val
+                )))
+            }
+        }
+    }
+    Ok(Value::List(res))
+}
+
+/// `reduce(f, xs, init)`, combining the items of `xs` from the left
+/// with `f`, starting from `init` or from the first item without it.
+fn reduce(args: &[Value], env: &mut Env) -> Result<Value, EvalErrorKind> {
+    let (f, xs, init) = match args {
+        [f, xs] => (f, xs, None),
+        [f, xs, init] => (f, xs, Some(init.clone())),
+        _ => {
+            return Err(EvalErrorKind::Arity(format!(
+                "reduce takes 2 to 3 arguments but {} {} given",
+                args.len(),
+                if args.len() == 1 { "was" } else { "were" },
+            )))
+        }
+    };
+    let (f, xs) = (function(f)?, list::items(xs)?);
+    let mut items = xs.iter().cloned();
+    let mut acc = match init.or_else(|| items.next()) {
+        Some(acc) => acc,
+        None => {
+            return Err(EvalErrorKind::Arity(String::from(
+                "reduce of an empty list needs a starting value",
+            )))
+        }
+    };
+    for x in items {
+        acc = f.apply(vec![acc, x], env)?;
+    }
+    Ok(acc)
+}
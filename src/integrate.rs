@@ -0,0 +1,185 @@
+use crate::env::Env;
+use crate::error::EvalErrorKind;
+use crate::function::{self, Function};
+use crate::value::Value;
+
+/// How close the integral has to be when `integrate` isn't given a
+/// tolerance, relative to its size when that's more than 1.
+const TOLERANCE: f64 = 1e-10;
+/// The most pieces the range of an integral is cut into.
+const MAX_PIECES: usize = 2000;
+
+/// The nodes of the 15 point Kronrod rule on [-1, 1], from the
+/// outside in. Those at odd indexes are the nodes of the 7 point
+/// Gauss rule as well.
+const NODES: [f64; 8] = [
+    0.9914553711208126,
+    0.9491079123427585,
+    0.8648644233597691,
+    0.7415311855993945,
+    0.5860872354676911,
+    0.4058451513773972,
+    0.20778495500789848,
+    0.0,
+];
+/// The weights of the Kronrod rule at `NODES`.
+const KRONROD: [f64; 8] = [
+    0.022935322010529224,
+    0.06309209262997856,
+    0.10479001032225019,
+    0.14065325971552592,
+    0.1690047266392679,
+    0.19035057806478542,
+    0.20443294007529889,
+    0.20948214108472782,
+];
+/// The weights of the Gauss rule at the odd `NODES`.
+const GAUSS: [f64; 4] = [
+    0.1294849661688697,
+    0.27970539148927664,
+    0.3818300505051189,
+    0.4179591836734694,
+];
+
+/// `integrate(f, a, b)`, the integral of `f` from `a` to `b`, worked
+/// out with adaptive Gauss-Kronrod quadrature. `integrate(f, a, b, tol)`
+/// asks for the result to be within `tol` instead of the default, and
+/// it's an error when the estimated error is still larger than that.
+pub(crate) fn integrate(args: &[Value], env: &mut Env) -> Result<Value, EvalErrorKind> {
+    let (f, a, b, tol) = match args {
+        [f, a, b] => (f, a, b, TOLERANCE),
+        [f, a, b, tol] => match tol.as_f64() {
+            Some(tol) if tol > 0.0 => (f, a, b, tol),
+            _ => {
+                return Err(EvalErrorKind::Type(format!(
+                    "expected a positive tolerance but got {}",
+                    tol
+                )))
+            }
+        },
+        _ => {
+            return Err(EvalErrorKind::Arity(format!(
+                "integrate takes 3 to 4 arguments but {} {} given",
+                args.len(),
+                if args.len() == 1 { "was" } else { "were" },
+            )))
+        }
+    };
+    let f = function::function(f)?;
+    let (a, b) = (bound(a)?, bound(b)?);
+    let (sign, a, b) = if a <= b { (1.0, a, b) } else { (-1.0, b, a) };
+    let (total, error) = adaptive(&f, a, b, tol, env)?;
+    if error > tol * total.abs().max(1.0) {
+        return Err(EvalErrorKind::Tolerance(format!(
+            "the integral is about {} but could be off by {:e}, more than the tolerance {:e}",
+            sign * total,
+            error,
+            tol
+        )));
+    }
+    Ok(Value::Float(sign * total))
+}
+
+fn bound(val: &Value) -> Result<f64, EvalErrorKind> {
+    match val.as_f64() {
+        Some(x) if x.is_finite() => Ok(x),
+        _ => Err(EvalErrorKind::Type(format!(
+            "expected a finite real number but got {}",
+            val
+        ))),
+    }
+}
+
+/// A piece of the range with the integral over it and
+/// an estimate of how far off that is.
+struct Piece {
+    a: f64,
+    b: f64,
+    integral: f64,
+    error: f64,
+}
+
+/// The integral of `f` over [a, b] and its estimated error. The piece
+/// with the largest error is halved until the total error is within
+/// the tolerance, or there are too many pieces.
+fn adaptive(
+    f: &Function,
+    a: f64,
+    b: f64,
+    tol: f64,
+    env: &mut Env,
+) -> Result<(f64, f64), EvalErrorKind> {
+    let mut pieces = vec![kronrod(f, a, b, env)?];
+    loop {
+        let total: f64 = pieces.iter().map(|p| p.integral).sum();
+        let error: f64 = pieces.iter().map(|p| p.error).sum();
+        if error <= tol * total.abs().max(1.0) || pieces.len() >= MAX_PIECES {
+            return Ok((total, error));
+        }
+        let worst = (0..pieces.len())
+            .max_by(|&i, &j| pieces[i].error.total_cmp(&pieces[j].error))
+            .unwrap();
+        let Piece { a, b, .. } = pieces.swap_remove(worst);
+        let mid = (a + b) / 2.0;
+        // the range can't be cut any finer
+        if mid <= a || mid >= b {
+            return Ok((total, error));
+        }
+        pieces.push(kronrod(f, a, mid, env)?);
+        pieces.push(kronrod(f, mid, b, env)?);
+    }
+}
+
+/// The integral of `f` over [a, b] with the 15 point Kronrod rule,
+/// with the difference from the 7 point Gauss rule as its error.
+fn kronrod(f: &Function, a: f64, b: f64, env: &mut Env) -> Result<Piece, EvalErrorKind> {
+    let (center, half) = ((a + b) / 2.0, (b - a) / 2.0);
+    let (mut k, mut g) = (0.0, 0.0);
+    let mut at = |x| at(f, x, (a, b), env);
+    for (i, x) in NODES.iter().enumerate() {
+        let y = match *x == 0.0 {
+            true => at(center)?,
+            false => at(center - half * x)? + at(center + half * x)?,
+        };
+        k += KRONROD[i] * y;
+        if i % 2 == 1 {
+            g += GAUSS[i / 2] * y;
+        }
+    }
+    Ok(Piece {
+        a,
+        b,
+        integral: k * half,
+        error: ((k - g) * half).abs(),
+    })
+}
+
+/// The value of `f` at `x` in `piece`, which has to be a finite real
+/// number. One that is too large means the integral diverges there.
+fn at(f: &Function, x: f64, piece: (f64, f64), env: &mut Env) -> Result<f64, EvalErrorKind> {
+    let diverges = || {
+        let near = Value::Float(shortest(piece.0, piece.1));
+        EvalErrorKind::Tolerance(format!("the integral diverges near {}", near))
+    };
+    let y = match f.apply(vec![Value::Float(x)], env) {
+        Err(EvalErrorKind::Overflow(_)) => return Err(diverges()),
+        res => res?,
+    };
+    match y.as_f64() {
+        Some(y) if y.is_finite() => Ok(y),
+        Some(y) if y.is_infinite() => Err(diverges()),
+        _ => Err(EvalErrorKind::Domain(format!("{}({})", f.describe(), x))),
+    }
+}
+
+/// The number between `a` and `b` with the fewest significant digits.
+fn shortest(a: f64, b: f64) -> f64 {
+    if a <= 0.0 && 0.0 <= b {
+        return 0.0;
+    }
+    let mid = (a + b) / 2.0;
+    (0..17)
+        .filter_map(|digits| format!("{:.*e}", digits, mid).parse().ok())
+        .find(|x| a <= *x && *x <= b)
+        .unwrap_or(mid)
+}
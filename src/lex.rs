@@ -2,16 +2,26 @@
 
 use std::fmt;
 use std::iter::Peekable;
-use std::str::Chars;
+use std::str::CharIndices;
+
+use crate::bigint::BigInt;
+use crate::decimal::Decimal;
+use crate::error::Span;
 
 // extern crate radix_trie;
 // use radix_trie::Trie;
 
-#[derive(Debug, PartialEq)]
+/// A piece of the source text. Tokens are also used
+/// to label the nodes of an [`Ast`](crate::Ast).
+#[derive(Debug, Clone, PartialEq)]
 pub enum Token {
     Op(char),
-    Int(i64),
-    Float(f64),
+    Int(BigInt),
+    /// A number with a decimal point, an exponent or a suffix, kept
+    /// exactly as it was written, like `0.1` or `6.022e23`.
+    Float(Decimal),
+    /// An imaginary number literal like `4i` or `2.5j`.
+    Imaginary(f64),
 
     // TODO: add a counter to the paren tokens
     // i.e. 'OpenParen(i32)' so when I need to
@@ -23,23 +33,58 @@ pub enum Token {
     // it on the Lexer).
     OpenParen,
     CloseParen,
+    OpenBracket,
+    CloseBracket,
+    /// The arrow of a lambda, "x -> x^2".
+    Arrow,
+    /// A range of numbers, "1..10".
+    Range,
+
+    /// Bitwise not, `~` or `¬`.
+    Negation,
+    /// Bitwise exclusive or, "x xor y".
+    Xor,
+    ShiftLeft,
+    ShiftRight,
+    /// Rotating the bits of a fixed-width integer, "x rol 4".
+    RotateLeft,
+    RotateRight,
 
-    Negation, // TODO: use ¬ or ~
+    /// Comparisons, besides `<` and `>` which are `Op`s.
+    LessEqual,
+    GreaterEqual,
+    EqualEqual,
+    NotEqual,
+    /// Logical operators on the results of comparisons.
+    And,
+    Or,
+    Not,
+    /// A conditional, "if x > 0 then x else -x".
+    If,
+    Then,
+    Else,
 
     Modulus,
-    Factorial, // TODO:
+    Factorial,
 
     Of, // TODO: 10 % of 3
+    /// Unit conversion, "60 mph to km/h".
+    To,
+    /// Unit conversion like `to`, "98.6 degF in degC", unless
+    /// it's the inch in "5 ft 11 in".
+    In,
 
-    // TODO:
-    //      - sum
-    //      - sqrt
-    //      - log
-    Func,   // TODO: add functions
-    Assign, // TODO: add assignment support ("let name = ...")
-    Equal,  // TODO: a single equal sign like real math f = 2*x
+    Func(String), // a function call node: "sqrt(2)"
+    List,         // a list node: "[1, 2, 3]"
+    Lambda,       // a lambda node, the parameters then the body: "(a, b) -> a*b"
+    Index,        // an indexing node: "xs[0]"
+    Call,         // a call of what the first child gives back: "adder(3)(4)"
+    Comma,
+    Let,    // the 'let' keyword
+    Assign, // an assignment node: "let name = ..."
+    Equal,  // a single equal sign, in an assignment or an equation: "x^2 = 2"
 
-    Ident, // Identifiers
+    Ident(String), // Identifiers
 
     End,
     Invalid,
@@ -61,9 +106,9 @@ pub enum Op {
 
 pub fn lex(s: &str) -> Vec<Token> {
     let mut toks = vec![];
-    let mut chars = s.chars().peekable();
+    let mut chars = s.char_indices().peekable();
     loop {
-        let (next, _) = next_token(&mut chars);
+        let (next, _) = next_token(&mut chars, s.len());
         match next {
             Token::End | Token::Invalid => break toks,
             _ => toks.push(next),
@@ -71,271 +116,187 @@ pub fn lex(s: &str) -> Vec<Token> {
     }
 }
 
-fn next_token<'b>(chars: &mut Peekable<Chars>) -> (Token, usize) {
-    let (tok, inc) = match eat_spaces(chars) {
-        (Some(c), i) => match c {
-            '(' => (Token::OpenParen, i + 1),
-            ')' => (Token::CloseParen, i + 1),
-            '0'..='9' | '.' => {
-                let (n, adv) = lex_num(chars);
-                return (n, adv + i);
-            }
-            '-' | '+' | '*' | '/' | '^' | '%' => (Token::Op(c), i + 1),
-            '!' => (Token::Factorial, i),
-            '¬' => (Token::Negation, i), // this might make things hard
-            '~' => (Token::Negation, i),
-            'a'..='z' => {
-                // TODO: write a better way to tokenize keywords
-                let mut s = String::new();
-                let mut read = 0;
-                while let Some(c) = chars.next() {
-                    if c.is_digit(10) {
-                        return (Token::Invalid, i + read);
-                    }
-                    s.push(c);
-                    read += 1;
-                    let keyword = match s.as_str() {
-                        "mod" => (Token::Modulus, i + read),
-                        "of" => (Token::Of, i + read + 1),
-                        _ => continue,
-                    };
-                    if let Some(&c) = chars.peek() {
-                        // there should be a space after keywords
-                        // otherwise invalide result
-                        if c != ' ' {
-                            // TODO: return a Token::Error that holads
-                            // an error message or return a Result<Token, &str>
-                            return (Token::Invalid, keyword.1);
-                        }
-                    }
-                    return keyword;
-                }
-                (Token::Invalid, i + read)
-            }
-            _ => return (Token::Invalid, 0),
-        },
-        (None, ..) => return (Token::End, 0),
+/// Read the next token and the span of text it came
+/// from, `len` is the length of the whole text.
+fn next_token(chars: &mut Chars, len: usize) -> (Token, Span) {
+    let (start, c) = match eat_spaces(chars) {
+        (Some(..), _) => *chars.peek().unwrap(),
+        (None, ..) => return (Token::End, Span::new(len, len)),
+    };
+    let tok = match c {
+        '(' => Token::OpenParen,
+        ')' => Token::CloseParen,
+        ',' => Token::Comma,
+        '[' => Token::OpenBracket,
+        ']' => Token::CloseBracket,
+        '-' if next_is(chars, '>') => {
+            chars.next();
+            chars.next();
+            return (Token::Arrow, Span::new(start, start + 2));
+        }
+        '→' => Token::Arrow,
+        '.' if next_is(chars, '.') => {
+            chars.next();
+            chars.next();
+            return (Token::Range, Span::new(start, start + 2));
+        }
+        '0'..='9' | '.' => return lex_num(chars),
+        '-' | '+' | '*' | '/' | '^' | '%' | '&' | '|' | '@' => Token::Op(c),
+        '<' | '>' => return lex_angle(chars),
+        // polar form "5∠53.13°"
+        '∠' | '°' => Token::Op(c),
+        '!' | '=' => return lex_equal(chars),
+        '≤' => Token::LessEqual,
+        '≥' => Token::GreaterEqual,
+        '≠' => Token::NotEqual,
+        '¬' | '~' => Token::Negation,
+        'a'..='z' | 'A'..='Z' | '_' => {
+            let word = lex_word(chars);
+            let span = Span::new(start, start + word.len());
+            let tok = match word.as_str() {
+                "mod" => Token::Modulus,
+                "of" => Token::Of,
+                "xor" => Token::Xor,
+                "rol" => Token::RotateLeft,
+                "ror" => Token::RotateRight,
+                "to" => Token::To,
+                "in" => Token::In,
+                "and" => Token::And,
+                "or" => Token::Or,
+                "not" => Token::Not,
+                "if" => Token::If,
+                "then" => Token::Then,
+                "else" => Token::Else,
+                "let" => Token::Let,
+                _ => Token::Ident(word),
+            };
+            return (tok, span);
+        }
+        _ => Token::Invalid,
     };
     chars.next();
-    (tok, inc)
-    // (tok, 0)
+    (tok, Span::new(start, start + c.len_utf8()))
 }
 
+type Chars<'a> = Peekable<CharIndices<'a>>;
+
 pub struct Lexer<'a> {
-    chars: Peekable<Chars<'a>>,
-    buf: Vec<Token>,
-    index: usize,
-    raw: String,
+    chars: Chars<'a>,
+    buf: Vec<(Token, Span)>,
+    last: Span,
+    previous: Token,
+    raw: &'a str,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(text: &'a str) -> Self {
-        let raw = String::from(text);
         Self {
-            chars: text.chars().peekable(),
+            chars: text.char_indices().peekable(),
             buf: vec![],
-            index: 0,
-            raw: raw,
+            last: Span::default(),
+            previous: Token::End,
+            raw: text,
         }
     }
 
     pub fn from(toks: Vec<Token>) -> Self {
-        return Self {
-            chars: "".chars().peekable(),
-            buf: toks,
-            index: 0,
-            raw: String::new(),
-        };
+        Self {
+            chars: "".char_indices().peekable(),
+            buf: toks.into_iter().map(|t| (t, Span::default())).collect(),
+            last: Span::default(),
+            previous: Token::End,
+            raw: "",
+        }
     }
 
     fn new_empty() -> Self {
-        Self {
-            chars: "".chars().peekable(),
-            buf: vec![],
-            index: 0,
-            raw: String::new(),
-        }
+        Self::new("")
     }
 
-    pub fn get_index(self) -> usize {
-        self.index
+    /// The text being tokenized.
+    pub fn raw(&self) -> &'a str {
+        self.raw
     }
 
-    pub fn error_at_current(&self) -> Vec<String> {
-        let mut v = vec![];
-        let max = self.raw.clone().trim().len();
-        let wrap = max - 1;
-        // let wrap = if self.index >= max {
-        //     max
-        // } else {
-        //     max - self.index
-        // };
-        // let wrap = if self.index < max - 3 {
-        //     3
-        // } else {
-        //     self.index - max
-        // };
+    /// The span of the last token taken from the lexer.
+    pub fn span(&self) -> Span {
+        self.last
+    }
 
-        // let wrap = if self.index >= max { 0 } else { 3 };
-        let mut s = String::new();
-        let raw = self.raw.clone();
-        // let code = &raw[self.index - wrap..self.index + wrap];
-        let code = &raw;
-        s.extend(code.chars());
-        v.push(s.clone());
-        s.clear();
+    /// The last token taken from the lexer, or `End` before the first.
+    pub fn previous(&self) -> &Token {
+        &self.previous
+    }
 
-        s.extend((0..wrap).map(|_| " ").collect::<String>().chars());
-        s.push('^');
-        v.push(s);
-        v
+    /// The span of the next token.
+    pub fn peek_span(&mut self) -> Span {
+        self.fill(1);
+        self.buf[0].1
     }
 
     pub fn peek(&mut self) -> Token {
-        if self.buf.len() == 0 {
-            let (next, i) = next_token(&mut self.chars);
-
-            // let r = self.raw.clone();
-            // if r.len() > self.index + 1 {
-            //     println!(
-            //         "\"{}\" -- Lexer::peek => '{}' {:?} (n:{})",
-            //         r,
-            //         &r[self.index - 0..self.index + 2],
-            //         next,
-            //         i
-            //     );
-            // }
-
-            self.buf.push(next);
-            self.index += i;
-        }
-        self.buf[0]
+        self.fill(1);
+        self.buf[0].0.clone()
     }
 
     pub fn look_ahead(&mut self, n: usize) -> Token {
-        let len = self.buf.len();
-        if len > 0 && len > n {
-            return self.buf[n];
+        self.fill(n + 1);
+        match self.buf.get(n) {
+            Some((tok, _)) => tok.clone(),
+            None => Token::End,
         }
+    }
 
-        // let (mut next, ix): (Token, usize);
-        for _ in len..n {
-            let (next, ix) = next_token(&mut self.chars);
-            if next == Token::Invalid {
-                return Token::End;
-            }
-            self.index += ix;
+    /// Read tokens into the buffer until it holds `n` of them
+    /// or the end of the text is reached.
+    fn fill(&mut self, n: usize) {
+        while self.buf.len() < n {
+            let next = next_token(&mut self.chars, self.raw.len());
+            let done = next.0 == Token::End || next.0 == Token::Invalid;
             self.buf.push(next);
-            if next == Token::End {
-                return next;
+            if done {
+                return;
             }
         }
-        let (next, ix) = next_token(&mut self.chars);
-        self.index += ix;
-        self.buf.push(next);
-        next
     }
 
     /// Discard n tokens from the tokenizer.
     pub fn discard(&mut self, n: usize) {
-        let len = self.buf.len();
-
-        if len < n {
-            self.buf.drain(0..len);
-            for _ in len..n {
-                next_token(&mut self.chars);
-            }
-        } else if len > n {
-            self.buf.drain(0..n);
+        for _ in 0..n {
+            self.next();
         }
     }
 
     pub fn as_vec(&self) -> Vec<Token> {
-        let mut chars = self.chars.clone();
-
-        let mut v = vec![];
-        v.extend(&self.buf);
-        loop {
-            let (t, _) = next_token(&mut chars);
-            match t {
-                Token::Invalid | Token::End => break v,
-                _ => v.push(t),
-            }
-        }
+        self.clone().collect()
     }
 
     pub fn is_empty(&self) -> bool {
-        if !self.buf.is_empty() {
-            false
-        } else {
-            match self.clone().next() {
+        match self.buf.first() {
+            Some((t, _)) => *t == Token::End,
+            None => match self.clone().next() {
                 Some(t) => t == Token::End,
                 None => true,
-            }
-        }
-    }
-
-    /// Capture a group within parenthesis tokens
-    pub fn capture_group(&mut self) -> Result<Lexer, String> {
-        let mut toks = self.clone();
-        match toks.next() {
-            Some(t) => match t {
-                Token::OpenParen => {}
-                _ => return Err(format!("expected '('")),
             },
-            None => return Err(format!("stream ended early")),
         }
-        self.next(); // skip the open paren
-
-        let mut expr = vec![];
-        let mut paren = 0;
-        for t in toks {
-            expr.push(t);
-            match t {
-                Token::CloseParen => {
-                    if paren == 0 {
-                        break;
-                    } else {
-                        paren -= 1;
-                    }
-                }
-                Token::OpenParen => paren += 1,
-                _ => {}
-            }
-        }
-        let size = expr.len() - 1;
-        if size == 0 {
-            return Err(format!("empty parenthesis expression"));
-        }
-        self.discard(size);
-
-        // check for the closing parenthesis in the expression
-        if expr.pop().unwrap() != Token::CloseParen {
-            return Err(format!("expected ')'"));
-        }
-        // check for a closing parenthesis in the main token stream
-        match self.next() {
-            Some(tok) => match tok {
-                Token::CloseParen => {}
-                _ => return Err(format!("expected ')'")),
-            },
-            None => {}
-        }
-        Ok(Lexer::from(expr))
     }
 }
 
 impl<'a> Iterator for Lexer<'a> {
     type Item = Token;
     fn next(&mut self) -> Option<Self::Item> {
-        if self.buf.len() > 0 {
-            return Some(self.buf.remove(0));
+        if !self.buf.is_empty() {
+            let (tok, span) = self.buf.remove(0);
+            self.last = span;
+            self.previous = tok.clone();
+            return Some(tok);
         }
-        let (tok, i) = next_token(&mut self.chars);
+        let (tok, span) = next_token(&mut self.chars, self.raw.len());
         match tok {
             Token::End | Token::Invalid => None,
             _ => {
-                self.index += i;
+                self.last = span;
+                self.previous = tok.clone();
                 Some(tok)
             }
         }
@@ -347,17 +308,18 @@ impl<'a> Clone for Lexer<'a> {
         Self {
             buf: self.buf.clone(),
             chars: self.chars.clone(),
-            index: self.index,
-            raw: self.raw.clone(),
+            last: self.last,
+            previous: self.previous.clone(),
+            raw: self.raw,
         }
     }
 }
 
-fn eat_spaces<'a>(chars: &'a mut Peekable<Chars>) -> (Option<char>, usize) {
+fn eat_spaces(chars: &mut Chars) -> (Option<char>, usize) {
     let mut i = 0;
-    while let Some(&c) = chars.peek() {
+    while let Some(&(_, c)) = chars.peek() {
         match c {
-            ' ' | '\n' | '\t' => {}
+            ' ' | '\n' | '\t' | '\r' => {}
             _ => return (Some(c), i),
         }
         chars.next();
@@ -366,54 +328,313 @@ fn eat_spaces<'a>(chars: &'a mut Peekable<Chars>) -> (Option<char>, usize) {
     (None, i)
 }
 
-fn lex_num<'a>(chars: &mut Peekable<Chars>) -> (Token, usize) {
+/// The engineering suffixes that can end a number, like the `k` in
+/// `4.7k`. There's no `m` for milli, since `2m` is two meters.
+const SUFFIXES: &[(char, i64)] = &[
+    ('T', 12),
+    ('G', 9),
+    ('M', 6),
+    ('k', 3),
+    ('u', -6),
+    ('µ', -6),
+    ('n', -9),
+    ('p', -12),
+    ('f', -15),
+];
+
+/// Read a decimal number like `42`, `1_000`, `6.022e23`, `4.7k` or `2.5i`.
+/// It ends before two dots in a row, so `1..10` is a range.
+/// Anything that starts like a number but isn't one, like `1.2.3`, is
+/// given back as an invalid token covering all of it.
+fn lex_num(chars: &mut Chars) -> (Token, Span) {
+    let start = chars.peek().map_or(0, |&(i, _)| i);
+    if let Some(radix) = radix_prefix(chars) {
+        return lex_radix(chars, start, radix);
+    }
     let mut s = String::with_capacity(16);
-    let mut isfloat = false;
+    let (mut dots, mut digits) = (0, 0);
+    let mut end = start;
 
-    while let Some(&c) = chars.peek() {
-        if c == '.' {
+    while let Some(&(i, c)) = chars.peek() {
+        if c == '.' && next_is(chars, '.') {
+            // the start of a range like 1..10
+            break;
+        } else if c == '.' {
+            dots += 1;
+        } else if c.is_ascii_digit() {
+            digits += 1;
+        } else if !(c == '_' && digit_follows(chars, 10)) {
+            // not a digit separator like in 1_000_000
+            break;
+        }
+        if c != '_' {
+            s.push(c);
+        }
+        end = i + c.len_utf8();
+        chars.next();
+    }
+    if dots > 1 || digits == 0 {
+        return (Token::Invalid, Span::new(start, end));
+    }
+    let mut isfloat = dots == 1;
+    let mut exp = 0;
+    if let Some((e, i)) = exponent(chars) {
+        match e.parse::<i64>() {
+            Ok(e) => exp = e,
+            Err(_) => return (Token::Invalid, Span::new(start, i)),
+        }
+        isfloat = true;
+        end = i;
+    }
+    let float = |exp: i64| format!("{}e{}", s, exp).parse::<f64>().unwrap();
+    // an 'i' or 'j' right after the digits makes it imaginary,
+    // as long as it doesn't start a word like "in"
+    if let Some(&(_, suffix @ ('i' | 'j'))) = chars.peek() {
+        if ends_word(chars) {
+            chars.next();
+            let span = Span::new(start, end + suffix.len_utf8());
+            return (Token::Imaginary(float(exp)), span);
+        }
+    }
+    if let Some(&(_, c)) = chars.peek() {
+        let suffix = SUFFIXES.iter().find(|&&(s, _)| s == c);
+        if let (Some((_, power)), true) = (suffix, ends_word(chars)) {
+            chars.next();
+            exp = match exp.checked_add(*power) {
+                Some(exp) => exp,
+                None => return (Token::Invalid, Span::new(start, end + c.len_utf8())),
+            };
             isfloat = true;
-        } else if !c.is_digit(10) {
+            end += c.len_utf8();
+        }
+    }
+    let span = Span::new(start, end);
+    if !isfloat {
+        return (Token::Int(BigInt::from_decimal(&s).unwrap()), span);
+    }
+    match Decimal::from_decimal(&s).and_then(|d| d.shifted(exp)) {
+        Some(d) => (Token::Float(d), span),
+        None => (Token::Invalid, span),
+    }
+}
+
+/// Read an exponent like the `e-9` in `1e-9`, giving back its digits and
+/// where it ends. An `e` that starts a word like in `5eV` is left alone,
+/// but one without digits after it like `1e` gives back no digits.
+fn exponent(chars: &mut Chars) -> Option<(String, usize)> {
+    let mut ahead = chars.clone();
+    let mut end = match ahead.next() {
+        Some((i, 'e' | 'E')) => i + 1,
+        _ => return None,
+    };
+    if ahead
+        .peek()
+        .is_some_and(|&(_, c)| c.is_alphabetic() || c == '_')
+    {
+        return None;
+    }
+    let mut exp = String::new();
+    if let Some(&(i, sign @ ('+' | '-'))) = ahead.peek() {
+        exp.push(sign);
+        end = i + 1;
+        ahead.next();
+    }
+    while let Some(&(i, c)) = ahead.peek() {
+        if !c.is_ascii_digit() {
             break;
         }
-        s.push(c);
+        exp.push(c);
+        end = i + 1;
+        ahead.next();
+    }
+    *chars = ahead;
+    Some((exp, end))
+}
+
+/// Whether the next character is a word of its own,
+/// so no letter, digit or underscore comes after it.
+fn ends_word(chars: &Chars) -> bool {
+    let mut ahead = chars.clone();
+    ahead.next();
+    !ahead
+        .peek()
+        .is_some_and(|&(_, c)| c.is_alphanumeric() || c == '_')
+}
+
+/// Whether the character after the next one is `c`.
+fn next_is(chars: &Chars, c: char) -> bool {
+    let mut ahead = chars.clone();
+    ahead.next();
+    ahead.peek().is_some_and(|&(_, next)| next == c)
+}
+
+/// Whether the character after the next one is a digit in base `radix`.
+fn digit_follows(chars: &Chars, radix: u32) -> bool {
+    let mut ahead = chars.clone();
+    ahead.next();
+    ahead.peek().is_some_and(|&(_, c)| c.is_digit(radix))
+}
+
+/// Skip a prefix like the `0x` in `0xFF` and give back its base. It
+/// only counts when a digit follows, so `0B` is still zero bytes.
+fn radix_prefix(chars: &mut Chars) -> Option<u32> {
+    let mut ahead = chars.clone();
+    if ahead.next()?.1 != '0' {
+        return None;
+    }
+    let radix = match ahead.peek()?.1 {
+        'x' | 'X' => 16,
+        'o' | 'O' => 8,
+        'b' | 'B' => 2,
+        _ => return None,
+    };
+    if !digit_follows(&ahead, radix) {
+        return None;
+    }
+    chars.next();
+    chars.next();
+    Some(radix)
+}
+
+/// Read the digits of an integer in base `radix` after its prefix.
+/// Letters and digits that don't belong to the base make the whole
+/// literal invalid instead of starting a new token.
+fn lex_radix(chars: &mut Chars, start: usize, radix: u32) -> (Token, Span) {
+    let mut s = String::new();
+    let mut end = start + 2;
+    while let Some(&(i, c)) = chars.peek() {
+        if !(c.is_ascii_alphanumeric() || c == '_') {
+            break;
+        }
+        if c != '_' {
+            s.push(c);
+        }
+        end = i + c.len_utf8();
         chars.next();
     }
-    if isfloat {
-        (Token::Float(s.parse::<f64>().unwrap()), s.len())
-    } else {
-        (Token::Int(s.parse::<i64>().unwrap()), s.len())
+    let span = Span::new(start, end);
+    match BigInt::from_radix(&s, radix) {
+        Some(n) => (Token::Int(n), span),
+        None => (Token::Invalid, span),
     }
 }
 
+/// Read an operator starting with `<` or `>`: a comparison
+/// like `<` or `>=`, or a shift, `<<` or `>>`.
+fn lex_angle(chars: &mut Chars) -> (Token, Span) {
+    let (start, c) = chars.next().unwrap();
+    let tok = match (c, chars.peek().map(|&(_, next)| next)) {
+        ('<', Some('<')) => Token::ShiftLeft,
+        ('>', Some('>')) => Token::ShiftRight,
+        ('<', Some('=')) => Token::LessEqual,
+        ('>', Some('=')) => Token::GreaterEqual,
+        _ => return (Token::Op(c), Span::new(start, start + 1)),
+    };
+    chars.next();
+    (tok, Span::new(start, start + 2))
+}
+
+/// Read `!` or `=` and an `=` after it. `==` and `!=` are
+/// comparisons, while `!` alone is a factorial and `=` alone
+/// is an assignment.
+fn lex_equal(chars: &mut Chars) -> (Token, Span) {
+    let (start, c) = chars.next().unwrap();
+    if let Some(&(_, '=')) = chars.peek() {
+        chars.next();
+        let tok = match c {
+            '=' => Token::EqualEqual,
+            _ => Token::NotEqual,
+        };
+        return (tok, Span::new(start, start + 2));
+    }
+    let tok = match c {
+        '=' => Token::Equal,
+        _ => Token::Factorial,
+    };
+    (tok, Span::new(start, start + 1))
+}
+
+/// Read an identifier or keyword, a letter or underscore
+/// followed by any number of letters, digits or underscores.
+fn lex_word(chars: &mut Chars) -> String {
+    let mut s = String::new();
+    while let Some(&(_, c)) = chars.peek() {
+        if !(c.is_ascii_alphanumeric() || c == '_') {
+            break;
+        }
+        s.push(c);
+        chars.next();
+    }
+    s
+}
+
 impl<'a> fmt::Display for Lexer<'a> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if self.buf.len() == 0 {
+        if self.buf.is_empty() {
             write!(f, "Lexer{{..}}")
         } else {
-            write!(f, "Lexer{{{:?}}}", self.buf)
+            let toks: Vec<&Token> = self.buf.iter().map(|(t, _)| t).collect();
+            write!(f, "Lexer{{{:?}}}", toks)
         }
     }
 }
 
-impl<'a> Copy for Token {}
+impl Token {
+    /// An integer token.
+    pub fn int(n: i64) -> Self {
+        Token::Int(BigInt::from(n))
+    }
 
-impl<'a> Clone for Token {
-    fn clone(&self) -> Self {
-        *self
+    /// A decimal number token with the shortest digits for `f`.
+    pub fn float(f: f64) -> Self {
+        Token::Float(Decimal::from_f64(f).unwrap())
     }
 }
 
-impl<'a> ToString for Token {
-    fn to_string(&self) -> String {
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Token::OpenParen => String::from("("),
-            Token::CloseParen => String::from(")"),
-            Token::Modulus => String::from("mod"),
-            Token::Int(i) => format!("{}", i),
-            Token::Float(f) => format!("{}", f),
-            Token::Op(c) => format!("{}", c),
-            _ => String::new(),
+            Token::OpenParen => write!(f, "("),
+            Token::CloseParen => write!(f, ")"),
+            Token::OpenBracket => write!(f, "["),
+            Token::CloseBracket => write!(f, "]"),
+            Token::Arrow => write!(f, "->"),
+            Token::Range => write!(f, ".."),
+            Token::Modulus => write!(f, "mod"),
+            Token::Negation => write!(f, "~"),
+            Token::Xor => write!(f, "xor"),
+            Token::ShiftLeft => write!(f, "<<"),
+            Token::ShiftRight => write!(f, ">>"),
+            Token::RotateLeft => write!(f, "rol"),
+            Token::RotateRight => write!(f, "ror"),
+            Token::Of => write!(f, "of"),
+            Token::To => write!(f, "to"),
+            Token::In => write!(f, "in"),
+            Token::LessEqual => write!(f, "<="),
+            Token::GreaterEqual => write!(f, ">="),
+            Token::EqualEqual => write!(f, "=="),
+            Token::NotEqual => write!(f, "!="),
+            Token::And => write!(f, "and"),
+            Token::Or => write!(f, "or"),
+            Token::Not => write!(f, "not"),
+            Token::If => write!(f, "if"),
+            Token::Then => write!(f, "then"),
+            Token::Else => write!(f, "else"),
+            Token::Int(i) => write!(f, "{}", i),
+            Token::Float(n) => write!(f, "{}", n),
+            Token::Imaginary(n) => write!(f, "{}i", n),
+            Token::Op(c) => write!(f, "{}", c),
+            Token::Let => write!(f, "let"),
+            Token::Equal => write!(f, "="),
+            Token::Ident(name) | Token::Func(name) => write!(f, "{}", name),
+            Token::Assign => write!(f, "="),
+            Token::List | Token::Index => write!(f, "["),
+            Token::Call => write!(f, "("),
+            Token::Lambda => write!(f, "->"),
+            Token::Comma => write!(f, ","),
+            Token::Factorial => write!(f, "!"),
+            Token::End => write!(f, "end of input"),
+            Token::Invalid => write!(f, "invalid token"),
         }
     }
 }
@@ -479,35 +700,38 @@ impl Op {
             Op::Invalid => "<invalid>",
         }
     }
-
-    fn presidence() -> i8 {
-        0
-    }
 }
 
 #[cfg(test)]
 mod test {
     use super::{eat_spaces, lex, lex_num, Lexer, Token};
+    use crate::bigint::BigInt;
+    use crate::error::Span;
 
     #[test]
     fn test_lex_num() {
-        let mut ch = "123".chars().peekable();
+        let mut ch = "123".char_indices().peekable();
         let res = match lex_num(&mut ch) {
             (Token::Int(i), ..) => i,
             (Token::Invalid, ..) => panic!("should not get invalid token for \"123\""),
-            _ => 0,
+            _ => BigInt::zero(),
         };
-        assert_eq!(res, 123);
+        assert_eq!(res, BigInt::from(123));
+        let mut ch = "123456789012345678901234567890".char_indices().peekable();
+        assert_eq!(
+            lex_num(&mut ch).0.to_string(),
+            "123456789012345678901234567890"
+        );
     }
 
     #[test]
     fn test_eat_spaces() {
-        let mut chars = "    a".chars().peekable();
+        let mut chars = "    a".char_indices().peekable();
         match eat_spaces(&mut chars) {
             (Some(c), ..) => assert_eq!(c, 'a'),
             (None, ..) => panic!("expected 'a'"),
         }
-        let mut ch = "a".chars().peekable();
+        let mut ch = "a".char_indices().peekable();
         assert_eq!('a', eat_spaces(&mut ch).0.unwrap());
     }
 
@@ -534,14 +758,14 @@ mod test {
     fn test_lex() {
         let s = "5 + 335 * (1.5+1)";
         let expected = vec![
-            Token::Int(5),
+            Token::int(5),
             Token::Op('+'),
-            Token::Int(335),
+            Token::int(335),
             Token::Op('*'),
             Token::OpenParen,
-            Token::Float(1.5),
+            Token::float(1.5),
             Token::Op('+'),
-            Token::Int(1),
+            Token::int(1),
             Token::CloseParen,
         ];
         let res = lex(s);
@@ -570,16 +794,245 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_lex_complex() {
+        let expected = vec![
+            Token::OpenParen,
+            Token::int(3),
+            Token::Op('+'),
+            Token::Imaginary(4.0),
+            Token::CloseParen,
+            Token::Op('*'),
+            Token::Imaginary(0.5),
+            Token::Op('+'),
+            Token::int(5),
+            Token::Op('∠'),
+            Token::float(53.13),
+            Token::Op('°'),
+        ];
+        assert_eq!(lex("(3+4i) * 0.5j + 5∠53.13°"), expected);
+        assert_eq!(lex("2in"), vec![Token::int(2), Token::In]);
+    }
+
+    #[test]
+    fn test_lex_radix() {
+        for (src, n) in [
+            ("0xFF", 255),
+            ("0xff", 255),
+            ("0o755", 493),
+            ("0b1010_0101", 165),
+            ("1_000_000", 1_000_000),
+        ] {
+            assert_eq!(lex(src), vec![Token::int(n)], "{}", src);
+        }
+        assert_eq!(
+            lex("0B"),
+            vec![Token::int(0), Token::Ident(String::from("B"))]
+        );
+        assert_eq!(
+            lex("1_x"),
+            vec![Token::int(1), Token::Ident(String::from("_x"))]
+        );
+        let mut l = Lexer::new("0b102 + 1");
+        assert_eq!(l.peek(), Token::Invalid);
+        assert_eq!(l.peek_span(), Span::new(0, 5));
+    }
+
+    #[test]
+    fn test_lex_scientific() {
+        for (src, f) in [
+            ("6.022e23", 6.022e23),
+            ("1E-9", 1e-9),
+            ("1.5e+3", 1.5e3),
+            ("4.7k", 4.7e3),
+            ("100n", 100e-9),
+            ("2M", 2e6),
+            ("2.2µ", 2.2e-6),
+            ("1e3k", 1e6),
+        ] {
+            assert_eq!(lex(src), vec![Token::float(f)], "{}", src);
+        }
+        assert_eq!(lex("2.5e3i"), vec![Token::Imaginary(2500.)]);
+        // the digits are kept exactly, past what a float can hold
+        for src in ["1.00000000000000000001", "123456789012345678901.5", "1e400"] {
+            let toks = lex(src);
+            assert!(matches!(toks[..], [Token::Float(_)]), "{}", src);
+            assert_eq!(toks[0].to_string(), src);
+        }
+        for (src, word) in [("5eV", "eV"), ("2m", "m"), ("2MB", "MB")] {
+            assert_eq!(
+                lex(src),
+                vec![
+                    Token::int(src[..1].parse().unwrap()),
+                    Token::Ident(String::from(word))
+                ],
+                "{}",
+                src
+            );
+        }
+        for (src, end) in [
+            ("1.2.3", 5),
+            (".", 1),
+            ("1.2.3..5", 5),
+            ("1e", 2),
+            ("2E + 1", 2),
+            ("3e-", 3),
+        ] {
+            let mut l = Lexer::new(src);
+            assert_eq!(l.peek(), Token::Invalid, "{}", src);
+            assert_eq!(l.peek_span(), Span::new(0, end), "{}", src);
+        }
+    }
+
+    #[test]
+    fn test_suffixes() {
+        // a suffix stays part of the number whatever names are around it
+        assert_eq!(
+            Lexer::new("3n + 4.7k").as_vec(),
+            vec![Token::float(3e-9), Token::Op('+'), Token::float(4.7e3)]
+        );
+        let toks = Lexer::new("sum(k, 1, 3, 2k)").as_vec();
+        assert_eq!(
+            toks[toks.len() - 2..],
+            [Token::float(2e3), Token::CloseParen]
+        );
+    }
+
+    #[test]
+    fn test_lex_bitwise() {
+        let expected = vec![
+            Token::Negation,
+            Token::Ident(String::from("x")),
+            Token::Op('&'),
+            Token::int(1),
+            Token::ShiftLeft,
+            Token::int(2),
+            Token::Op('|'),
+            Token::int(3),
+            Token::ShiftRight,
+            Token::int(4),
+            Token::Xor,
+            Token::int(5),
+            Token::RotateLeft,
+            Token::int(6),
+        ];
+        assert_eq!(lex("~x & 1 << 2 | 3>>4 xor 5 rol 6"), expected);
+        assert_eq!(lex("¬1"), vec![Token::Negation, Token::int(1)]);
+    }
+
+    #[test]
+    fn test_lex_comparisons() {
+        let expected = vec![
+            Token::Op('<'),
+            Token::LessEqual,
+            Token::Op('>'),
+            Token::GreaterEqual,
+            Token::EqualEqual,
+            Token::NotEqual,
+            Token::Equal,
+            Token::Factorial,
+            Token::ShiftLeft,
+        ];
+        assert_eq!(lex("< <= > >= == != = ! <<"), expected);
+        assert_eq!(
+            lex("1≤2≠3≥4"),
+            vec![
+                Token::int(1),
+                Token::LessEqual,
+                Token::int(2),
+                Token::NotEqual,
+                Token::int(3),
+                Token::GreaterEqual,
+                Token::int(4),
+            ]
+        );
+        assert_eq!(
+            lex("3!=6"),
+            vec![Token::int(3), Token::NotEqual, Token::int(6)]
+        );
+        let expected = vec![
+            Token::If,
+            Token::Not,
+            Token::Ident(String::from("x")),
+            Token::And,
+            Token::Ident(String::from("y")),
+            Token::Or,
+            Token::Ident(String::from("z")),
+            Token::Then,
+            Token::int(1),
+            Token::Else,
+            Token::int(0),
+        ];
+        assert_eq!(lex("if not x and y or z then 1 else 0"), expected);
+    }
+
+    #[test]
+    fn test_lex_lambda() {
+        let expected = vec![
+            Token::Ident(String::from("map")),
+            Token::OpenParen,
+            Token::Ident(String::from("x")),
+            Token::Arrow,
+            Token::Ident(String::from("x")),
+            Token::Op('-'),
+            Token::int(1),
+            Token::Comma,
+            Token::OpenBracket,
+            Token::int(1),
+            Token::CloseBracket,
+            Token::CloseParen,
+        ];
+        assert_eq!(lex("map(x->x-1, [1])"), expected);
+        assert_eq!(lex("x → 2")[1], Token::Arrow);
+        assert_eq!(
+            lex("1..10"),
+            vec![Token::int(1), Token::Range, Token::int(10)]
+        );
+        assert_eq!(
+            lex("0.5..x"),
+            vec![
+                Token::float(0.5),
+                Token::Range,
+                Token::Ident(String::from("x"))
+            ]
+        );
+        assert_eq!(lex("1 - >2")[1], Token::Op('-'));
+    }
+
+    #[test]
+    fn test_lex_words() {
+        let expected = vec![
+            Token::Let,
+            Token::Ident(String::from("rate_2")),
+            Token::Equal,
+            Token::Ident(String::from("model")),
+            Token::Modulus,
+            Token::int(3),
+            Token::Op('%'),
+            Token::Of,
+            Token::Ident(String::from("X")),
+        ];
+        assert_eq!(lex("let rate_2 = model mod 3% of X"), expected);
+        let expected = vec![
+            Token::Ident(String::from("x")),
+            Token::In,
+            Token::Ident(String::from("cm")),
+            Token::To,
+            Token::Ident(String::from("into")),
+        ];
+        assert_eq!(lex("x in cm to into"), expected);
+    }
+
     #[test]
     fn test_lex_iter() {
         let mut l = Lexer::new("1+1");
         let p = l.peek().clone();
-        assert_eq!(p, Token::Int(1));
+        assert_eq!(p, Token::int(1));
         assert_eq!(l.look_ahead(0), p);
         assert_eq!(l.look_ahead(1), Token::Op('+'));
-        assert_eq!(l.look_ahead(2), Token::Int(1));
+        assert_eq!(l.look_ahead(2), Token::int(1));
         match l.peek().clone() {
-            Token::Int(n) => assert_eq!(n, 1),
+            Token::Int(n) => assert_eq!(n, BigInt::from(1)),
             _ => panic!("expected the number one"),
         }
         l.next();
@@ -589,13 +1042,14 @@ mod test {
         }
         l.next();
         match l.peek().clone() {
-            Token::Int(n) => assert_eq!(n, 1),
+            Token::Int(n) => assert_eq!(n, BigInt::from(1)),
             _ => panic!("expected number one"),
         }
         let l = Lexer::new("1 + 1 + 1");
         for t in l {
             match t {
-                Token::Int(1) | Token::Op('+') | Token::End => {}
+                Token::Int(n) if n == BigInt::from(1) => {}
+                Token::Op('+') | Token::End => {}
                 Token::Invalid => {
                     println!("invalid");
                     break;
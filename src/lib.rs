@@ -1,7 +1,38 @@
 mod ast;
+mod bigint;
+mod builtins;
+mod calculus;
+mod complex;
+mod decimal;
+mod env;
+mod error;
+mod function;
+mod integrate;
 mod lex;
+mod list;
+mod matrix;
 mod parser;
+mod radix;
+mod rational;
+mod series;
+mod simplify;
+mod solve;
 mod trie;
+mod units;
+mod value;
+
+pub use ast::Ast;
+pub use bigint::BigInt;
+pub use complex::Complex;
+pub use decimal::{Context, Decimal, Rounding};
+pub use env::{Env, IntType, Mode, Output, Policy};
+pub use error::{Error, EvalError, EvalErrorKind, Span};
+pub use function::Function;
+pub use lex::Token;
+pub use radix::Radix;
+pub use rational::Rational;
+pub use units::{Quantity, Unit};
+pub use value::Value;
 
 /// Execute the code given as a raw string.
 ///
@@ -12,13 +43,24 @@ mod trie;
 ///
 /// match exec("1 + 1") {
 ///     Ok(result) => assert_eq!(result, 2.0),
-///     Err(msg) => panic!(msg),
+///     Err(msg) => panic!("{}", msg),
 /// }
 /// ```
-pub fn exec(text: &str) -> Result<f64, String> {
-    let ast = match parser::parse(text) {
-        Ok(ast) => ast,
-        Err(msg) => return Err(msg),
-    };
-    Ok(ast::eval(&ast))
+pub fn exec(text: &str) -> Result<Value, Error> {
+    Env::new().exec(text)
+}
+
+/// Parse an expression and rewrite it into a simpler form, see
+/// [`Ast::simplify`].
+///
+/// # Examples
+///
+/// ```
+/// use calc::simplify;
+///
+/// let ast = simplify("x * 1 + 3 * x - 2 + 10% of x").unwrap();
+/// assert_eq!(ast.to_string(), "4.1 * x - 2");
+/// ```
+pub fn simplify(text: &str) -> Result<Ast, Error> {
+    Ok(text.parse::<Ast>()?.simplify())
 }
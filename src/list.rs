@@ -0,0 +1,84 @@
+use crate::decimal::Context;
+use crate::error::EvalErrorKind;
+use crate::function::arity;
+use crate::rational::Rational;
+use crate::value::Value;
+
+/// The most items a range like `1..10` can have.
+pub(crate) const MAX_RANGE: usize = 1_000_000;
+
+/// A builtin that takes lists as arguments.
+pub(crate) type ListFn = fn(&[Value], &Context) -> Result<Value, EvalErrorKind>;
+
+/// Find a builtin that takes lists as arguments.
+pub(crate) fn lookup(name: &str) -> Option<ListFn> {
+    match name {
+        "sum" => Some(sum),
+        "len" => Some(len),
+        _ => None,
+    }
+}
+
+/// The items of a list, or an error if `val` isn't one.
+pub(crate) fn items(val: &Value) -> Result<&[Value], EvalErrorKind> {
+    match val {
+        Value::List(items) => Ok(items),
+        val => Err(EvalErrorKind::Type(format!(
+            "expected a list but got {}",
+            val
+        ))),
+    }
+}
+
+/// The list `start, start + 1, ...` up to and including `end`.
+pub(crate) fn range(start: &Value, end: &Value, ctx: &Context) -> Result<Value, EvalErrorKind> {
+    let describe = || format!("{}..{}", start, end);
+    let (a, b) = match (start.as_f64(), end.as_f64()) {
+        (Some(a), Some(b)) => (a, b),
+        _ => {
+            return Err(EvalErrorKind::Type(format!(
+                "{} is not a range of real numbers",
+                describe()
+            )))
+        }
+    };
+    let len = (b - a).floor() + 1.0;
+    if len.is_nan() || len > MAX_RANGE as f64 {
+        return Err(EvalErrorKind::Overflow(describe()));
+    }
+    let one = Value::Rational(Rational::from_int(1));
+    let mut items = Vec::with_capacity(len.max(0.0) as usize);
+    let mut x = start.clone();
+    for _ in 0..len.max(0.0) as usize {
+        let next = x.add(&one, ctx)?;
+        items.push(x);
+        x = next;
+    }
+    Ok(Value::List(items))
+}
+
+/// `sum(xs)`, the total of the items of `xs`.
+fn sum(args: &[Value], ctx: &Context) -> Result<Value, EvalErrorKind> {
+    let [xs] = args else {
+        return Err(arity("sum", 1, args.len()));
+    };
+    let mut total = Value::Rational(Rational::from_int(0));
+    for x in items(xs)? {
+        if !x.is_number() {
+            return Err(EvalErrorKind::Type(format!(
+                "expected a number but got {}",
+                x
+            )));
+        }
+        total = total.add(x, ctx)?;
+    }
+    Ok(total)
+}
+
+/// `len(xs)`, the number of items in `xs`.
+fn len(args: &[Value], _: &Context) -> Result<Value, EvalErrorKind> {
+    let [xs] = args else {
+        return Err(arity("len", 1, args.len()));
+    };
+    Ok(Value::Rational(Rational::from_int(items(xs)?.len() as i64)))
+}
@@ -1,38 +1,148 @@
 use std::env;
-use std::io::{self, Error, ErrorKind, Write};
+use std::fs;
+use std::io::{self, Error, Write};
+use std::process;
 
-use calc::exec;
+use calc::{Ast, Env, Mode, Output, Rounding};
 
 fn interpreter() -> Result<(), Error> {
     let stdin = io::stdin();
     let mut stdout = io::stdout();
+    let mut env = Env::new();
 
     let mut s = String::new();
     loop {
         print!(">>> ");
 
         stdout.flush()?;
-        stdin.read_line(&mut s)?;
+        if stdin.read_line(&mut s)? == 0 {
+            return Ok(());
+        }
 
-        if s.as_bytes()[0] as char == 'q' || s == "q" || s == "quit" || s == "exit" {
+        let line = s.trim();
+        if line == "q" || line == "quit" || line == "exit" {
             return Ok(());
         }
-        match exec(s.as_str()) {
-            Ok(res) => println!("{}", res),
-            Err(msg) => println!("Error: {}", msg),
+        if let Some(cmd) = line.strip_prefix(':') {
+            if let Err(msg) = command(&mut env, cmd) {
+                println!("Error: {}", msg);
+            }
+            s.clear();
+            continue;
+        }
+        match env.exec(line) {
+            Ok(res) => println!("{}", env.show(&res)),
+            Err(err) => println!("{}", err.render(line)),
         }
         s.clear()
     }
 }
 
+/// Run a meta command, the part of a line after a leading ':'.
+fn command(env: &mut Env, cmd: &str) -> Result<(), String> {
+    let words: Vec<&str> = cmd.split_whitespace().collect();
+    match words.as_slice() {
+        ["mode", "exact"] => env.set_mode(Mode::Exact),
+        ["mode", "decimal"] => env.set_mode(Mode::Decimal),
+        ["mode", "float"] => env.set_mode(Mode::Float),
+        ["mode"] => println!("{:?}", env.mode()),
+        ["output", "exact"] => env.set_output(Output::Exact),
+        ["output", "decimal"] => env.set_output(Output::Decimal),
+        ["output"] => println!("{:?}", env.output()),
+        ["precision", n] => match n.parse::<usize>() {
+            Ok(n) if n > 0 => env.set_precision(n),
+            _ => return Err(format!("invalid precision '{}'", n)),
+        },
+        ["precision"] => println!("{}", env.context().precision),
+        ["rounding", "half-even"] => env.set_rounding(Rounding::HalfEven),
+        ["rounding", "half-up"] => env.set_rounding(Rounding::HalfUp),
+        ["rounding", "toward-zero"] => env.set_rounding(Rounding::TowardZero),
+        ["rounding"] => println!("{:?}", env.context().rounding),
+        ["base", n] => match n.parse::<u32>() {
+            Ok(n) => env.set_base(n)?,
+            Err(_) => return Err(format!("unsupported base '{}'", n)),
+        },
+        ["base"] => println!("{}", env.base()),
+        ["width", n] => match n.parse::<u32>() {
+            Ok(n) => env.set_width(n)?,
+            Err(_) => return Err(format!("unsupported width '{}'", n)),
+        },
+        ["width"] => println!("{}", env.width()),
+        ["int", "off"] => env.set_int(None),
+        ["int", ty] => env.set_int(Some(ty.parse()?)),
+        ["int"] => match env.int() {
+            Some(int) => println!("{}", int),
+            None => println!("off"),
+        },
+        ["depth", n] => match n.parse::<usize>() {
+            Ok(n) if (1..=Env::MAX_DEPTH).contains(&n) => env.set_max_depth(n),
+            _ => return Err(format!("invalid depth '{}'", n)),
+        },
+        ["depth"] => println!("{}", env.max_depth()),
+        ["functions"] => {
+            for func in env.functions() {
+                println!("{}", func);
+            }
+        }
+        ["export", path] => fs::write(path, env.export()).map_err(|e| e.to_string())?,
+        ["load", path] => load(env, path)?,
+        ["simplify", _, ..] => {
+            let expr = cmd.trim_start()["simplify".len()..].trim();
+            match expr.parse::<Ast>() {
+                Ok(ast) => println!("{}", ast.simplify()),
+                Err(err) => println!("{}", err.render(expr)),
+            }
+        }
+        ["mode", ..] => return Err(String::from("usage: :mode [exact|decimal|float]")),
+        ["output", ..] => return Err(String::from("usage: :output [exact|decimal]")),
+        ["precision", ..] => return Err(String::from("usage: :precision [digits]")),
+        ["rounding", ..] => {
+            return Err(String::from(
+                "usage: :rounding [half-even|half-up|toward-zero]",
+            ))
+        }
+        ["base", ..] => return Err(String::from("usage: :base [2|8|10|16]")),
+        ["width", ..] => return Err(String::from("usage: :width [bits]")),
+        ["int", ..] => return Err(String::from("usage: :int [u8|i32|u64|...|off]")),
+        ["depth", ..] => return Err(String::from("usage: :depth [calls]")),
+        ["export", ..] => return Err(String::from("usage: :export file")),
+        ["load", ..] => return Err(String::from("usage: :load file")),
+        ["simplify"] => return Err(String::from("usage: :simplify expression")),
+        _ => return Err(format!("unknown command ':{}'", cmd)),
+    }
+    Ok(())
+}
+
+/// Run every line of a file, like one written by `:export`.
+fn load(env: &mut Env, path: &str) -> Result<(), String> {
+    let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Err(err) = env.exec(line) {
+            return Err(format!("{}:{}: {}", path, i + 1, err));
+        }
+    }
+    Ok(())
+}
+
 fn main() -> Result<(), Error> {
-    let args = env::args();
+    let mut args = env::args();
     if args.len() == 1 {
         return interpreter();
     }
-    let exp = args.last().unwrap();
-    match exec(exp.as_str()) {
-        Ok(res) => Ok(println!("{}", res)),
-        Err(msg) => Err(Error::new(ErrorKind::Other, msg)),
+    let exp = args.next_back().unwrap();
+    let mut env = Env::new();
+    match env.exec(exp.as_str()) {
+        Ok(res) => {
+            println!("{}", env.show(&res));
+            Ok(())
+        }
+        Err(err) => {
+            eprintln!("{}", err.render(&exp));
+            process::exit(1)
+        }
     }
 }
@@ -0,0 +1,303 @@
+use crate::decimal::Context;
+use crate::error::EvalErrorKind;
+use crate::function::arity;
+use crate::list::{self, ListFn};
+use crate::rational::Rational;
+use crate::value::Value;
+
+/// The largest matrix `identity` makes.
+const MAX_SIZE: i64 = 1000;
+
+/// Find a builtin that works on matrices, which are lists of rows
+/// that all have the same length, like `[[1, 2], [3, 4]]`.
+pub(crate) fn lookup(name: &str) -> Option<ListFn> {
+    match name {
+        "transpose" => Some(transpose),
+        "det" => Some(det),
+        "inv" => Some(inv),
+        "identity" => Some(identity),
+        "linsolve" => Some(linsolve),
+        _ => None,
+    }
+}
+
+/// A list of numbers.
+fn vector(val: &Value) -> Result<&[Value], EvalErrorKind> {
+    let items = list::items(val)?;
+    match items.iter().find(|x| !x.is_number()) {
+        Some(x) => Err(EvalErrorKind::Type(format!(
+            "expected a number but got {}",
+            x
+        ))),
+        None => Ok(items),
+    }
+}
+
+/// Whether a list is a list of numbers rather than a matrix.
+fn is_vector(val: &Value) -> Result<bool, EvalErrorKind> {
+    Ok(!matches!(list::items(val)?.first(), Some(Value::List(_))))
+}
+
+/// The rows of a matrix.
+fn matrix(val: &Value) -> Result<Vec<&[Value]>, EvalErrorKind> {
+    let not_matrix = || EvalErrorKind::Type(format!("expected a matrix but got {}", val));
+    let rows = list::items(val).map_err(|_| not_matrix())?;
+    if rows.is_empty() || rows.iter().any(|row| !matches!(row, Value::List(_))) {
+        return Err(not_matrix());
+    }
+    let rows = rows.iter().map(vector).collect::<Result<Vec<_>, _>>()?;
+    let cols = rows[0].len();
+    if cols == 0 || rows.iter().any(|row| row.len() != cols) {
+        return Err(EvalErrorKind::Shape(format!(
+            "the rows of {} have different lengths",
+            val
+        )));
+    }
+    Ok(rows)
+}
+
+/// The rows of a square matrix.
+fn square(val: &Value) -> Result<Vec<&[Value]>, EvalErrorKind> {
+    let rows = matrix(val)?;
+    if rows.len() != rows[0].len() {
+        return Err(EvalErrorKind::Shape(format!(
+            "{} is not square",
+            shape(&rows)
+        )));
+    }
+    Ok(rows)
+}
+
+/// Describe the size of a matrix for an error message.
+fn shape(rows: &[&[Value]]) -> String {
+    format!("a {}x{} matrix", rows.len(), rows[0].len())
+}
+
+fn to_value(rows: Vec<Vec<Value>>) -> Value {
+    Value::List(rows.into_iter().map(Value::List).collect())
+}
+
+fn zero() -> Value {
+    Value::Rational(Rational::from_int(0))
+}
+
+fn one() -> Value {
+    Value::Rational(Rational::from_int(1))
+}
+
+/// The sum of `xs[i] * ys[i]`.
+fn dot<'a>(
+    xs: impl Iterator<Item = &'a Value>,
+    ys: impl Iterator<Item = &'a Value>,
+    ctx: &Context,
+) -> Result<Value, EvalErrorKind> {
+    let mut total = zero();
+    for (x, y) in xs.zip(ys) {
+        total = total.add(&x.mul(y, ctx)?, ctx)?;
+    }
+    Ok(total)
+}
+
+/// `a @ b`, the matrix product. A list of numbers on the left is used
+/// as a row and one on the right as a column, so two of them give
+/// their dot product.
+pub(crate) fn matmul(a: &Value, b: &Value, ctx: &Context) -> Result<Value, EvalErrorKind> {
+    let mismatch = |a: String, b: String| {
+        Err(EvalErrorKind::Shape(format!(
+            "cannot multiply {} and {}",
+            a, b
+        )))
+    };
+    let length = |xs: &[Value]| format!("a list of length {}", xs.len());
+    match (is_vector(a)?, is_vector(b)?) {
+        (true, true) => {
+            let (xs, ys) = (vector(a)?, vector(b)?);
+            if xs.len() != ys.len() {
+                return mismatch(length(xs), length(ys));
+            }
+            dot(xs.iter(), ys.iter(), ctx)
+        }
+        (false, true) => {
+            let (rows, ys) = (matrix(a)?, vector(b)?);
+            if rows[0].len() != ys.len() {
+                return mismatch(shape(&rows), length(ys));
+            }
+            let res = rows.iter().map(|row| dot(row.iter(), ys.iter(), ctx));
+            res.collect::<Result<_, _>>().map(Value::List)
+        }
+        (true, false) => {
+            let (xs, cols) = (vector(a)?, matrix(b)?);
+            if xs.len() != cols.len() {
+                return mismatch(length(xs), shape(&cols));
+            }
+            let res = (0..cols[0].len()).map(|j| dot(xs.iter(), cols.iter().map(|c| &c[j]), ctx));
+            res.collect::<Result<_, _>>().map(Value::List)
+        }
+        (false, false) => {
+            let (a, b) = (matrix(a)?, matrix(b)?);
+            if a[0].len() != b.len() {
+                return mismatch(shape(&a), shape(&b));
+            }
+            let mut res = Vec::with_capacity(a.len());
+            for row in &a {
+                let cols = (0..b[0].len()).map(|j| dot(row.iter(), b.iter().map(|r| &r[j]), ctx));
+                res.push(cols.collect::<Result<_, _>>()?);
+            }
+            Ok(to_value(res))
+        }
+    }
+}
+
+/// The size of a number, for picking pivots.
+fn size(x: &Value) -> f64 {
+    x.magnitude().as_complex().map_or(0.0, |z| z.abs())
+}
+
+/// Reduce the square matrix at the left of `m` to the identity with
+/// row operations, which are applied to any columns on its right as
+/// well. Gives the determinant of the square part, or `None` when it's
+/// singular.
+fn reduce(m: &mut [Vec<Value>], ctx: &Context) -> Result<Option<Value>, EvalErrorKind> {
+    let n = m.len();
+    // float entries that are this small compared to the matrix are
+    // rounding errors from cancelling, and count as zero
+    let largest = m
+        .iter()
+        .flat_map(|row| &row[..n])
+        .map(size)
+        .fold(0.0, f64::max);
+    let tolerance = largest * n as f64 * f64::EPSILON;
+    let mut det = one();
+    for col in 0..n {
+        let best = (col..n).max_by(|&i, &j| size(&m[i][col]).total_cmp(&size(&m[j][col])));
+        let pivot = match best {
+            Some(i) if !m[i][col].is_zero() => i,
+            _ => return Ok(None),
+        };
+        if let Value::Float(_) = m[pivot][col] {
+            if size(&m[pivot][col]) <= tolerance {
+                return Ok(None);
+            }
+        }
+        if pivot != col {
+            m.swap(pivot, col);
+            det = det.neg();
+        }
+        let p = m[col][col].clone();
+        det = det.mul(&p, ctx)?;
+        for x in &mut m[col] {
+            *x = x.div(&p, ctx)?;
+        }
+        let pivot_row = m[col].clone();
+        for (i, row) in m.iter_mut().enumerate() {
+            let factor = row[col].clone();
+            if i == col || factor.is_zero() {
+                continue;
+            }
+            for (x, p) in row.iter_mut().zip(&pivot_row) {
+                *x = x.sub(&factor.mul(p, ctx)?, ctx)?;
+            }
+        }
+    }
+    Ok(Some(det))
+}
+
+/// `transpose(a)`, which swaps the rows and columns of `a`.
+fn transpose(args: &[Value], _: &Context) -> Result<Value, EvalErrorKind> {
+    let [a] = args else {
+        return Err(arity("transpose", 1, args.len()));
+    };
+    let rows = matrix(a)?;
+    let cols = (0..rows[0].len()).map(|j| rows.iter().map(|row| row[j].clone()).collect());
+    Ok(to_value(cols.collect()))
+}
+
+/// `det(a)`, the determinant of a square matrix.
+fn det(args: &[Value], ctx: &Context) -> Result<Value, EvalErrorKind> {
+    let [a] = args else {
+        return Err(arity("det", 1, args.len()));
+    };
+    let mut m: Vec<Vec<Value>> = square(a)?.iter().map(|row| row.to_vec()).collect();
+    Ok(reduce(&mut m, ctx)?.unwrap_or_else(zero))
+}
+
+/// `inv(a)`, the inverse of a square matrix.
+fn inv(args: &[Value], ctx: &Context) -> Result<Value, EvalErrorKind> {
+    let [a] = args else {
+        return Err(arity("inv", 1, args.len()));
+    };
+    let rows = square(a)?;
+    let n = rows.len();
+    let mut m: Vec<Vec<Value>> = rows
+        .iter()
+        .enumerate()
+        .map(|(i, row)| {
+            let unit = (0..n).map(|j| if i == j { one() } else { zero() });
+            row.iter().cloned().chain(unit).collect()
+        })
+        .collect();
+    if reduce(&mut m, ctx)?.is_none() {
+        return Err(EvalErrorKind::Singular);
+    }
+    Ok(to_value(
+        m.into_iter().map(|row| row[n..].to_vec()).collect(),
+    ))
+}
+
+/// `identity(n)`, the n by n identity matrix.
+fn identity(args: &[Value], _: &Context) -> Result<Value, EvalErrorKind> {
+    let [n] = args else {
+        return Err(arity("identity", 1, args.len()));
+    };
+    if !n.is_number() {
+        return Err(EvalErrorKind::Type(format!(
+            "expected a number but got {}",
+            n
+        )));
+    }
+    let size = match n.integer()?.to_i64() {
+        Some(size) if (1..=MAX_SIZE).contains(&size) => size as usize,
+        _ => return Err(EvalErrorKind::Overflow(format!("identity({})", n))),
+    };
+    let rows = (0..size).map(|i| (0..size).map(move |j| if i == j { one() } else { zero() }));
+    Ok(to_value(rows.map(Iterator::collect).collect()))
+}
+
+/// `linsolve(a, b)`, the `x` with `a @ x == b` for a square matrix `a`,
+/// where `b` is a list of numbers or a matrix with as many rows as `a`.
+fn linsolve(args: &[Value], ctx: &Context) -> Result<Value, EvalErrorKind> {
+    let [a, b] = args else {
+        return Err(arity("linsolve", 2, args.len()));
+    };
+    let rows = square(a)?;
+    let n = rows.len();
+    let column = is_vector(b)?;
+    let rhs: Vec<Vec<Value>> = match column {
+        true => vector(b)?.iter().map(|x| vec![x.clone()]).collect(),
+        false => matrix(b)?.iter().map(|row| row.to_vec()).collect(),
+    };
+    if rhs.len() != n {
+        let rhs = match column {
+            true => format!("a list of length {}", rhs.len()),
+            false => format!("a {}x{} matrix", rhs.len(), rhs[0].len()),
+        };
+        return Err(EvalErrorKind::Shape(format!(
+            "cannot solve with {} and {}",
+            shape(&rows),
+            rhs
+        )));
+    }
+    let mut m: Vec<Vec<Value>> = rows
+        .iter()
+        .zip(rhs)
+        .map(|(row, rhs)| row.iter().cloned().chain(rhs).collect())
+        .collect();
+    if reduce(&mut m, ctx)?.is_none() {
+        return Err(EvalErrorKind::Singular);
+    }
+    let x = m.into_iter().map(|row| row[n..].to_vec());
+    Ok(match column {
+        true => Value::List(x.map(|mut row| row.remove(0)).collect()),
+        false => to_value(x.collect()),
+    })
+}
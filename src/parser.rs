@@ -1,148 +1,717 @@
 #![allow(dead_code)]
 
 use crate::ast::Ast;
+use crate::builtins;
+use crate::error::Error;
 use crate::lex::{
     Lexer, Token,
-    Token::{CloseParen, Float, Int, Invalid, Modulus, Op, OpenParen},
+    Token::{CloseParen, Comma, Float, Func, Ident, Imaginary, Int, Invalid, Op, OpenParen},
 };
+use crate::list;
+use crate::matrix;
+use crate::units;
 
-type AstRes = Result<Ast, String>;
+type AstRes = Result<Ast, Error>;
 
 /// Parse a raw string and return the abstract syntax tree.
 pub fn parse(text: &str) -> AstRes {
     let mut l = Lexer::new(text);
-    let result = expr(&mut l);
-    result
+    let ast = match is_assignment(&mut l) {
+        true => assignment(&mut l)?,
+        false => expr(&mut l)?,
+    };
+    match l.peek() {
+        Token::End => Ok(ast),
+        _ => Err(unexpected(&mut l)),
+    }
+}
+
+/// Build an error for the next token, which the
+/// parser did not expect to see.
+fn unexpected(toks: &mut Lexer) -> Error {
+    let span = toks.peek_span();
+    match toks.peek() {
+        Token::End => Error::parse("unexpected end of input", span),
+        Invalid => {
+            let text = &toks.raw()[span.start..span.end];
+            match text.starts_with(|c: char| c.is_ascii_digit() || c == '.') {
+                true => Error::lex(format!("invalid number '{}'", text), span),
+                false => Error::lex(format!("invalid character '{}'", text), span),
+            }
+        }
+        tok => Error::parse(format!("unexpected token '{}'", tok), span),
+    }
 }
 
 /*
- * < assignment > ::= 'let' < var > '=' < expression >
+ * < assignment > ::= 'let' < target > '=' < expression > |
+ *                    < target > '=' < expression > |
+ *                    < expression > '=' < expression >
+ *
+ * < target > ::= < var > | < var > (< params >) | < var > ()
+ *
+ * < params > ::= < var > , < params > | < var >
+ *
+ * < expression > ::= < prefix-op > < expression > |
+ *                    < expression > < infix-op > < expression > |
+ *                    < expression > < postfix-op > |
+ *                    < primary >
+ *
+ * < primary > ::= (< expression >) |
+ *                 [< args >] | [] |
+ *                 < var > -> < expression > |
+ *                 (< params >) -> < expression > |
+ *                 'if' < expression > 'then' < expression > 'else' < expression > |
+ *                 < func > (< args >) |
+ *                 < quantity > |
+ *                 < var >
+ *
+ * < quantity > ::= < number > < units > < quantity > |
+ *                  < number > < units >
+ *
+ * A number, unit, call, parenthesized group or a power of one of them
+ * followed by a variable, call or opening parenthesis is an implicit
+ * product, like `2x`, which binds
+ * tighter than `*` and `/` but looser than `^`: `1/2x` is `1 / (2 * x)`.
  *
- * < expression > ::= < term > + < expression > |
- *                    < term > - < expression > |
- *                    < term >
+ * < number > ::= < float > | < imaginary > | < int >
  *
- * < term > ::= < factor > *   < term > |
- *              < factor > /   < term > |
- *              < factor > ^   < term > |
- *              < factor > mod < term > |
- *              < factor >
+ * < units > ::= < unit > < units > |
+ *               < unit > ^ < expression > < units > |
+ *               ''
  *
- * < factor > ::= (< expression >) |
- *                < float > |
- *                < int >   |
- *                < var >
+ * < unit > ::= < var > | 'in'
+ *
+ * < args > ::= < expression > , < args > |
+ *              < expression >
+ *
+ * Precedence and associativity of the operators are
+ * given by the OPERATORS table.
  */
 
-fn expr(toks: &mut Lexer) -> AstRes {
-    let head = match term(toks) {
-        Ok(ast) if toks.is_empty() => return Ok(ast),
-        Ok(ast) => ast,
-        Err(msg) => return Err(msg),
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Fixity {
+    Prefix,
+    Infix,
+    Postfix,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Assoc {
+    Left,
+    Right,
+}
+
+/// An operator known to the parser. Operators with a higher
+/// binding power are grouped before operators with a lower one.
+pub(crate) struct Operator {
+    pub(crate) tok: Token,
+    pub(crate) fixity: Fixity,
+    pub(crate) bp: u8,
+    pub(crate) assoc: Assoc,
+}
+
+impl Operator {
+    const fn prefix(tok: Token, bp: u8) -> Self {
+        Self {
+            tok,
+            fixity: Fixity::Prefix,
+            bp,
+            assoc: Assoc::Right,
+        }
+    }
+
+    const fn infix(tok: Token, bp: u8, assoc: Assoc) -> Self {
+        Self {
+            tok,
+            fixity: Fixity::Infix,
+            bp,
+            assoc,
+        }
+    }
+
+    const fn postfix(tok: Token, bp: u8) -> Self {
+        Self {
+            tok,
+            fixity: Fixity::Postfix,
+            bp,
+            assoc: Assoc::Left,
+        }
+    }
+
+    /// The binding power used when parsing the right hand side.
+    fn right_bp(&self) -> u8 {
+        match self.assoc {
+            Assoc::Left => self.bp + 1,
+            Assoc::Right => self.bp,
+        }
+    }
+}
+
+// the equal sign of an equation splits it in two
+const EQUATION: u8 = 1;
+const CONVERT: u8 = 2;
+const OR: u8 = 3;
+const AND: u8 = 4;
+const NOT: u8 = 5;
+// comparisons bind looser than the bitwise operators, so
+// `x & 1 == 0` is `(x & 1) == 0`, unlike in C
+const COMPARE: u8 = 6;
+const RANGE: u8 = 7;
+// the bitwise operators are ordered like they are in C
+const BIT_OR: u8 = 8;
+const BIT_XOR: u8 = 9;
+const BIT_AND: u8 = 10;
+const SHIFT: u8 = 11;
+const SUM: u8 = 12;
+const PRODUCT: u8 = 20;
+const PERCENT_OF: u8 = 25;
+const ANGLE: u8 = 26;
+const NEGATE: u8 = 27;
+// "2x" and "3(4 + 5)", so "1/2x" is "1 / (2 * x)" and "2x^2" is "2 * x^2"
+const IMPLICIT: u8 = 28;
+const POWER: u8 = 30;
+const POSTFIX: u8 = 40;
+
+/// The operator table. New operators are added by registering
+/// them here and giving them a rule in `ast::eval`.
+const OPERATORS: &[Operator] = &[
+    // "x^2 = 4", an equation to solve when it isn't an assignment
+    Operator::infix(Token::Equal, EQUATION, Assoc::Left),
+    // "60 mph to km/h", converting everything on the left
+    Operator::infix(Token::To, CONVERT, Assoc::Left),
+    Operator::infix(Token::In, CONVERT, Assoc::Left),
+    Operator::infix(Token::Or, OR, Assoc::Left),
+    Operator::infix(Token::And, AND, Assoc::Left),
+    // "not x > 0" negates the whole comparison
+    Operator::prefix(Token::Not, NOT),
+    Operator::infix(Op('<'), COMPARE, Assoc::Left),
+    Operator::infix(Token::LessEqual, COMPARE, Assoc::Left),
+    Operator::infix(Op('>'), COMPARE, Assoc::Left),
+    Operator::infix(Token::GreaterEqual, COMPARE, Assoc::Left),
+    Operator::infix(Token::EqualEqual, COMPARE, Assoc::Left),
+    Operator::infix(Token::NotEqual, COMPARE, Assoc::Left),
+    // "1..n+1", the range of everything on either side
+    Operator::infix(Token::Range, RANGE, Assoc::Left),
+    Operator::infix(Op('|'), BIT_OR, Assoc::Left),
+    Operator::infix(Token::Xor, BIT_XOR, Assoc::Left),
+    Operator::infix(Op('&'), BIT_AND, Assoc::Left),
+    Operator::infix(Token::ShiftLeft, SHIFT, Assoc::Left),
+    Operator::infix(Token::ShiftRight, SHIFT, Assoc::Left),
+    Operator::infix(Token::RotateLeft, SHIFT, Assoc::Left),
+    Operator::infix(Token::RotateRight, SHIFT, Assoc::Left),
+    Operator::infix(Op('+'), SUM, Assoc::Left),
+    Operator::infix(Op('-'), SUM, Assoc::Left),
+    Operator::infix(Op('*'), PRODUCT, Assoc::Left),
+    Operator::infix(Op('/'), PRODUCT, Assoc::Left),
+    Operator::infix(Op('@'), PRODUCT, Assoc::Left),
+    Operator::infix(Token::Modulus, PRODUCT, Assoc::Left),
+    // "12% of 55", the only operator spelled with two tokens
+    Operator::infix(Token::Of, PERCENT_OF, Assoc::Left),
+    // polar form "5∠53.13°"
+    Operator::infix(Op('∠'), ANGLE, Assoc::Left),
+    Operator::prefix(Op('-'), NEGATE),
+    Operator::prefix(Token::Negation, NEGATE),
+    Operator::infix(Op('^'), POWER, Assoc::Right),
+    Operator::postfix(Token::Factorial, POSTFIX),
+    Operator::postfix(Op('%'), POSTFIX),
+    Operator::postfix(Op('°'), POSTFIX),
+    // "xs[0]", the index is parsed up to the closing bracket
+    Operator::postfix(Token::OpenBracket, POSTFIX),
+];
+
+/// The product of things written next to each other without a sign.
+/// It has no token of its own and isn't in the table.
+static IMPLICIT_PRODUCT: Operator = Operator::infix(Op('*'), IMPLICIT, Assoc::Left);
+
+fn find_operator(tok: &Token, fixity: Fixity) -> Option<&'static Operator> {
+    OPERATORS
+        .iter()
+        .find(|op| op.fixity == fixity && op.tok == *tok)
+}
+
+/// The operator a node with `operands` children is labeled with, if
+/// the node is one. A `%` with two operands is "12% of 55".
+pub(crate) fn operator(tok: &Token, operands: usize) -> Option<&'static Operator> {
+    match (tok, operands) {
+        (Op('%'), 2) => find_operator(&Token::Of, Fixity::Infix),
+        (Token::Index, 2) => find_operator(&Token::OpenBracket, Fixity::Postfix),
+        (tok, 1) => {
+            find_operator(tok, Fixity::Prefix).or_else(|| find_operator(tok, Fixity::Postfix))
+        }
+        (tok, 2) => find_operator(tok, Fixity::Infix),
+        _ => None,
+    }
+}
+
+/// Find the infix or postfix operator at the front of the token stream,
+/// which is an implicit product when there's none but one can go there.
+fn next_operator(lhs: &Ast, toks: &mut Lexer) -> Option<&'static Operator> {
+    let tok = toks.peek();
+    if tok == Op('%') && toks.look_ahead(1) == Token::Of {
+        return find_operator(&Token::Of, Fixity::Infix);
+    }
+    find_operator(&tok, Fixity::Infix)
+        .or_else(|| find_operator(&tok, Fixity::Postfix))
+        .or_else(|| is_juxtaposed(lhs, toks).then_some(&IMPLICIT_PRODUCT))
+}
+
+/// Whether the next token is multiplied by `lhs` without a sign in
+/// between: a variable, a call or a parenthesized group that comes
+/// right after a number, a unit, a call, a parenthesized group or a
+/// power of one of them, like in `2x`, `2 sin(x)`, `3(4 + 5)`, `(a)(b)`
+/// and `2^3x`. Two names in a row, like `x y` or `x^2 y`, aren't a product.
+fn is_juxtaposed(lhs: &Ast, toks: &mut Lexer) -> bool {
+    matches!(toks.peek(), Ident(_) | OpenParen) && is_multiplier(lhs, toks)
+}
+
+/// Whether `lhs` can be multiplied by what comes right after it.
+fn is_multiplier(lhs: &Ast, toks: &Lexer) -> bool {
+    if is_group(lhs, toks) {
+        return true;
+    }
+    match (&lhs.tok, lhs.children.as_slice()) {
+        (Int(_) | Float(_) | Imaginary(_), []) => true,
+        (Ident(name), []) => units::lookup(name).is_some(),
+        (Func(_) | Token::Call, _) => true,
+        (Op('^'), [base, _]) => is_multiplier(base, toks),
+        // a number with its units, like `2 m`
+        (Op('*'), [num, unit]) if is_unit(unit) => is_multiplier(num, toks),
+        _ => false,
+    }
+}
+
+/// Whether `ast` is a unit or the power of one, like `m^2`.
+fn is_unit(ast: &Ast) -> bool {
+    match (&ast.tok, ast.children.as_slice()) {
+        (Ident(name), []) => units::lookup(name).is_some(),
+        (Op('^'), [base, _]) => is_unit(base),
+        _ => false,
+    }
+}
+
+/// Whether `ast` was written in parentheses, like `(a + 1)`.
+fn is_group(ast: &Ast, toks: &Lexer) -> bool {
+    let text = toks.raw().get(ast.span.start..ast.span.end).unwrap_or("");
+    let mut depth = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ => (),
+        }
+        if depth == 0 {
+            // the parenthesis that opens it has to close at the end
+            return i > 0 && i + 1 == text.len();
+        }
+    }
+    false
+}
+
+/// Whether `lhs` can give back a function, so an opening parenthesis
+/// after it is a call like `adder(3)(4)` or `(x -> x^2)(3)` instead of
+/// a product. Builtins like `sqrt(4)` only give back numbers.
+fn is_callee(lhs: &Ast, toks: &Lexer) -> bool {
+    match &lhs.tok {
+        Func(name) => {
+            builtins::lookup(name).is_none()
+                && list::lookup(name).is_none()
+                && matrix::lookup(name).is_none()
+        }
+        Token::Call => true,
+        // only a lambda in parentheses, since the body of one
+        // without them goes as far to the right as it can
+        Token::Lambda => *toks.previous() == CloseParen,
+        _ => false,
+    }
+}
+
+/// Whether the text is an assignment like `x = 1`, or a function
+/// definition like `f(x, y) = x*y`, with or without 'let'.
+fn is_assignment(toks: &mut Lexer) -> bool {
+    match (toks.peek(), toks.look_ahead(1)) {
+        (Token::Let, _) | (Ident(_), Token::Equal) => return true,
+        (Ident(_), OpenParen) => (),
+        _ => return false,
+    }
+    match skip_params(toks, 2) {
+        Some(end) => toks.look_ahead(end) == Token::Equal,
+        None => false,
+    }
+}
+
+/// Whether the tokens from the `start`th one on look like the
+/// parameters of a function after the opening parenthesis, giving
+/// back the position of the token after the closing one.
+fn skip_params(toks: &mut Lexer, start: usize) -> Option<usize> {
+    let mut i = start;
+    loop {
+        match toks.look_ahead(i) {
+            Ident(_) | Comma => i += 1,
+            CloseParen => return Some(i + 1),
+            _ => return None,
+        }
+    }
+}
+
+/// Whether a lambda, `x -> ...` or `(a, b) -> ...`, comes next.
+fn is_lambda(toks: &mut Lexer) -> bool {
+    match (toks.peek(), toks.look_ahead(1)) {
+        (Ident(_), Token::Arrow) => true,
+        (OpenParen, _) => match skip_params(toks, 1) {
+            Some(end) => toks.look_ahead(end) == Token::Arrow,
+            None => false,
+        },
+        _ => false,
+    }
+}
+
+/// Parse an assignment, with or without the 'let' keyword. The
+/// target is a variable or, for a function definition, a call
+/// with the parameter names as its arguments.
+fn assignment(toks: &mut Lexer) -> AstRes {
+    let start = toks.peek_span();
+    if toks.peek() == Token::Let {
+        toks.next();
+    }
+    let mut target = match toks.peek() {
+        Ident(name) => Ast::new(Ident(name)).with_span(toks.peek_span()),
+        _ => {
+            return Err(Error::parse(
+                "expected a variable name after 'let'",
+                toks.peek_span(),
+            ))
+        }
+    };
+    toks.next();
+    if toks.peek() == OpenParen {
+        toks.next();
+        target = definition(toks, target)?;
+    }
+    if toks.peek() != Token::Equal {
+        return Err(Error::parse(
+            format!("expected '=' after 'let {}'", target.tok),
+            toks.peek_span(),
+        ));
+    }
+    toks.next();
+    let value = expr(toks)?;
+    let span = start.join(value.span);
+    Ok(Ast::from(Token::Assign, vec![target, value]).with_span(span))
+}
+
+/// Parse the parameters of a function definition up to and including
+/// the closing parenthesis, giving back a call to `name` with the
+/// parameters as its arguments.
+fn definition(toks: &mut Lexer, name: Ast) -> AstRes {
+    let params = params(toks)?;
+    let func = match name.tok {
+        Ident(name) => Func(name),
+        tok => tok,
     };
+    let span = name.span.join(toks.span());
+    Ok(Ast::from(func, params).with_span(span))
+}
 
-    let mut root = match toks.next().unwrap() {
-        Op(c) => match c {
-            '+' | '-' => Ast::new(Op(c)),
-            _ => {
-                return Err(format!("invalid operation: '{}'", c));
+/// Parse the comma separated parameter names of a function
+/// up to and including the closing parenthesis.
+fn params(toks: &mut Lexer) -> Result<Vec<Ast>, Error> {
+    let mut params: Vec<Ast> = vec![];
+    while toks.peek() != CloseParen {
+        if !params.is_empty() {
+            if toks.peek() != Comma {
+                return Err(unexpected(toks));
             }
-        },
-        _ => return Ok(head),
+            toks.next();
+        }
+        let param = match toks.peek() {
+            Ident(param) => param,
+            _ => return Err(Error::parse("expected a parameter name", toks.peek_span())),
+        };
+        if params.iter().any(|p| p.tok == Ident(param.clone())) {
+            return Err(Error::parse(
+                format!("duplicate parameter '{}'", param),
+                toks.peek_span(),
+            ));
+        }
+        toks.next();
+        params.push(Ast::new(Ident(param)).with_span(toks.span()));
+    }
+    toks.next();
+    Ok(params)
+}
+
+/// Parse a lambda like `x -> x^2` or `(a, b) -> a + b`. Like
+/// a conditional, the body goes as far to the right as it can.
+fn lambda(toks: &mut Lexer) -> AstRes {
+    let start = toks.peek_span();
+    let mut children = match toks.next() {
+        Some(Ident(name)) => vec![Ast::new(Ident(name)).with_span(start)],
+        _ => params(toks)?,
+    };
+    toks.next(); // skip the arrow
+    let body = expr(toks)?;
+    let span = start.join(body.span);
+    children.push(body);
+    Ok(Ast::from(Token::Lambda, children).with_span(span))
+}
+
+/// Parse a list like `[1, 2, 3]`.
+fn list(toks: &mut Lexer) -> AstRes {
+    let start = toks.peek_span();
+    toks.next(); // skip the '['
+    let mut items = vec![];
+    if toks.peek() != Token::CloseBracket {
+        loop {
+            items.push(expr(toks)?);
+            match toks.peek() {
+                Comma => {
+                    toks.next();
+                }
+                _ => break,
+            }
+        }
+    }
+    match toks.peek() {
+        Token::CloseBracket => toks.next(),
+        Token::End => return Err(Error::parse("expected ']'", toks.peek_span())),
+        _ => return Err(unexpected(toks)),
     };
-    root.push(head);
-    root.push(match expr(toks) {
-        Ok(ast) => ast,
-        Err(msg) => return Err(msg),
-    });
-    Ok(root)
+    Ok(Ast::from(Token::List, items).with_span(start.join(toks.span())))
+}
+
+/// Parse a full expression.
+fn expr(toks: &mut Lexer) -> AstRes {
+    expression(toks, 0)
 }
 
+/// Parse an expression made of products and anything
+/// that binds tighter.
 fn term(toks: &mut Lexer) -> AstRes {
-    let res = match factor(toks) {
-        Ok(ast) if toks.is_empty() => return Ok(ast),
-        Ok(ast) => ast,
-        Err(msg) => return Err(msg),
+    expression(toks, PRODUCT)
+}
+
+/// Parse a single operand and its prefix and postfix operators.
+fn factor(toks: &mut Lexer) -> AstRes {
+    expression(toks, NEGATE)
+}
+
+/// Parse an expression only containing operators
+/// with a binding power of at least `min_bp`.
+fn expression(toks: &mut Lexer, min_bp: u8) -> AstRes {
+    let mut lhs = match find_operator(&toks.peek(), Fixity::Prefix) {
+        Some(op) => {
+            let span = toks.peek_span();
+            let tok = toks.next().unwrap();
+            let operand = expression(toks, op.right_bp())?;
+            let span = span.join(operand.span);
+            Ast::from(tok, vec![operand]).with_span(span)
+        }
+        None => primary(toks)?,
     };
 
-    let mut root = match toks.peek() {
-        Op(c) => match c {
-            '/' | '*' | '^' => Ast::new(match toks.next() {
-                Some(tok) => tok,
-                None => {
-                    return Err(format!(
-                        "no more stuff (this needs to be a better error message, sorry)"
-                    ))
-                }
-            }),
-            '%' => {
-                let op = match toks.next() {
-                    Some(tok) => tok,
-                    // This should not happen because
-                    // we just peeked a valid token,
-                    // If next ever returns None here
-                    // then the tokenizer is broken
-                    None => return Ok(res),
-                };
-                match toks.peek() {
-                    Token::Of => match toks.next() {
-                        // setting root as the '%' operator
-                        Some(..) => Ast::new(op),
-                        None => return Err(format!("unexpected end to token stream")),
-                    },
-                    OpenParen | CloseParen => {
-                        return Err(format!("invalid parenthesis '{:?}'", toks.peek()))
-                    }
-                    Token::Invalid => return Err(format!("got invalid token")),
-                    Token::Op(..) => match toks.next() {
-                        // setting root as an operator
-                        Some(tok) => Ast::from(tok, vec![Ast::new(op)]),
-                        None => return Err(format!("unexpected end of token stream")),
-                    },
-                    _ => return Ok(Ast::from(op, vec![res])),
+    loop {
+        if toks.peek() == OpenParen && is_callee(&lhs, toks) {
+            toks.next();
+            let mut children = vec![lhs];
+            children.extend(arguments(toks)?);
+            let span = children[0].span.join(toks.span());
+            lhs = Ast::from(Token::Call, children).with_span(span);
+            continue;
+        }
+        let op = match next_operator(&lhs, toks) {
+            Some(op) if op.bp >= min_bp => op,
+            _ => break,
+        };
+        let tok = match op.bp {
+            IMPLICIT => op.tok.clone(),
+            _ => toks.next().unwrap(),
+        };
+        lhs = match op.fixity {
+            Fixity::Postfix if tok == Token::OpenBracket => {
+                let index = expr(toks)?;
+                expect(toks, Token::CloseBracket)?;
+                let span = lhs.span.join(toks.span());
+                Ast::from(Token::Index, vec![lhs, index]).with_span(span)
+            }
+            Fixity::Postfix => {
+                let span = lhs.span.join(toks.span());
+                Ast::from(tok, vec![lhs]).with_span(span)
+            }
+            _ => {
+                if op.tok == Token::Of {
+                    toks.next(); // skip the 'of' after '%'
                 }
+                let rhs = expression(toks, op.right_bp())?;
+                let span = lhs.span.join(rhs.span);
+                Ast::from(tok, vec![lhs, rhs]).with_span(span)
             }
-            '+' | '-' => return Ok(res),
-            _ => return Err(format!("invalid operation '{}'", c)),
-        },
-        Modulus => Ast::new(toks.next().unwrap()),
-        _ => return Err(format!("unexpected input")),
-    };
-    // This catches a weird edge case where we have '%' followed by
-    // some operator. This is a hack, better grammar will probably
-    // fix this.
-    if root.children.len() == 1 && root.children[0].tok == Op('%') {
-        root.children[0].push(res);
-    } else {
-        root.push(res);
+        };
+    }
+    Ok(lhs)
+}
+
+fn primary(toks: &mut Lexer) -> AstRes {
+    let start = toks.peek_span();
+    if is_lambda(toks) {
+        return lambda(toks);
     }
-    if let Ok(rhs) = term(toks) {
-        root.push(rhs);
+    match toks.peek() {
+        Int(..) | Float(..) | Imaginary(..) => quantity(toks),
+        Ident(name) => {
+            toks.next();
+            if toks.peek() != OpenParen {
+                return Ok(Ast::new(Ident(name)).with_span(start));
+            }
+            toks.next();
+            let args = arguments(toks)?;
+            Ok(Ast::from(Func(name), args).with_span(start.join(toks.span())))
+        }
+        OpenParen => {
+            toks.next();
+            let ast = expr(toks)?;
+            expect_close(toks)?;
+            Ok(ast.with_span(start.join(toks.span())))
+        }
+        Token::If => conditional(toks),
+        Token::OpenBracket => list(toks),
+        _ => Err(unexpected(toks)),
     }
-    Ok(root)
 }
 
-fn factor(toks: &mut Lexer) -> AstRes {
+/// Parse `if c then a else b`. Like a prefix operator, the
+/// `else` branch goes as far to the right as it can.
+fn conditional(toks: &mut Lexer) -> AstRes {
+    let start = toks.peek_span();
+    toks.next(); // skip the 'if' keyword
+    let cond = expr(toks)?;
+    expect(toks, Token::Then)?;
+    let then = expr(toks)?;
+    expect(toks, Token::Else)?;
+    let other = expr(toks)?;
+    let span = start.join(other.span);
+    Ok(Ast::from(Token::If, vec![cond, then, other]).with_span(span))
+}
+
+/// Skip the keyword `tok`, which has to come next.
+fn expect(toks: &mut Lexer, tok: Token) -> Result<(), Error> {
     match toks.peek() {
-        Int(..) | Float(..) => Ok(Ast::new(toks.next().unwrap())),
-        OpenParen => match expr(&mut toks.capture_group()?) {
-            Ok(ast) => Ok(ast.as_grouped()),
-            Err(msg) => Err(msg),
-        },
-        Op('-') => Ok(Ast::from(toks.next().unwrap(), vec![factor(toks)?])),
-        Invalid => Err(format!("invalid input")),
-        _ => {
-            return Err(format!("invalid token '{}'", toks.peek().to_string(),));
+        t if t == tok => {
+            toks.next();
+            Ok(())
+        }
+        Token::End => Err(Error::parse(
+            format!("expected '{}'", tok),
+            toks.peek_span(),
+        )),
+        Invalid => Err(unexpected(toks)),
+        t => Err(Error::parse(
+            format!("expected '{}', got '{}'", tok, t),
+            toks.peek_span(),
+        )),
+    }
+}
+
+/// Parse a number and its units. Quantities written one after
+/// the other are added, so `5 ft 11 in` is `5 ft + 11 in`.
+fn quantity(toks: &mut Lexer) -> AstRes {
+    let num = Ast::new(toks.next().unwrap()).with_span(toks.span());
+    let ast = units(toks, num)?;
+    let has_units = ast.tok == Op('*');
+    match toks.peek() {
+        Int(..) | Float(..) if has_units => {
+            let rest = quantity(toks)?;
+            let span = ast.span.join(rest.span);
+            Ok(Ast::from(Op('+'), vec![ast, rest]).with_span(span))
         }
+        _ => Ok(ast),
+    }
+}
+
+/// Parse the units written after a number, like the `km` in `3.5 km`,
+/// as a product that binds tighter than any operator. This keeps
+/// `3.5 km / 20 min` from being read as `3.5 km / 20 * min`. A unit
+/// takes its own power, so `16 m^2` is sixteen square meters.
+fn units(toks: &mut Lexer, num: Ast) -> AstRes {
+    let mut ast = num;
+    loop {
+        let name = match (toks.peek(), toks.look_ahead(1)) {
+            (Ident(_), OpenParen) => break,
+            // other names are multiplied in as an implicit product
+            (Ident(name), _) if units::lookup(&name).is_some() => name,
+            // "in" is a conversion when a unit comes after it
+            (Token::In, Ident(_) | OpenParen) => break,
+            (Token::In, _) => String::from("in"),
+            _ => break,
+        };
+        toks.next();
+        let mut unit = Ast::new(Ident(name)).with_span(toks.span());
+        if toks.peek() == Op('^') {
+            let tok = toks.next().unwrap();
+            let exp = expression(toks, POWER)?;
+            let span = unit.span.join(exp.span);
+            unit = Ast::from(tok, vec![unit, exp]).with_span(span);
+        }
+        let span = ast.span.join(unit.span);
+        ast = Ast::from(Op('*'), vec![ast, unit]).with_span(span);
+    }
+    Ok(ast)
+}
+
+/// Parse the comma separated arguments of a function
+/// call up to and including the closing parenthesis.
+fn arguments(toks: &mut Lexer) -> Result<Vec<Ast>, Error> {
+    let mut args = vec![];
+    if toks.peek() == CloseParen {
+        toks.next();
+        return Ok(args);
+    }
+    loop {
+        args.push(expr(toks)?);
+        match toks.peek() {
+            Comma => {
+                toks.next();
+            }
+            _ => break,
+        }
+    }
+    expect_close(toks)?;
+    Ok(args)
+}
+
+fn expect_close(toks: &mut Lexer) -> Result<(), Error> {
+    match toks.peek() {
+        CloseParen => {
+            toks.next();
+            Ok(())
+        }
+        Token::End => Err(Error::parse("expected ')'", toks.peek_span())),
+        Invalid => Err(unexpected(toks)),
+        tok => Err(Error::parse(
+            format!("expected ')', got '{}'", tok),
+            toks.peek_span(),
+        )),
     }
 }
 
 #[cfg(test)]
+#[allow(
+    non_fmt_panics,
+    unused_parens,
+    clippy::single_match,
+    clippy::unnecessary_cast,
+    clippy::useless_vec
+)]
 mod test {
     use super::parse;
     use super::{expr, factor, term};
-    use crate::ast::eval;
-    use crate::lex::{Lexer, Token, Token::Int};
+    use crate::ast::{self, Ast};
+    use crate::env::Env;
+    use crate::lex::{Lexer, Token};
+
+    fn eval(ast: &Ast) -> f64 {
+        ast::eval(ast, &mut Env::new()).unwrap().as_f64().unwrap()
+    }
 
     #[test]
     fn test_errors() {
@@ -158,19 +727,271 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_assignment() {
+        match parse("let x = 2 * 3") {
+            Ok(ast) => {
+                assert_eq!(ast.tok, Token::Assign);
+                assert_eq!(ast.children[0].tok, Token::Ident(String::from("x")));
+                assert_eq!(ast.children[1].tok, Token::Op('*'));
+            }
+            Err(msg) => panic!("{}", msg),
+        }
+        let ast = parse("x = 1 == 2").unwrap();
+        assert_eq!(ast.tok, Token::Assign);
+        assert_eq!(ast.children[1].tok, Token::EqualEqual);
+        let ast = parse("let f(x, y) = x * y").unwrap();
+        assert_eq!(ast.tok, Token::Assign);
+        assert_eq!(ast.children[0].tok, Token::Func(String::from("f")));
+        assert_eq!(ast.children[0].children.len(), 2);
+        assert_eq!(parse("g() = 1").unwrap().children[0].children.len(), 0);
+        // a call that is compared, not a definition
+        assert_eq!(parse("f(x) == 1").unwrap().tok, Token::EqualEqual);
+        // an equation, not a definition
+        assert_eq!(parse("f(1) = 1").unwrap().tok, Token::Equal);
+        assert_eq!(parse("2 * x + 3 = 11").unwrap().tok, Token::Equal);
+        for s in ["f(x, x) = x", "let f(x y) = x", "let f(x) + 1"] {
+            assert!(parse(s).is_err(), "expected an error from \"{}\"", s);
+        }
+        assert!(parse("let x + 1").is_err());
+        assert!(parse("1 + let").is_err());
+    }
+
+    #[test]
+    fn test_functions() {
+        match parse("max(1, (2), 3 * 4)") {
+            Ok(ast) => {
+                assert_eq!(ast.tok, Token::Func(String::from("max")));
+                assert_eq!(ast.children.len(), 3);
+                assert_eq!(ast.children[0].tok, Token::int(1));
+                assert_eq!(ast.children[1].tok, Token::int(2));
+                assert_eq!(ast.children[2].tok, Token::Op('*'));
+            }
+            Err(msg) => panic!("{}", msg),
+        }
+        match parse("2 * sqrt(16) / 2") {
+            Ok(ast) => assert_eq!(eval(&ast), 4.),
+            Err(msg) => panic!("{}", msg),
+        }
+        for s in ["sqrt(,)", "sqrt(1,)", "sqrt(1 2)"] {
+            assert!(parse(s).is_err(), "expected an error from \"{}\"", s);
+        }
+    }
+
+    #[test]
+    fn test_factorial() {
+        match parse("2^3!") {
+            Ok(ast) => {
+                assert_eq!(ast.tok, Token::Op('^'));
+                assert_eq!(ast.children[1].tok, Token::Factorial);
+                assert_eq!(ast.children[1].children[0].tok, Token::int(3));
+                assert_eq!(eval(&ast), 64.);
+            }
+            Err(msg) => panic!("{}", msg),
+        }
+        match parse("-(3+2)!") {
+            Ok(ast) => {
+                assert_eq!(ast.tok, Token::Op('-'));
+                assert_eq!(ast.children[0].tok, Token::Factorial);
+                assert_eq!(eval(&ast), -120.);
+            }
+            Err(msg) => panic!("{}", msg),
+        }
+        assert!(parse("!3").is_err());
+    }
+
+    #[test]
+    fn test_associativity() {
+        for s in ["1-2-3", "8/2/2", "9 mod 5 mod 3", "2*3/4"] {
+            match parse(s) {
+                // left associative operators group to the left
+                Ok(ast) => assert_eq!(ast.children[1].children.len(), 0, "{}", s),
+                Err(msg) => panic!("{}", msg),
+            }
+        }
+        for (s, expected) in [
+            ("1-2-3", -4.),
+            ("8/2/2", 2.),
+            ("-2^2", -4.),
+            ("2^-1", 0.5),
+            ("2*-3", -6.),
+            ("-2*3", -6.),
+            ("2^3^2", 512.),
+            ("1 + 20% of 50 * 2", 21.),
+        ] {
+            match parse(s) {
+                Ok(ast) => assert_eq!(eval(&ast), expected, "{}", s),
+                Err(msg) => panic!("{}", msg),
+            }
+        }
+        for s in ["(1+2", "1+", "2 3", "sqrt(2", "max(1,,2)", "*2", "2 % of"] {
+            assert!(parse(s).is_err(), "expected an error from \"{}\"", s);
+        }
+    }
+
+    #[test]
+    fn test_logic() {
+        let ast = parse("not x > 0 and y <= 1 or z").unwrap();
+        assert_eq!(ast.tok, Token::Or);
+        assert_eq!(ast.children[0].tok, Token::And);
+        assert_eq!(ast.children[0].children[0].tok, Token::Not);
+        assert_eq!(ast.children[0].children[0].children[0].tok, Token::Op('>'));
+        let ast = parse("x & 1 == 0").unwrap();
+        assert_eq!(ast.tok, Token::EqualEqual);
+        let ast = parse("1 + if x > 0 then x else -x * 2").unwrap();
+        assert_eq!(ast.children[1].tok, Token::If);
+        assert_eq!(ast.children[1].children[2].tok, Token::Op('*'));
+        for s in ["if 1 then 2", "if 1 else 2", "if then 1 else 2", "1 <"] {
+            assert!(parse(s).is_err(), "expected an error from \"{}\"", s);
+        }
+    }
+
+    #[test]
+    fn test_source() {
+        for s in [
+            "x^2 + 2 * (x - 1)",
+            "-2^2",
+            "(-2)^2",
+            "2^3^2",
+            "(2^3)^2",
+            "1 - (2 - 3)",
+            "(1 - 2) - 3",
+            "(3 + 2)!",
+            "12% of (50 + 5)",
+            "not x > 0 and y",
+            "f(x, sin(y)) to cm",
+            "1 + (if x then 1 else 2)",
+            "f(x) = if x <= 1 then 1 else x * f(x - 1)",
+            "map(x -> x^2, [1, 2, []])",
+            "g = (a, b) -> (x -> a * x) + b",
+            "(xs + 1)[0] * [1, 2][n - 1]",
+            "sum(1..n^2)",
+            "inv(a @ b) @ (x + y)",
+        ] {
+            let ast = parse(s).unwrap();
+            let source = ast.to_string();
+            let again = parse(&source).unwrap();
+            assert_eq!(format!("{:?}", again), format!("{:?}", ast), "{}", s);
+            assert_eq!(again.to_string(), source, "{}", s);
+        }
+        assert_eq!(parse("(1 - 2) - 3").unwrap().to_string(), "1 - 2 - 3");
+        assert_eq!(parse("(x * y)").unwrap().to_string(), "x * y");
+        assert_eq!(parse("-(x + 1)").unwrap().to_string(), "-(x + 1)");
+        assert_eq!(parse("((-2))^2").unwrap().to_string(), "(-2)^2");
+    }
+
+    #[test]
+    fn test_lambda() {
+        let ast = parse("map((a, b) -> a * b, [1, [2], []])").unwrap();
+        let lambda = &ast.children[0];
+        assert_eq!(lambda.tok, Token::Lambda);
+        assert_eq!(lambda.children.len(), 3);
+        assert_eq!(lambda.children[2].tok, Token::Op('*'));
+        let list = &ast.children[1];
+        assert_eq!(list.tok, Token::List);
+        assert_eq!(list.children[1].tok, Token::List);
+        assert_eq!(list.children[2].children.len(), 0);
+        let ast = parse("x -> y -> x + y").unwrap();
+        assert_eq!(ast.children[1].tok, Token::Lambda);
+        assert_eq!(parse("() -> 1").unwrap().children.len(), 1);
+        assert_eq!(parse("(x) * 2").unwrap().tok, Token::Op('*'));
+        for s in ["[1, 2", "[1 2]", "(x, 1) -> x", "x ->", "[,]"] {
+            assert!(parse(s).is_err(), "expected an error from \"{}\"", s);
+        }
+    }
+
+    #[test]
+    fn test_lists() {
+        let ast = parse("xs[i + 1]^2").unwrap();
+        assert_eq!(ast.tok, Token::Op('^'));
+        assert_eq!(ast.children[0].tok, Token::Index);
+        assert_eq!(ast.children[0].children[1].tok, Token::Op('+'));
+        let ast = parse("[[1, 2], [3]][0][1]").unwrap();
+        assert_eq!(ast.children[0].tok, Token::Index);
+        let ast = parse("1..n + 1").unwrap();
+        assert_eq!(ast.tok, Token::Range);
+        assert_eq!(ast.children[1].tok, Token::Op('+'));
+        for s in ["xs[1", "xs[]", "xs[1, 2]", "1.."] {
+            assert!(parse(s).is_err(), "expected an error from \"{}\"", s);
+        }
+    }
+
+    #[test]
+    fn test_implicit_product() {
+        for (s, source) in [
+            ("2x", "2 * x"),
+            ("3(4 + 5)", "3 * (4 + 5)"),
+            ("(a)(b)", "a * b"),
+            ("(a + 1)x", "(a + 1) * x"),
+            ("2 sin(x)", "2 * sin(x)"),
+            ("sin(x)cos(x)", "sin(x) * cos(x)"),
+            // tighter than a product, looser than a power
+            ("1/2x", "1 / (2 * x)"),
+            ("2x^2", "2 * x^2"),
+            ("2^3x", "2^3 * x"),
+            ("-2x", "-(2 * x)"),
+            ("2x!", "2 * x!"),
+            ("2x + 1", "2 * x + 1"),
+            ("3 km / 2x", "3 * km / (2 * x)"),
+            ("9mod 5", "9 mod 5"),
+        ] {
+            assert_eq!(parse(s).unwrap().to_string(), source, "{}", s);
+        }
+        // a call, not a product
+        assert_eq!(parse("f(2)").unwrap().tok, Token::Func(String::from("f")));
+        assert_eq!(parse("(x)^2 y").unwrap().to_string(), "x^2 * y");
+        assert_eq!(parse("f(2) y").unwrap().to_string(), "f(2) * y");
+        assert_eq!(parse("2 m^2 x").unwrap().to_string(), "2 * m^2 * x");
+        for s in ["x y", "x^2 y", "2 * x y", "2x y", "(1)2", "[1](2)", "x!(2)"] {
+            assert!(parse(s).is_err(), "expected an error from \"{}\"", s);
+        }
+    }
+
+    #[test]
+    fn test_operator_table() {
+        use super::{find_operator, Fixity, OPERATORS};
+        for op in OPERATORS {
+            // every (token, fixity) pair should only be registered once
+            let n = OPERATORS
+                .iter()
+                .filter(|other| other.tok == op.tok && other.fixity == op.fixity)
+                .count();
+            assert_eq!(n, 1, "duplicate operator '{}'", op.tok);
+        }
+        assert!(find_operator(&Token::Op('-'), Fixity::Prefix).is_some());
+        assert!(find_operator(&Token::Op('*'), Fixity::Prefix).is_none());
+        let pow = find_operator(&Token::Op('^'), Fixity::Infix).unwrap();
+        assert_eq!(pow.right_bp(), pow.bp);
+        let sub = find_operator(&Token::Op('-'), Fixity::Infix).unwrap();
+        assert_eq!(sub.right_bp(), sub.bp + 1);
+    }
+
+    #[test]
+    fn test_spans() {
+        use crate::error::Span;
+        let ast = parse("1 + (2 * 3)! - sqrt(4)").unwrap();
+        assert_eq!(ast.span, Span::new(0, 22));
+        let lhs = &ast.children[0];
+        assert_eq!(lhs.children[0].span, Span::new(0, 1));
+        assert_eq!(lhs.children[1].span, Span::new(4, 12));
+        assert_eq!(lhs.children[1].children[0].span, Span::new(4, 11));
+        assert_eq!(ast.children[1].span, Span::new(15, 22));
+        assert_eq!(parse("let x = -1").unwrap().span, Span::new(0, 10));
+    }
+
     #[test]
     fn test_keywords() {
         match parse("20%^2") {
             Ok(ast) => {
-                assert_eq!(eval(&ast), (20. / 100. as f64).powf(2.));
+                assert_eq!(eval(&ast), 0.04);
             }
             Err(msg) => panic!(msg),
         }
         match parse("4 mod 5") {
             Ok(ast) => {
                 assert_eq!(ast.tok, Token::Modulus);
-                assert_eq!(ast.children[0].tok, Int(4));
-                assert_eq!(ast.children[1].tok, Int(5));
+                assert_eq!(ast.children[0].tok, Token::int(4));
+                assert_eq!(ast.children[1].tok, Token::int(5));
                 assert_eq!(eval(&ast), 4.0 % 5.0);
             }
             Err(msg) => panic!(msg),
@@ -178,8 +999,8 @@ mod test {
         match parse("12% of 55") {
             Ok(ast) => {
                 assert_eq!(ast.tok, Token::Op('%'));
-                assert_eq!(ast.children[0].tok, Int(12));
-                assert_eq!(ast.children[1].tok, Int(55));
+                assert_eq!(ast.children[0].tok, Token::int(12));
+                assert_eq!(ast.children[1].tok, Token::int(55));
                 assert_eq!(eval(&ast), (12.0 / 100.) * 55.);
             }
             Err(msg) => panic!(msg),
@@ -198,8 +1019,8 @@ mod test {
             match parse(s) {
                 Ok(ast) => {
                     assert_eq!(ast.tok, Token::Op('^'));
-                    assert_eq!(ast.children[0].tok, Int(2));
-                    assert_eq!(ast.children[1].tok, Int(2));
+                    assert_eq!(ast.children[0].tok, Token::int(2));
+                    assert_eq!(ast.children[1].tok, Token::int(2));
                 }
                 Err(msg) => panic!(msg),
             }
@@ -208,10 +1029,10 @@ mod test {
             match parse(s) {
                 Ok(ast) => {
                     assert_eq!(ast.tok, Token::Op('^'));
-                    assert_eq!(ast.children[0].tok, Int(2));
+                    assert_eq!(ast.children[0].tok, Token::int(2));
                     assert_eq!(ast.children[1].tok, Token::Op('^'));
-                    assert_eq!(ast.children[1].children[0].tok, Int(3));
-                    assert_eq!(ast.children[1].children[1].tok, Int(2));
+                    assert_eq!(ast.children[1].children[0].tok, Token::int(3));
+                    assert_eq!(ast.children[1].children[1].tok, Token::int(2));
                     assert_eq!(eval(&ast), (2.0 as f64).powf((3.0 as f64).powf(2.0)));
                 }
                 Err(msg) => panic!(msg),
@@ -220,10 +1041,10 @@ mod test {
         match parse("(2^3)^2") {
             Ok(ast) => {
                 assert_eq!(ast.tok, Token::Op('^'));
-                assert_eq!(ast.children[1].tok, Int(2));
+                assert_eq!(ast.children[1].tok, Token::int(2));
                 assert_eq!(ast.children[0].tok, Token::Op('^'));
-                assert_eq!(ast.children[0].children[0].tok, Int(2));
-                assert_eq!(ast.children[0].children[1].tok, Int(3));
+                assert_eq!(ast.children[0].children[0].tok, Token::int(2));
+                assert_eq!(ast.children[0].children[1].tok, Token::int(3));
                 assert_eq!(eval(&ast), (2.0 as f64).powf(3.0).powf(2.0));
             }
             Err(msg) => panic!(msg),
@@ -238,13 +1059,13 @@ mod test {
             Err(msg) => panic!(msg),
         };
         assert_eq!(t.children[0].tok, Token::Op('/'));
-        assert_eq!(t.children[1].tok, Int(6));
-        assert_eq!(t.children[0].children[0].tok, Int(3));
+        assert_eq!(t.children[1].tok, Token::int(6));
+        assert_eq!(t.children[0].children[0].tok, Token::int(3));
         assert_eq!(t.children[0].children[1].children[0].tok, Token::Op('/'));
-        assert_eq!(t.children[0].children[1].children[1].tok, Int(5));
+        assert_eq!(t.children[0].children[1].children[1].tok, Token::int(5));
         let sub = &t.children[0].children[1].children[0];
-        assert_eq!(sub.children[0].tok, Int(3));
-        assert_eq!(sub.children[1].tok, Int(4));
+        assert_eq!(sub.children[0].tok, Token::int(3));
+        assert_eq!(sub.children[1].tok, Token::int(4));
         assert_eq!(eval(&t), 3.0 / (3.0 / 4.0 / 5.0) / 6.0);
         match parse("") {
             Err(..) => {}
@@ -259,7 +1080,7 @@ mod test {
             let mut t = Lexer::new(s);
             match factor(&mut t) {
                 Ok(ast) => {
-                    assert_eq!(ast.tok, Int(1));
+                    assert_eq!(ast.tok, Token::int(1));
                     assert_eq!(ast.children.len(), 0);
                 }
                 Err(msg) => panic!(msg),
@@ -272,7 +1093,7 @@ mod test {
                 Ok(ast) => {
                     assert_eq!(ast.tok, Token::Op('-'));
                     assert_eq!(ast.children.len(), 1);
-                    assert_eq!(ast.children[0].tok, Token::Float(3.1));
+                    assert_eq!(ast.children[0].tok, Token::float(3.1));
                 }
                 Err(msg) => panic!(msg),
             }
@@ -282,14 +1103,14 @@ mod test {
     #[test]
     fn test_term() {
         match term(&mut Lexer::new("(1)")) {
-            Ok(ast) => assert_eq!(ast.tok, Int(1)),
+            Ok(ast) => assert_eq!(ast.tok, Token::int(1)),
             Err(msg) => panic!(msg),
         }
         match term(&mut Lexer::new("1*1")) {
             Ok(ast) => {
                 assert_eq!(ast.tok, Token::Op('*'));
-                assert_eq!(ast.children[0].tok, Int(1));
-                assert_eq!(ast.children[1].tok, Int(1));
+                assert_eq!(ast.children[0].tok, Token::int(1));
+                assert_eq!(ast.children[1].tok, Token::int(1));
             }
             Err(msg) => panic!(msg),
         }
@@ -316,9 +1137,9 @@ mod test {
                 Ok(ast) => {
                     assert_eq!(ast.tok, Token::Op(s.1));
                     assert_eq!(ast.children[0].tok, Token::Op(s.1));
-                    assert_eq!(ast.children[0].children[0].tok, Int(1));
-                    assert_eq!(ast.children[0].children[1].tok, Int(2));
-                    assert_eq!(ast.children[1].tok, Int(3));
+                    assert_eq!(ast.children[0].children[0].tok, Token::int(1));
+                    assert_eq!(ast.children[0].children[1].tok, Token::int(2));
+                    assert_eq!(ast.children[1].tok, Token::int(3));
                     assert_eq!(
                         eval(&ast),
                         match s.1 {
@@ -361,12 +1182,12 @@ mod test {
                         assert_eq!(r, *a);
                     }
                     assert_eq!(ast.tok, Token::Op('+'));
-                    assert_eq!(ast.children[0].tok, Int(5));
+                    assert_eq!(ast.children[0].tok, Token::int(5));
                     assert_eq!(ast.children[1].tok, Token::Op('/'));
                     assert_eq!(ast.children[1].children[0].tok, Token::Op('*'));
-                    assert_eq!(ast.children[1].children[0].children[0].tok, Token::Int(3));
-                    assert_eq!(ast.children[1].children[0].children[1].tok, Token::Int(3));
-                    assert_eq!(ast.children[1].children[1].tok, Token::Int(6));
+                    assert_eq!(ast.children[1].children[0].children[0].tok, Token::int(3));
+                    assert_eq!(ast.children[1].children[0].children[1].tok, Token::int(3));
+                    assert_eq!(ast.children[1].children[1].tok, Token::int(6));
                 }
                 Err(msg) => panic!(msg),
             }
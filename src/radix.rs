@@ -0,0 +1,83 @@
+use std::fmt;
+
+use crate::bigint::BigInt;
+
+/// An integer written in base 2, 8 or 16, the result of a
+/// conversion like `255 to hex`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Radix {
+    value: BigInt,
+    base: u32,
+    /// The number of bits negative numbers are written with.
+    width: u32,
+}
+
+impl Radix {
+    pub fn new(value: BigInt, base: u32, width: u32) -> Self {
+        Self { value, base, width }
+    }
+
+    pub fn value(&self) -> &BigInt {
+        &self.value
+    }
+
+    pub fn base(&self) -> u32 {
+        self.base
+    }
+}
+
+/// The base named by the target of a conversion like `to hex`.
+pub(crate) fn base(name: &str) -> Option<u32> {
+    match name {
+        "hex" => Some(16),
+        "oct" => Some(8),
+        "bin" => Some(2),
+        "dec" => Some(10),
+        _ => None,
+    }
+}
+
+impl fmt::Display for Radix {
+    /// Written with a prefix like `0xFF`. Negative numbers are written in
+    /// two's complement when they fit in the width, so -1 in 8 bits is
+    /// `0xFF`, and with a minus sign otherwise.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let prefix = match self.base {
+            16 => "0x",
+            8 => "0o",
+            2 => "0b",
+            _ => return write!(f, "{}", self.value),
+        };
+        if !self.value.is_negative() {
+            return write!(f, "{}{}", prefix, self.value.to_radix(self.base));
+        }
+        let modulus = BigInt::one().shl(self.width.into());
+        let min = -&modulus.shr(1);
+        if self.value >= min {
+            let bits = &modulus + &self.value;
+            write!(f, "{}{}", prefix, bits.to_radix(self.base))
+        } else {
+            write!(f, "-{}{}", prefix, self.value.to_radix(self.base))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Radix;
+    use crate::bigint::BigInt;
+
+    #[test]
+    fn test_display() {
+        let show = |n: i64, base, width| Radix::new(BigInt::from(n), base, width).to_string();
+        assert_eq!(show(255, 16, 64), "0xFF");
+        assert_eq!(show(493, 8, 64), "0o755");
+        assert_eq!(show(165, 2, 8), "0b10100101");
+        assert_eq!(show(0, 2, 8), "0b0");
+        assert_eq!(show(-1, 16, 8), "0xFF");
+        assert_eq!(show(-128, 16, 8), "0x80");
+        assert_eq!(show(-129, 16, 8), "-0x81");
+        assert_eq!(show(-2, 16, 32), "0xFFFFFFFE");
+        assert_eq!(show(-5, 10, 8), "-5");
+    }
+}
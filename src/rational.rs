@@ -0,0 +1,318 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Mul, Neg, Sub};
+
+use crate::bigint::{self, BigInt};
+
+/// The largest number of bits a power or factorial is allowed to
+/// have before it is handed over to floating point instead.
+pub const MAX_BITS: u64 = 1 << 17;
+
+/// An exact fraction, always kept in lowest terms with a positive
+/// denominator.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Rational {
+    num: BigInt,
+    den: BigInt,
+}
+
+impl Rational {
+    pub fn new<N: Into<BigInt>, D: Into<BigInt>>(num: N, den: D) -> Option<Self> {
+        let (num, den) = (num.into(), den.into());
+        if den.is_zero() {
+            return None;
+        }
+        let g = num.gcd(&den);
+        let (mut num, mut den) = match g.is_one() || g.is_zero() {
+            true => (num, den),
+            false => (num.div_rem(&g)?.0, den.div_rem(&g)?.0),
+        };
+        if den.is_negative() {
+            num = -&num;
+            den = -&den;
+        }
+        Some(Self { num, den })
+    }
+
+    pub fn from_int<N: Into<BigInt>>(n: N) -> Self {
+        Self {
+            num: n.into(),
+            den: BigInt::one(),
+        }
+    }
+
+    /// Convert a float through its shortest decimal representation,
+    /// so `0.1` becomes exactly one tenth.
+    pub fn from_f64(f: f64) -> Option<Self> {
+        if !f.is_finite() {
+            return None;
+        }
+        Self::from_decimal(&format!("{}", f))
+    }
+
+    /// Read a plain decimal number like `-12.375`.
+    pub fn from_decimal(s: &str) -> Option<Self> {
+        let (int, frac) = match s.find('.') {
+            Some(i) => (&s[..i], &s[i + 1..]),
+            None => (s, ""),
+        };
+        if frac.starts_with(['-', '+']) {
+            return None;
+        }
+        let num = BigInt::from_decimal(&format!("{}{}", int, frac))?;
+        Self::new(num, BigInt::from(10).pow(frac.len() as u64))
+    }
+
+    pub fn numer(&self) -> &BigInt {
+        &self.num
+    }
+
+    pub fn denom(&self) -> &BigInt {
+        &self.den
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.den.is_one()
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.num.is_zero()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.num.is_negative()
+    }
+
+    /// The nearest float, found by dividing out enough
+    /// bits to fill the mantissa.
+    pub fn to_f64(&self) -> f64 {
+        let shift = 64 - (self.num.bits() as i64 - self.den.bits() as i64);
+        let q = if shift >= 0 {
+            self.num.shl(shift as u64).div_rem(&self.den)
+        } else {
+            self.num.div_rem(&self.den.shl(-shift as u64))
+        };
+        let q = q.map_or(f64::NAN, |(q, _)| q.to_f64());
+        bigint::scale(q, -shift)
+    }
+
+    pub fn checked_div(&self, other: &Self) -> Option<Self> {
+        Some(self * &other.recip()?)
+    }
+
+    /// The remainder of truncated division, which has
+    /// the same sign as `self`.
+    pub fn checked_rem(&self, other: &Self) -> Option<Self> {
+        let q = self.checked_div(other)?.trunc();
+        Some(self - &(&q * other))
+    }
+
+    /// Raise to an integer power, or `None` if the result
+    /// would have more than [`MAX_BITS`] bits.
+    pub fn checked_pow(&self, exp: i64) -> Option<Self> {
+        let base = if exp < 0 { self.recip()? } else { self.clone() };
+        let exp = exp.unsigned_abs();
+        let bits = base.num.bits().max(base.den.bits());
+        if bits > 1 && bits.saturating_mul(exp) > MAX_BITS {
+            return None;
+        }
+        Some(Self {
+            num: base.num.pow(exp),
+            den: base.den.pow(exp),
+        })
+    }
+
+    pub fn recip(&self) -> Option<Self> {
+        Self::new(self.den.clone(), self.num.clone())
+    }
+
+    pub fn abs(&self) -> Self {
+        Self {
+            num: self.num.abs(),
+            den: self.den.clone(),
+        }
+    }
+
+    pub fn floor(&self) -> Self {
+        Self::from_int(self.num.div_floor(&self.den).unwrap())
+    }
+
+    pub fn ceil(&self) -> Self {
+        -&(-self).floor()
+    }
+
+    pub fn trunc(&self) -> Self {
+        Self::from_int(self.num.div_rem(&self.den).unwrap().0)
+    }
+
+    /// Round half away from zero.
+    pub fn round(&self) -> Self {
+        let half = Self::new(if self.is_negative() { -1 } else { 1 }, 2).unwrap();
+        (self + &half).trunc()
+    }
+
+    /// The square root, if it is rational.
+    pub fn sqrt(&self) -> Option<Self> {
+        let (n, d) = (self.num.sqrt()?, self.den.sqrt()?);
+        if &n * &n == self.num && &d * &d == self.den {
+            Some(Self { num: n, den: d })
+        } else {
+            None
+        }
+    }
+
+    /// Is the decimal expansion finite, i.e. is the
+    /// denominator made of only twos and fives.
+    pub fn is_terminating(&self) -> bool {
+        let (_, rest) = self.den.remove_factor(2);
+        rest.remove_factor(5).1.is_one()
+    }
+}
+
+impl Add for &Rational {
+    type Output = Rational;
+
+    fn add(self, other: &Rational) -> Rational {
+        let num = &(&self.num * &other.den) + &(&other.num * &self.den);
+        Rational::new(num, &self.den * &other.den).unwrap()
+    }
+}
+
+impl Sub for &Rational {
+    type Output = Rational;
+
+    fn sub(self, other: &Rational) -> Rational {
+        self + &-other
+    }
+}
+
+impl Mul for &Rational {
+    type Output = Rational;
+
+    fn mul(self, other: &Rational) -> Rational {
+        Rational::new(&self.num * &other.num, &self.den * &other.den).unwrap()
+    }
+}
+
+impl Neg for &Rational {
+    type Output = Rational;
+
+    fn neg(self) -> Rational {
+        Rational {
+            num: -&self.num,
+            den: self.den.clone(),
+        }
+    }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Rational {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (&self.num * &other.den).cmp(&(&other.num * &self.den))
+    }
+}
+
+impl fmt::Display for Rational {
+    /// Integers and fractions with a finite decimal expansion are
+    /// written out exactly as decimals, anything else as `num/den`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_integer() {
+            return write!(f, "{}", self.num);
+        }
+        let (twos, rest) = self.den.remove_factor(2);
+        let (fives, rest) = rest.remove_factor(5);
+        if !rest.is_one() {
+            return write!(f, "{}/{}", self.num, self.den);
+        }
+        // scale up to a whole number of the smallest decimal place
+        let places = twos.max(fives);
+        let scaled = &self.num * &BigInt::from(2).pow(places - twos);
+        let scaled = &scaled * &BigInt::from(5).pow(places - fives);
+        let digits = scaled.abs().to_string();
+        let places = places as usize;
+        let digits = format!("{:0>width$}", digits, width = places + 1);
+        let (int, frac) = digits.split_at(digits.len() - places);
+        let sign = if self.is_negative() { "-" } else { "" };
+        write!(f, "{}{}.{}", sign, int, frac)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Rational;
+    use crate::bigint::BigInt;
+
+    fn r(num: i128, den: i128) -> Rational {
+        Rational::new(num, den).unwrap()
+    }
+
+    #[test]
+    fn test_new() {
+        assert_eq!(r(2, 4), r(1, 2));
+        assert_eq!(r(3, -6), r(-1, 2));
+        assert_eq!(r(-3, -6).denom().to_string(), "2");
+        assert!(Rational::new(1, 0).is_none());
+        assert_eq!(Rational::from_f64(0.1), Some(r(1, 10)));
+        assert_eq!(Rational::from_f64(-3.75), Some(r(-15, 4)));
+        assert_eq!(Rational::from_decimal("12"), Some(r(12, 1)));
+        assert!(Rational::from_decimal("1.-2").is_none());
+        assert!(Rational::from_f64(f64::NAN).is_none());
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(&r(1, 10) + &r(2, 10), r(3, 10));
+        assert_eq!(&r(1, 3) * &r(3, 1), r(1, 1));
+        assert_eq!(&r(1, 2) - &r(1, 3), r(1, 6));
+        assert_eq!(r(1, 2).checked_div(&r(1, 4)), Some(r(2, 1)));
+        assert_eq!(r(1, 2).checked_div(&r(0, 1)), None);
+        assert_eq!(r(7, 1).checked_rem(&r(3, 1)), Some(r(1, 1)));
+        assert_eq!(r(-7, 1).checked_rem(&r(3, 1)), Some(r(-1, 1)));
+        assert_eq!(r(7, 2).checked_rem(&r(1, 1)), Some(r(1, 2)));
+        assert_eq!(r(2, 3).checked_pow(3), Some(r(8, 27)));
+        assert_eq!(r(2, 3).checked_pow(-2), Some(r(9, 4)));
+        assert_eq!(r(1, 1).checked_pow(i64::MAX), Some(r(1, 1)));
+        assert_eq!(r(2, 1).checked_pow(1 << 20), None);
+        let big = &r(i128::MAX, 1) + &r(1, 1);
+        assert_eq!(big.to_string(), "170141183460469231731687303715884105728");
+    }
+
+    #[test]
+    fn test_rounding() {
+        assert_eq!(r(-7, 2).floor(), r(-4, 1));
+        assert_eq!(r(-7, 2).ceil(), r(-3, 1));
+        assert_eq!(r(-7, 2).trunc(), r(-3, 1));
+        assert_eq!(r(-7, 2).round(), r(-4, 1));
+        assert_eq!(r(5, 2).round(), r(3, 1));
+        assert_eq!(r(9, 4).sqrt(), Some(r(3, 2)));
+        assert_eq!(r(2, 1).sqrt(), None);
+        assert_eq!(r(-4, 1).sqrt(), None);
+    }
+
+    #[test]
+    fn test_to_f64() {
+        assert_eq!(r(1, 3).to_f64(), 1.0 / 3.0);
+        assert_eq!(r(-7, 2).to_f64(), -3.5);
+        assert_eq!(r(0, 1).to_f64(), 0.0);
+        let huge = r(10, 1).checked_pow(400).unwrap();
+        assert_eq!(huge.recip().unwrap().to_f64(), 0.0);
+        assert!(huge.to_f64().is_infinite());
+        let ratio = Rational::new(huge.numer() * &BigInt::from(3), huge.numer().clone()).unwrap();
+        assert_eq!(ratio.to_f64(), 3.0);
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(r(3, 10).to_string(), "0.3");
+        assert_eq!(r(-1, 3).to_string(), "-1/3");
+        assert_eq!(r(-5, 4).to_string(), "-1.25");
+        assert_eq!(r(-1, 20).to_string(), "-0.05");
+        assert_eq!(r(42, 1).to_string(), "42");
+        assert_eq!(r(1, 1024).to_string(), "0.0009765625");
+    }
+}
@@ -0,0 +1,73 @@
+use crate::ast::{self, Ast};
+use crate::bigint::BigInt;
+use crate::calculus;
+use crate::decimal::Context;
+use crate::env::Env;
+use crate::error::EvalErrorKind;
+use crate::function::{arity, Function};
+use crate::list::MAX_RANGE;
+use crate::rational::Rational;
+use crate::value::Value;
+
+/// `sum(k, a, b, f)`, the sum of `f` for `k` from `a` to `b`, like
+/// `sum(k, 1, 100, k^2)`. A sum over no terms is 0.
+pub(crate) fn sum(args: &[Ast], env: &mut Env) -> Result<Value, EvalErrorKind> {
+    series("sum", args, Rational::from_int(0), Value::add, env)
+}
+
+/// `prod(k, a, b, f)`, the product of `f` for `k` from `a` to `b`, like
+/// `prod(k, 1, 10, k)`. A product of no terms is 1.
+pub(crate) fn prod(args: &[Ast], env: &mut Env) -> Result<Value, EvalErrorKind> {
+    series("prod", args, Rational::from_int(1), Value::mul, env)
+}
+
+/// Combine the terms of a series with `op`, starting from `init`.
+fn series(
+    name: &str,
+    args: &[Ast],
+    init: Rational,
+    op: fn(&Value, &Value, &Context) -> Result<Value, EvalErrorKind>,
+    env: &mut Env,
+) -> Result<Value, EvalErrorKind> {
+    let [var, start, end, term] = args else {
+        // `sum` of a list is the other form
+        return Err(match name {
+            "sum" => EvalErrorKind::Arity(format!(
+                "sum takes 1 or 4 arguments but {} {} given",
+                args.len(),
+                if args.len() == 1 { "was" } else { "were" },
+            )),
+            _ => arity(name, 4, args.len()),
+        });
+    };
+    let var = calculus::variable(var)?;
+    let start = ast::eval(start, env).map_err(|err| err.kind)?.integer()?;
+    let end = ast::eval(end, env).map_err(|err| err.kind)?.integer()?;
+    let one = BigInt::from(1);
+    let count = match &(&end - &start) + &one {
+        n if n.is_negative() => 0,
+        n => n.to_i64().unwrap_or(i64::MAX),
+    };
+    if count > MAX_RANGE as i64 {
+        return Err(EvalErrorKind::Overflow(format!(
+            "{}({}, {}, {}, {})",
+            name, var, start, end, term
+        )));
+    }
+    let func = Function::lambda(vec![var], term.clone(), env);
+    let ctx = env.context();
+    let mut total = Value::Rational(init);
+    let mut k = start;
+    for _ in 0..count {
+        let x = func.apply(vec![Value::Rational(Rational::from_int(k.clone()))], env)?;
+        if !x.is_number() {
+            return Err(EvalErrorKind::Type(format!(
+                "expected a number but got {}",
+                x
+            )));
+        }
+        total = op(&total, &x, &ctx)?;
+        k = &k + &one;
+    }
+    Ok(total)
+}
@@ -0,0 +1,312 @@
+use crate::ast::{self, Ast};
+use crate::builtins;
+use crate::env::Env;
+use crate::lex::Token;
+use crate::rational::Rational;
+use crate::value::Value;
+
+/// Rewrite an expression into a simpler one with the same value.
+/// Parts without variables are worked out when the result is exact,
+/// `12% of x` becomes `0.12 * x`, and sums and products are written
+/// with each term and factor once, so `x * x + 2 * x - x` is
+/// `x^2 + x`.
+pub(crate) fn simplify(ast: &Ast) -> Ast {
+    if let Some(r) = fold(ast) {
+        return number(&r);
+    }
+    let children = ast.children.iter().map(simplify).collect();
+    let ast = Ast::from(ast.tok.clone(), children).with_span(ast.span);
+    let res = match (&ast.tok, ast.children.len()) {
+        (Token::Op('+'), 2) | (Token::Op('-'), 1 | 2) => sum(&ast),
+        (Token::Op('*' | '/'), 2) | (Token::Op('%'), 1 | 2) => {
+            Product::of(&ast).map(|p| p.to_ast())
+        }
+        (Token::Op('^'), 2) => power(&ast),
+        _ => None,
+    };
+    res.unwrap_or(ast)
+}
+
+/// The value of an expression without variables, if it's exact.
+fn fold(ast: &Ast) -> Option<Rational> {
+    if !is_constant(ast) {
+        return None;
+    }
+    match ast::eval(ast, &mut Env::new()) {
+        Ok(Value::Rational(r)) => Some(r),
+        _ => None,
+    }
+}
+
+fn is_constant(ast: &Ast) -> bool {
+    let constant = match &ast.tok {
+        Token::Int(_) | Token::Float(_) => true,
+        Token::Func(name) => builtins::lookup(name).is_some(),
+        Token::Ident(_) | Token::Assign | Token::Lambda | Token::List => false,
+        _ => !ast.children.is_empty(),
+    };
+    constant && ast.children.iter().all(is_constant)
+}
+
+/// A number written the way the calculator shows it, as an integer,
+/// a decimal or a fraction.
+fn number(r: &Rational) -> Ast {
+    if r.is_integer() {
+        return Ast::new(Token::Int(r.numer().clone()));
+    }
+    match decimal(r) {
+        Some(f) => Ast::new(Token::float(f)),
+        None => Ast::from(
+            Token::Op('/'),
+            vec![
+                Ast::new(Token::Int(r.numer().clone())),
+                Ast::new(Token::Int(r.denom().clone())),
+            ],
+        ),
+    }
+}
+
+/// The float that is exactly `r`, if `r` has a short enough decimal
+/// expansion.
+fn decimal(r: &Rational) -> Option<f64> {
+    let f = r.to_f64();
+    match r.is_terminating() && Rational::from_f64(f).as_ref() == Some(r) {
+        true => Some(f),
+        false => None,
+    }
+}
+
+fn one() -> Rational {
+    Rational::from_int(1)
+}
+
+/// A product written as a number times powers of distinct factors.
+struct Product {
+    coefficient: Rational,
+    factors: Vec<(Ast, Rational)>,
+}
+
+impl Product {
+    /// Collect the factors of a product, or `None` if it divides by zero.
+    fn of(ast: &Ast) -> Option<Product> {
+        let mut product = Product {
+            coefficient: one(),
+            factors: vec![],
+        };
+        product.collect(ast, false)?;
+        product.factors.retain(|(_, exp)| !exp.is_zero());
+        Some(product)
+    }
+
+    /// Multiply in `ast`, or divide by it when `inverse` is set.
+    fn collect(&mut self, ast: &Ast, inverse: bool) -> Option<()> {
+        let hundredth = Rational::new(1, 100).unwrap();
+        match (&ast.tok, ast.children.as_slice()) {
+            (Token::Op('*'), [a, b]) => {
+                self.collect(a, inverse)?;
+                self.collect(b, inverse)
+            }
+            (Token::Op('/'), [a, b]) => {
+                self.collect(a, inverse)?;
+                self.collect(b, !inverse)
+            }
+            (Token::Op('-'), [a]) => {
+                self.coefficient = -&self.coefficient;
+                self.collect(a, inverse)
+            }
+            // "x%" is x / 100, and "x% of y" is x / 100 * y
+            (Token::Op('%'), [a, rest @ ..]) => {
+                self.scale(&hundredth, inverse)?;
+                self.collect(a, inverse)?;
+                rest.iter().try_for_each(|b| self.collect(b, inverse))
+            }
+            _ => match fold(ast) {
+                Some(r) => self.scale(&r, inverse),
+                None => {
+                    let (base, exp) = match (&ast.tok, ast.children.as_slice()) {
+                        (Token::Op('^'), [base, exp]) => match fold(exp) {
+                            Some(exp) => (base, exp),
+                            None => (ast, one()),
+                        },
+                        _ => (ast, one()),
+                    };
+                    let exp = if inverse { -&exp } else { exp };
+                    self.multiply(base, exp);
+                    Some(())
+                }
+            },
+        }
+    }
+
+    fn scale(&mut self, r: &Rational, inverse: bool) -> Option<()> {
+        let r = if inverse { r.recip()? } else { r.clone() };
+        self.coefficient = &self.coefficient * &r;
+        Some(())
+    }
+
+    /// Multiply by `base^exp`, adding to the power of `base` if it's
+    /// already a factor.
+    fn multiply(&mut self, base: &Ast, exp: Rational) {
+        let key = base.to_string();
+        match self.factors.iter_mut().find(|(b, _)| b.to_string() == key) {
+            Some((_, e)) => *e = &*e + &exp,
+            None => self.factors.push((base.clone(), exp)),
+        }
+    }
+
+    /// Identifies products that differ only in their coefficient,
+    /// whatever order the factors are in.
+    fn key(&self) -> String {
+        let mut factors: Vec<String> = self
+            .factors
+            .iter()
+            .map(|(base, exp)| format!("({})^({})", base, exp))
+            .collect();
+        factors.sort();
+        factors.join(" * ")
+    }
+
+    fn to_ast(&self) -> Ast {
+        if self.coefficient.is_zero() {
+            return Ast::new(Token::int(0));
+        }
+        let c = self.coefficient.abs();
+        let (mut top, mut bottom) = (vec![], vec![]);
+        if c.is_integer() || decimal(&c).is_some() {
+            if c != one() {
+                top.push(number(&c));
+            }
+        } else {
+            if !c.numer().is_one() {
+                top.push(Ast::new(Token::Int(c.numer().clone())));
+            }
+            bottom.push(Ast::new(Token::Int(c.denom().clone())));
+        }
+        for (base, exp) in &self.factors {
+            match exp.is_negative() {
+                false => top.push(power_of(base, exp)),
+                true => bottom.push(power_of(base, &-exp)),
+            }
+        }
+        if top.is_empty() {
+            top.push(Ast::new(Token::int(1)));
+        }
+        if self.coefficient.is_negative() {
+            top[0] = negate(top[0].clone());
+        }
+        let times = |a, b| Ast::from(Token::Op('*'), vec![a, b]);
+        let top = top.into_iter().reduce(times).unwrap();
+        match bottom.into_iter().reduce(times) {
+            Some(bottom) => Ast::from(Token::Op('/'), vec![top, bottom]),
+            None => top,
+        }
+    }
+}
+
+fn power_of(base: &Ast, exp: &Rational) -> Ast {
+    match *exp == one() {
+        true => base.clone(),
+        false => Ast::from(Token::Op('^'), vec![base.clone(), number(exp)]),
+    }
+}
+
+fn negate(ast: Ast) -> Ast {
+    match ast.tok {
+        Token::Int(n) => Ast::new(Token::Int(-&n)),
+        Token::Float(d) => Ast::new(Token::Float(d.neg())),
+        _ => Ast::from(Token::Op('-'), vec![ast]),
+    }
+}
+
+/// Collect like terms, so `2 * x + y - x` is `x + y`. Numbers are
+/// added up and go last, unless the sum would start with a minus
+/// sign otherwise, and `-x + 1` is `1 - x`.
+fn sum(ast: &Ast) -> Option<Ast> {
+    let mut terms = vec![];
+    let mut constant = Rational::from_int(0);
+    collect_terms(ast, false, &mut terms, &mut constant)?;
+    if !constant.is_zero() {
+        terms.push(Product {
+            coefficient: constant,
+            factors: vec![],
+        });
+    }
+    terms.retain(|t| !t.coefficient.is_zero());
+    if let Some(i) = terms.iter().position(|t| !t.coefficient.is_negative()) {
+        let first = terms.remove(i);
+        terms.insert(0, first);
+    }
+    let mut res: Option<Ast> = None;
+    for mut term in terms {
+        res = Some(match res {
+            None => term.to_ast(),
+            Some(acc) if term.coefficient.is_negative() => {
+                term.coefficient = -&term.coefficient;
+                Ast::from(Token::Op('-'), vec![acc, term.to_ast()])
+            }
+            Some(acc) => Ast::from(Token::Op('+'), vec![acc, term.to_ast()]),
+        });
+    }
+    Some(res.unwrap_or_else(|| Ast::new(Token::int(0))))
+}
+
+fn collect_terms(
+    ast: &Ast,
+    negative: bool,
+    terms: &mut Vec<Product>,
+    constant: &mut Rational,
+) -> Option<()> {
+    match (&ast.tok, ast.children.as_slice()) {
+        (Token::Op('+'), [a, b]) => {
+            collect_terms(a, negative, terms, constant)?;
+            collect_terms(b, negative, terms, constant)
+        }
+        (Token::Op('-'), [a, b]) => {
+            collect_terms(a, negative, terms, constant)?;
+            collect_terms(b, !negative, terms, constant)
+        }
+        (Token::Op('-'), [a]) => collect_terms(a, !negative, terms, constant),
+        _ => {
+            let mut term = Product::of(ast)?;
+            if negative {
+                term.coefficient = -&term.coefficient;
+            }
+            if term.factors.is_empty() {
+                *constant = &*constant + &term.coefficient;
+                return Some(());
+            }
+            let key = term.key();
+            match terms.iter_mut().find(|t| t.key() == key) {
+                Some(t) => t.coefficient = &t.coefficient + &term.coefficient,
+                None => terms.push(term),
+            }
+            Some(())
+        }
+    }
+}
+
+/// Simplify `base^exp` when the exponent is a number: `x^1` is `x`,
+/// `(x^2)^3` is `x^6` and `(2 * x)^2` is `4 * x^2`.
+fn power(ast: &Ast) -> Option<Ast> {
+    let (base, exp) = (&ast.children[0], &ast.children[1]);
+    let exp = fold(exp)?;
+    if exp.is_zero() {
+        return Some(Ast::new(Token::int(1)));
+    }
+    let n = exp.numer().to_i64().filter(|_| exp.is_integer());
+    let product = match (&base.tok, base.children.as_slice(), n) {
+        (Token::Op('^' | '*' | '/' | '-'), _, Some(n)) => {
+            let mut product = Product::of(base)?;
+            product.coefficient = product.coefficient.checked_pow(n)?;
+            for (_, e) in &mut product.factors {
+                *e = &*e * &exp;
+            }
+            product
+        }
+        _ => Product {
+            coefficient: one(),
+            factors: vec![(base.clone(), exp)],
+        },
+    };
+    Some(product.to_ast())
+}
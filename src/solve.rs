@@ -0,0 +1,474 @@
+use crate::ast::{self, Ast};
+use crate::builtins;
+use crate::calculus;
+use crate::env::Env;
+use crate::error::EvalErrorKind;
+use crate::function::Function;
+use crate::lex::Token;
+use crate::rational::Rational;
+use crate::units;
+use crate::value::Value;
+
+/// Where roots are looked for when there's no formula for them
+/// and `solve` isn't given a range.
+const SEARCH: (f64, f64) = (-100.0, 100.0);
+/// The number of pieces the range is cut into to find
+/// where the sign changes.
+const STEPS: usize = 2000;
+/// The highest power of a polynomial that is expanded.
+const MAX_DEGREE: i64 = 64;
+
+/// `solve(lhs = rhs, x)`, the real roots of an equation in increasing
+/// order. Without an equal sign the expression is solved for zero, and
+/// `solve(f, x, a, b)` only looks for roots between `a` and `b`.
+pub(crate) fn solve(args: &[Ast], env: &mut Env) -> Result<Value, EvalErrorKind> {
+    let (eq, var, range) = match args {
+        [eq, var] => (eq, var, None),
+        [eq, var, a, b] => (eq, var, Some((a, b))),
+        _ => {
+            return Err(EvalErrorKind::Arity(format!(
+                "solve takes 2 or 4 arguments but {} {} given",
+                args.len(),
+                if args.len() == 1 { "was" } else { "were" },
+            )))
+        }
+    };
+    let var = calculus::variable(var)?;
+    let range = match range {
+        Some((a, b)) => Some((bound(a, env)?, bound(b, env)?)),
+        None => None,
+    };
+    roots(eq, &var, range, env)
+}
+
+fn bound(ast: &Ast, env: &mut Env) -> Result<f64, EvalErrorKind> {
+    let val = ast::eval(ast, env).map_err(|err| err.kind)?;
+    val.as_f64()
+        .ok_or_else(|| EvalErrorKind::Type(format!("expected a real number but got {}", val)))
+}
+
+/// Evaluate an equation on its own, like `2 * x + 3 = 11`,
+/// by solving it for the one variable that isn't defined.
+/// Names of units are unknowns too, like `t` in `2 * t + 3 = 11`,
+/// but only when the equation has no other unknown.
+pub(crate) fn equation(ast: &Ast, env: &mut Env) -> Result<Value, EvalErrorKind> {
+    let mut unknowns = vec![];
+    find_unknowns(ast, env, &mut unknowns);
+    let (units, mut unknowns): (Vec<_>, Vec<_>) = unknowns
+        .into_iter()
+        .partition(|name| units::lookup(name).is_some());
+    if unknowns.is_empty() {
+        unknowns = units;
+    }
+    match unknowns.as_slice() {
+        [var] => roots(ast, &var.clone(), None, env),
+        [] => Err(EvalErrorKind::Symbolic(format!(
+            "{} has no unknown to solve for",
+            ast
+        ))),
+        [a, b, ..] => Err(EvalErrorKind::Symbolic(format!(
+            "cannot solve {} for both {} and {}",
+            ast, a, b
+        ))),
+    }
+}
+
+fn find_unknowns(ast: &Ast, env: &Env, names: &mut Vec<String>) {
+    match (&ast.tok, ast.children.as_slice()) {
+        (Token::Ident(name), []) => {
+            let known = env.get(name).is_some() || builtins::constant(name, env).is_some();
+            if !known && !names.contains(name) {
+                names.push(name.clone());
+            }
+        }
+        // the variable of a series only stands for its terms
+        (Token::Func(name), [k, rest @ ..])
+            if (name == "sum" || name == "prod") && rest.len() == 3 =>
+        {
+            let mut inner = vec![];
+            for child in rest {
+                find_unknowns(child, env, &mut inner);
+            }
+            for name in inner {
+                if k.to_string() != name && !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+        }
+        _ => {
+            for child in &ast.children {
+                find_unknowns(child, env, names);
+            }
+        }
+    }
+}
+
+/// The real roots of `eq` in `var`, exactly for linear and quadratic
+/// equations, and numerically for others.
+fn roots(
+    eq: &Ast,
+    var: &str,
+    range: Option<(f64, f64)>,
+    env: &mut Env,
+) -> Result<Value, EvalErrorKind> {
+    let f = match (&eq.tok, eq.children.as_slice()) {
+        (Token::Equal, [lhs, rhs]) => Ast::from(Token::Op('-'), vec![lhs.clone(), rhs.clone()]),
+        _ => eq.clone(),
+    };
+    let describe = || match eq.tok {
+        Token::Equal => eq.to_string(),
+        _ => format!("{} = 0", eq),
+    };
+    if !mentions(&f, var, env) {
+        return Err(EvalErrorKind::Symbolic(format!(
+            "{} does not appear in {}",
+            var,
+            describe()
+        )));
+    }
+    // a name that isn't defined would otherwise look like there are no roots
+    let func = Function::lambda(vec![String::from(var)], f.clone(), env);
+    if let Err(err @ (EvalErrorKind::UndefinedVariable(_) | EvalErrorKind::UnknownFunction(_))) =
+        func.apply(vec![Value::Rational(Rational::from_int(1))], env)
+    {
+        return Err(err);
+    }
+    let mut roots = match polynomial(&f, var, env) {
+        Some(p) if p.len() == 1 && p[0].is_zero() => {
+            return Err(EvalErrorKind::NoSolution(format!(
+                "every value of {} solves {}",
+                var,
+                describe()
+            )))
+        }
+        Some(p) => polynomial_roots(&p),
+        None => {
+            let (lo, hi) = range.unwrap_or(SEARCH);
+            let roots = numeric_roots(&f, var, lo, hi, env);
+            if roots.is_empty() {
+                return Err(EvalErrorKind::NoSolution(format!(
+                    "no root of {} was found between {} and {}",
+                    describe(),
+                    lo,
+                    hi
+                )));
+            }
+            roots
+        }
+    };
+    let between = match range {
+        Some((lo, hi)) => {
+            roots.retain(|x| x.as_f64().is_some_and(|x| lo <= x && x <= hi));
+            format!(" between {} and {}", lo, hi)
+        }
+        None => String::new(),
+    };
+    if roots.is_empty() {
+        return Err(EvalErrorKind::NoSolution(format!(
+            "{} has no real roots{}",
+            describe(),
+            between
+        )));
+    }
+    Ok(Value::List(roots))
+}
+
+/// Whether `ast` could change with `var`. Calls to user defined
+/// functions might, through the variables they use.
+fn mentions(ast: &Ast, var: &str, env: &Env) -> bool {
+    let uses = match &ast.tok {
+        Token::Ident(name) => name == var,
+        Token::Func(name) => env.get(name).is_some(),
+        _ => false,
+    };
+    uses || ast.children.iter().any(|child| mentions(child, var, env))
+}
+
+/// The coefficients of `ast` as a polynomial in `var` with exact
+/// coefficients, lowest power first, if it is one.
+fn polynomial(ast: &Ast, var: &str, env: &mut Env) -> Option<Vec<Rational>> {
+    if !mentions(ast, var, env) {
+        let val = ast::eval(ast, env).ok()?;
+        return Some(vec![val.to_rational()?]);
+    }
+    let hundredth = vec![Rational::new(1, 100).unwrap()];
+    let args = &ast.children;
+    match (&ast.tok, args.as_slice()) {
+        (Token::Ident(_), []) => Some(vec![Rational::from_int(0), Rational::from_int(1)]),
+        (Token::Op('+'), [a, b]) => Some(add(&polynomial(a, var, env)?, &polynomial(b, var, env)?)),
+        (Token::Op('-'), [a, b]) => {
+            let b = mul(&polynomial(b, var, env)?, &[Rational::from_int(-1)]);
+            Some(add(&polynomial(a, var, env)?, &b))
+        }
+        (Token::Op('-'), [a]) => Some(mul(&polynomial(a, var, env)?, &[Rational::from_int(-1)])),
+        (Token::Op('*'), [a, b]) => Some(mul(&polynomial(a, var, env)?, &polynomial(b, var, env)?)),
+        (Token::Op('/'), [a, b]) => match polynomial(b, var, env)?.as_slice() {
+            [d] => Some(mul(&polynomial(a, var, env)?, &[d.recip()?])),
+            _ => None,
+        },
+        (Token::Op('^'), [a, n]) => {
+            let n = match polynomial(n, var, env)?.as_slice() {
+                [n] if n.is_integer() => n.numer().to_i64()?,
+                _ => return None,
+            };
+            if !(0..=MAX_DEGREE).contains(&n) {
+                return None;
+            }
+            let a = polynomial(a, var, env)?;
+            let mut res = vec![Rational::from_int(1)];
+            for _ in 0..n {
+                res = mul(&res, &a);
+            }
+            Some(res)
+        }
+        (Token::Op('%'), [a]) => Some(mul(&polynomial(a, var, env)?, &hundredth)),
+        (Token::Op('%'), [a, b]) => {
+            let product = mul(&polynomial(a, var, env)?, &polynomial(b, var, env)?);
+            Some(mul(&product, &hundredth))
+        }
+        _ => None,
+    }
+    .map(trim)
+}
+
+fn trim(mut p: Vec<Rational>) -> Vec<Rational> {
+    while p.len() > 1 && p.last().is_some_and(Rational::is_zero) {
+        p.pop();
+    }
+    p
+}
+
+fn add(p: &[Rational], q: &[Rational]) -> Vec<Rational> {
+    let zero = Rational::from_int(0);
+    (0..p.len().max(q.len()))
+        .map(|i| p.get(i).unwrap_or(&zero) + q.get(i).unwrap_or(&zero))
+        .collect()
+}
+
+fn mul(p: &[Rational], q: &[Rational]) -> Vec<Rational> {
+    let mut res = vec![Rational::from_int(0); p.len() + q.len() - 1];
+    for (i, a) in p.iter().enumerate() {
+        for (j, b) in q.iter().enumerate() {
+            res[i + j] = &res[i + j] + &(a * b);
+        }
+    }
+    res
+}
+
+/// The value of the polynomial `p` at `x`.
+fn eval_exact(p: &[Rational], x: &Rational) -> Rational {
+    p.iter()
+        .rev()
+        .fold(Rational::from_int(0), |acc, c| &(&acc * x) + c)
+}
+
+fn eval_float(p: &[f64], x: f64) -> f64 {
+    p.iter().rev().fold(0.0, |acc, c| acc * x + c)
+}
+
+/// The real roots of a polynomial that isn't zero, in order.
+fn polynomial_roots(p: &[Rational]) -> Vec<Value> {
+    match p {
+        [_] => vec![],
+        // a + bx = 0
+        [a, b] => vec![Value::Rational((-a).checked_div(b).unwrap())],
+        [c, b, a] => quadratic(a, b, c),
+        _ => {
+            let floats: Vec<f64> = p.iter().map(Rational::to_f64).collect();
+            real_roots(&floats)
+                .into_iter()
+                .map(|x| exact(x, |r| eval_exact(p, r).is_zero()))
+                .collect()
+        }
+    }
+}
+
+/// The roots of `ax^2 + bx + c`.
+fn quadratic(a: &Rational, b: &Rational, c: &Rational) -> Vec<Value> {
+    let four = Rational::from_int(4);
+    let disc = &(b * b) - &(&(&four * a) * c);
+    let two_a = &Rational::from_int(2) * a;
+    if disc.is_negative() {
+        return vec![];
+    }
+    if disc.is_zero() {
+        return vec![Value::Rational((-b).checked_div(&two_a).unwrap())];
+    }
+    let mut roots: Vec<Value> = match disc.sqrt() {
+        Some(root) => vec![
+            (&-b - &root).checked_div(&two_a).unwrap(),
+            (&-b + &root).checked_div(&two_a).unwrap(),
+        ]
+        .into_iter()
+        .map(Value::Rational)
+        .collect(),
+        None if b.is_zero() => {
+            let x = (-c).checked_div(a).unwrap().to_f64().sqrt();
+            vec![Value::Float(-x), Value::Float(x)]
+        }
+        None => {
+            // q avoids subtracting numbers that are nearly equal
+            let (a, b, c) = (a.to_f64(), b.to_f64(), c.to_f64());
+            let q = -(b + b.signum() * disc.to_f64().sqrt()) / 2.0;
+            vec![Value::Float(q / a), Value::Float(c / q)]
+        }
+    };
+    roots.sort_by(|x, y| x.as_f64().unwrap().total_cmp(&y.as_f64().unwrap()));
+    roots
+}
+
+/// The real roots of a polynomial with float coefficients, found
+/// between the roots of its derivative, where it can only cross zero
+/// once, or at them for roots where it only touches zero.
+fn real_roots(p: &[f64]) -> Vec<f64> {
+    let n = p.len() - 1;
+    if n == 1 {
+        return vec![-p[0] / p[1]];
+    }
+    let slope: Vec<f64> = (1..=n).map(|i| p[i] * i as f64).collect();
+    // every root is smaller than this in size
+    let bound = 1.0 + p[..n].iter().map(|c| (c / p[n]).abs()).fold(0.0, f64::max);
+    let mut points = vec![-bound];
+    points.extend(real_roots(&slope).into_iter().filter(|x| x.abs() < bound));
+    points.push(bound);
+    let mut roots = vec![];
+    for pair in points.windows(2) {
+        let (a, b) = (pair[0], pair[1]);
+        let (fa, fb) = (eval_float(p, a), eval_float(p, b));
+        if fa.signum() != fb.signum() && fa != 0.0 && fb != 0.0 {
+            roots.push(refine(|x| (eval_float(p, x), eval_float(&slope, x)), a, b));
+        }
+    }
+    for &x in &points {
+        let scale: f64 = p
+            .iter()
+            .enumerate()
+            .map(|(i, c)| (c * x.powi(i as i32)).abs())
+            .sum();
+        if eval_float(p, x).abs() <= scale * 1e-12 {
+            roots.push(x);
+        }
+    }
+    sorted(roots)
+}
+
+/// The roots of the expression `f` in `var` between `lo` and `hi`,
+/// looked for where it changes sign or comes close to zero.
+fn numeric_roots(f: &Ast, var: &str, lo: f64, hi: f64, env: &mut Env) -> Vec<Value> {
+    let func = Function::lambda(vec![String::from(var)], f.clone(), env);
+    let slope = calculus::derivative(f, var, env)
+        .ok()
+        .map(|d| Function::lambda(vec![String::from(var)], d, env));
+    let mut curve = |x: f64| {
+        let y = at(&func, Value::Float(x), env);
+        let dy = slope
+            .as_ref()
+            .map_or(f64::NAN, |d| at(d, Value::Float(x), env));
+        (y, dy)
+    };
+    let step = (hi - lo) / STEPS as f64;
+    let xs: Vec<f64> = (0..=STEPS).map(|i| lo + step * i as f64).collect();
+    let ys: Vec<f64> = xs.iter().map(|&x| curve(x).0).collect();
+    let mut roots = vec![];
+    for i in 0..STEPS {
+        let (a, b, fa, fb) = (xs[i], xs[i + 1], ys[i], ys[i + 1]);
+        if fa == 0.0 {
+            roots.push(a);
+        } else if fa.signum() == -fb.signum() && fb != 0.0 {
+            let x = refine(&mut curve, a, b);
+            // a sign change at a pole isn't a root
+            if curve(x).0.abs() <= 1e-9 * fa.abs().max(fb.abs()).max(1.0) {
+                roots.push(x);
+            }
+        } else if i > 0 && fa.abs() < ys[i - 1].abs() && fa.abs() < fb.abs() {
+            // the curve might touch zero near a low point
+            if let Some(x) = touching(&mut curve, xs[i - 1], b) {
+                roots.push(x);
+            }
+        }
+    }
+    if ys[STEPS] == 0.0 {
+        roots.push(hi);
+    }
+    sorted(roots)
+        .into_iter()
+        .map(|x| exact(x, |r| at(&func, Value::Rational(r.clone()), env) == 0.0))
+        .collect()
+}
+
+/// The value of `func` at `x` as a float, NaN if it isn't a real number.
+fn at(func: &Function, x: Value, env: &mut Env) -> f64 {
+    match func.apply(vec![x], env) {
+        Ok(y) if y.is_zero() => 0.0,
+        Ok(y) => y.as_f64().unwrap_or(f64::NAN),
+        Err(_) => f64::NAN,
+    }
+}
+
+/// Narrow down the root of `f` between `a` and `b`, where it has
+/// opposite signs, with Newton's method where it stays between them
+/// and halving the range where it doesn't. `f` gives the value and
+/// the slope, which can be NaN.
+fn refine(mut f: impl FnMut(f64) -> (f64, f64), mut a: f64, mut b: f64) -> f64 {
+    let negative = f(a).0 < 0.0;
+    let mut x = (a + b) / 2.0;
+    for _ in 0..200 {
+        let (y, dy) = f(x);
+        if y == 0.0 {
+            return x;
+        }
+        if (y < 0.0) == negative {
+            a = x;
+        } else {
+            b = x;
+        }
+        let step = x - y / dy;
+        let next = match step.is_finite() && step >= a.min(b) && step <= a.max(b) {
+            true => step,
+            false => (a + b) / 2.0,
+        };
+        if (next - x).abs() <= 4.0 * f64::EPSILON * x.abs().max(f64::MIN_POSITIVE) {
+            // the last steps are rounding, so keep the closer one
+            return match f(next).0.abs() < y.abs() {
+                true => next,
+                false => x,
+            };
+        }
+        x = next;
+    }
+    x
+}
+
+/// A root where `f` touches zero between `a` and `b` without
+/// crossing it, found where its slope changes sign.
+fn touching(mut f: impl FnMut(f64) -> (f64, f64), a: f64, b: f64) -> Option<f64> {
+    let (da, db) = (f(a).1, f(b).1);
+    if da.is_nan() || db.is_nan() || da.signum() == db.signum() {
+        return None;
+    }
+    let x = refine(|x| (f(x).1, f64::NAN), a, b);
+    (f(x).0.abs() <= 1e-12).then_some(x)
+}
+
+/// Sort roots and drop ones that are found twice.
+fn sorted(mut roots: Vec<f64>) -> Vec<f64> {
+    roots.sort_by(f64::total_cmp);
+    roots.dedup_by(|x, y| (*x - *y).abs() <= 1e-9 * y.abs().max(1.0));
+    roots
+}
+
+/// The root as a fraction if a simple one near it is exactly a root,
+/// which `is_root` tells.
+fn exact(x: f64, mut is_root: impl FnMut(&Rational) -> bool) -> Value {
+    for den in 1..=12 {
+        let num = (x * den as f64).round();
+        if num.abs() > 1e15 {
+            break;
+        }
+        let r = Rational::new(num as i64, den).unwrap();
+        if (r.to_f64() - x).abs() <= 1e-9 * x.abs().max(1.0) && is_root(&r) {
+            return Value::Rational(r);
+        }
+    }
+    Value::Float(x)
+}
@@ -0,0 +1,600 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::slice;
+
+use crate::builtins::Builtin;
+use crate::decimal::Context;
+use crate::error::EvalErrorKind;
+use crate::rational::Rational;
+use crate::value::Value;
+
+/// The powers of the base dimensions: length, mass, time,
+/// electric current, temperature, amount of substance,
+/// luminous intensity and information.
+type Dim = [i32; 8];
+
+const NONE: Dim = [0, 0, 0, 0, 0, 0, 0, 0];
+const LENGTH: Dim = [1, 0, 0, 0, 0, 0, 0, 0];
+const MASS: Dim = [0, 1, 0, 0, 0, 0, 0, 0];
+const TIME: Dim = [0, 0, 1, 0, 0, 0, 0, 0];
+const TEMPERATURE: Dim = [0, 0, 0, 0, 1, 0, 0, 0];
+const INFORMATION: Dim = [0, 0, 0, 0, 0, 0, 0, 1];
+const AREA: Dim = [2, 0, 0, 0, 0, 0, 0, 0];
+const VOLUME: Dim = [3, 0, 0, 0, 0, 0, 0, 0];
+const SPEED: Dim = [1, 0, -1, 0, 0, 0, 0, 0];
+const FORCE: Dim = [1, 1, -2, 0, 0, 0, 0, 0];
+const ENERGY: Dim = [2, 1, -2, 0, 0, 0, 0, 0];
+const POWER: Dim = [2, 1, -3, 0, 0, 0, 0, 0];
+const PRESSURE: Dim = [-1, 1, -2, 0, 0, 0, 0, 0];
+
+/// A named unit, given by its dimension and its size in SI base units.
+#[derive(Debug, PartialEq)]
+pub struct UnitDef {
+    name: &'static str,
+    dim: Dim,
+    /// The size is `size / den`, which keeps sizes like the
+    /// 5/9 of a degree Fahrenheit exact.
+    size: f64,
+    den: f64,
+    /// How far zero on the unit's scale is above the zero of the SI
+    /// unit, counted in the unit itself. Only temperatures have one.
+    offset: f64,
+    /// Whether SI prefixes like the `k` in `km` can be put in front.
+    prefixed: bool,
+}
+
+const fn si(name: &'static str, dim: Dim, size: f64) -> UnitDef {
+    UnitDef {
+        name,
+        dim,
+        size,
+        den: 1.0,
+        offset: 0.0,
+        prefixed: true,
+    }
+}
+
+const fn plain(name: &'static str, dim: Dim, size: f64) -> UnitDef {
+    UnitDef {
+        prefixed: false,
+        ..si(name, dim, size)
+    }
+}
+
+/// A temperature scale with degrees of `size / den` kelvin,
+/// where absolute zero is at `-offset`.
+const fn scale(name: &'static str, size: f64, den: f64, offset: f64) -> UnitDef {
+    UnitDef {
+        den,
+        offset,
+        ..plain(name, TEMPERATURE, size)
+    }
+}
+
+static UNITS: &[UnitDef] = &[
+    // SI base units, with the gram standing in for the kilogram
+    si("m", LENGTH, 1.0),
+    si("g", MASS, 1e-3),
+    si("s", TIME, 1.0),
+    si("A", [0, 0, 0, 1, 0, 0, 0, 0], 1.0),
+    si("K", TEMPERATURE, 1.0),
+    si("mol", [0, 0, 0, 0, 0, 1, 0, 0], 1.0),
+    si("cd", [0, 0, 0, 0, 0, 0, 1, 0], 1.0),
+    // derived SI units
+    si("N", FORCE, 1.0),
+    si("J", ENERGY, 1.0),
+    si("W", POWER, 1.0),
+    si("Pa", PRESSURE, 1.0),
+    si("Hz", [0, 0, -1, 0, 0, 0, 0, 0], 1.0),
+    si("C", [0, 0, 1, 1, 0, 0, 0, 0], 1.0),
+    si("V", [2, 1, -3, -1, 0, 0, 0, 0], 1.0),
+    si("Ω", [2, 1, -3, -2, 0, 0, 0, 0], 1.0),
+    si("ohm", [2, 1, -3, -2, 0, 0, 0, 0], 1.0),
+    si("L", VOLUME, 1e-3),
+    si("Wh", ENERGY, 3600.0),
+    si("eV", ENERGY, 1.602176634e-19),
+    si("cal", ENERGY, 4.184),
+    // temperature scales
+    scale("degC", 1.0, 1.0, 273.15),
+    scale("degF", 5.0, 9.0, 459.67),
+    // information
+    si("bit", INFORMATION, 1.0),
+    si("B", INFORMATION, 8.0),
+    // time
+    plain("min", TIME, 60.0),
+    plain("h", TIME, 3600.0),
+    plain("hr", TIME, 3600.0),
+    plain("day", TIME, 86400.0),
+    plain("week", TIME, 604800.0),
+    plain("yr", TIME, 31557600.0),
+    // imperial and US customary units
+    plain("in", LENGTH, 0.0254),
+    plain("ft", LENGTH, 0.3048),
+    plain("yd", LENGTH, 0.9144),
+    plain("mi", LENGTH, 1609.344),
+    plain("acre", AREA, 4046.8564224),
+    plain("gal", VOLUME, 0.003785411784),
+    plain("oz", MASS, 0.028349523125),
+    plain("lb", MASS, 0.45359237),
+    plain("t", MASS, 1000.0),
+    plain("mph", SPEED, 0.44704),
+    plain("lbf", FORCE, 4.4482216152605),
+    plain("psi", PRESSURE, 6894.757293168361),
+    plain("bar", PRESSURE, 1e5),
+    plain("atm", PRESSURE, 101325.0),
+    plain("hp", POWER, 745.6998715822702),
+];
+
+/// The units that products of SI units are simplified to.
+const DERIVED: &[&str] = &[
+    "m", "kg", "s", "A", "K", "mol", "cd", "bit", "N", "J", "W", "Pa", "C", "V", "Ω",
+];
+
+#[derive(Debug, PartialEq)]
+struct Prefix {
+    name: &'static str,
+    size: f64,
+}
+
+const fn prefix(name: &'static str, size: f64) -> Prefix {
+    Prefix { name, size }
+}
+
+static PREFIXES: &[Prefix] = &[
+    prefix("T", 1e12),
+    prefix("G", 1e9),
+    prefix("M", 1e6),
+    prefix("k", 1e3),
+    prefix("h", 1e2),
+    prefix("d", 1e-1),
+    prefix("c", 1e-2),
+    prefix("m", 1e-3),
+    prefix("u", 1e-6),
+    prefix("µ", 1e-6),
+    prefix("n", 1e-9),
+    prefix("p", 1e-12),
+    // binary prefixes, as in GiB
+    prefix("Ki", 1024.0),
+    prefix("Mi", 1048576.0),
+    prefix("Gi", 1073741824.0),
+    prefix("Ti", 1099511627776.0),
+];
+
+/// An exact copy of a number from the unit tables.
+fn exact(f: f64) -> Rational {
+    Rational::from_f64(f).unwrap()
+}
+
+/// A unit raised to a power, like the `s^2` in `m/s^2`.
+#[derive(Debug, Clone, PartialEq)]
+struct Factor {
+    prefix: Option<&'static Prefix>,
+    def: &'static UnitDef,
+    power: i32,
+}
+
+impl Factor {
+    /// The size of the unit without the power, in SI base units.
+    fn size(&self) -> Rational {
+        let def = self.def;
+        let size = exact(def.size).checked_div(&exact(def.den)).unwrap();
+        match self.prefix {
+            Some(p) => &exact(p.size) * &size,
+            None => size,
+        }
+    }
+
+    fn same_unit(&self, other: &Factor) -> bool {
+        self.prefix == other.prefix && self.def == other.def
+    }
+}
+
+impl fmt::Display for Factor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(p) = self.prefix {
+            write!(f, "{}", p.name)?;
+        }
+        write!(f, "{}", self.def.name)?;
+        match self.power {
+            1 => Ok(()),
+            p => write!(f, "^{}", p),
+        }
+    }
+}
+
+/// A product of powers of units, like `kg*m/s^2`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Unit {
+    factors: Vec<Factor>,
+}
+
+impl Unit {
+    /// Look up a unit by its name, which may start with an SI prefix.
+    pub fn parse(name: &str) -> Option<Unit> {
+        let factor = |prefix, def| Unit {
+            factors: vec![Factor {
+                prefix,
+                def,
+                power: 1,
+            }],
+        };
+        if let Some(def) = UNITS.iter().find(|u| u.name == name) {
+            return Some(factor(None, def));
+        }
+        PREFIXES.iter().find_map(|p| {
+            let rest = name.strip_prefix(p.name)?;
+            let def = UNITS.iter().find(|u| u.prefixed && u.name == rest)?;
+            Some(factor(Some(p), def))
+        })
+    }
+
+    pub fn is_dimensionless(&self) -> bool {
+        self.dim() == NONE
+    }
+
+    fn dim(&self) -> Dim {
+        let mut dim = NONE;
+        for f in &self.factors {
+            for (d, u) in dim.iter_mut().zip(f.def.dim.iter()) {
+                *d += u * f.power;
+            }
+        }
+        dim
+    }
+
+    /// The size of the unit in SI base units.
+    fn size(&self) -> Option<Rational> {
+        let mut size = Rational::from_int(1);
+        for f in &self.factors {
+            size = &size * &f.size().checked_pow(f.power.into())?;
+        }
+        Some(size)
+    }
+
+    /// The product of two units along with the number the magnitude has
+    /// to be multiplied by. Units of the same dimension are merged into
+    /// the first one, so `km * m` is in `km^2`.
+    fn mul(&self, other: &Unit) -> Option<(Unit, Rational)> {
+        let mut factors = self.factors.clone();
+        let mut scale = Rational::from_int(1);
+        for f in &other.factors {
+            match factors.iter_mut().find(|e| e.def.dim == f.def.dim) {
+                Some(e) => {
+                    if !e.same_unit(f) {
+                        let ratio = f.size().checked_div(&e.size())?;
+                        scale = &scale * &ratio.checked_pow(f.power.into())?;
+                    }
+                    e.power += f.power;
+                }
+                None => factors.push(f.clone()),
+            }
+        }
+        factors.retain(|f| f.power != 0);
+        Some((Unit { factors }, scale))
+    }
+
+    /// The offset of a lone temperature scale like `degC`, in its own
+    /// units. Temperatures inside products are differences, which
+    /// don't depend on where the scale starts.
+    fn offset(&self) -> Rational {
+        match self.factors.as_slice() {
+            [f] if f.power == 1 => exact(f.def.offset),
+            _ => Rational::from_int(0),
+        }
+    }
+
+    fn recip(&self) -> Unit {
+        let mut unit = self.clone();
+        for f in &mut unit.factors {
+            f.power = -f.power;
+        }
+        unit
+    }
+
+    /// The named SI unit that a product of units is equal to, like `N`
+    /// for `kg*m/s^2` or `W` for `kWh/h`, along with the number the
+    /// magnitude has to be multiplied by.
+    fn simplify(&self) -> Option<(Unit, Rational)> {
+        if self.factors.len() < 2 {
+            return None;
+        }
+        let dim = self.dim();
+        let unit = DERIVED
+            .iter()
+            .filter_map(|name| Unit::parse(name))
+            .find(|u| u.dim() == dim)?;
+        Some((unit, self.size()?))
+    }
+}
+
+impl fmt::Display for Unit {
+    /// Written as `kg*m/s^2`, or with negative powers like
+    /// `s^-1` when there is nothing above the line.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let join = |factors: Vec<Factor>| {
+            factors
+                .iter()
+                .map(|f| f.to_string())
+                .collect::<Vec<_>>()
+                .join("*")
+        };
+        let (num, den): (Vec<Factor>, Vec<Factor>) =
+            self.factors.iter().cloned().partition(|f| f.power > 0);
+        if num.is_empty() {
+            return write!(f, "{}", join(den));
+        }
+        write!(f, "{}", join(num))?;
+        let den: Vec<Factor> = den
+            .into_iter()
+            .map(|f| Factor {
+                power: -f.power,
+                ..f
+            })
+            .collect();
+        match den.len() {
+            0 => Ok(()),
+            1 => write!(f, "/{}", join(den)),
+            _ => write!(f, "/({})", join(den)),
+        }
+    }
+}
+
+/// A number along with the unit it's measured in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quantity {
+    value: Box<Value>,
+    unit: Unit,
+}
+
+impl Quantity {
+    /// The number of units.
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+
+    pub fn unit(&self) -> &Unit {
+        &self.unit
+    }
+
+    pub(crate) fn map<F: FnOnce(&Value) -> Value>(&self, f: F) -> Quantity {
+        Quantity {
+            value: Box::new(f(&self.value)),
+            unit: self.unit.clone(),
+        }
+    }
+}
+
+impl fmt::Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.value, self.unit)
+    }
+}
+
+/// One of the unit with the given name.
+pub fn lookup(name: &str) -> Option<Value> {
+    Unit::parse(name).map(|unit| {
+        Value::Quantity(Quantity {
+            value: Box::new(Value::Rational(Rational::from_int(1))),
+            unit,
+        })
+    })
+}
+
+/// Build a value measured in `unit`, dropping the unit if it
+/// has no dimension.
+fn quantity(value: Value, unit: Unit, ctx: &Context) -> Result<Value, EvalErrorKind> {
+    if unit.factors.is_empty() {
+        return Ok(value);
+    }
+    if unit.is_dimensionless() {
+        // like Hz*s, which is a plain number
+        return value.mul(&Value::Rational(size(&unit)?), ctx);
+    }
+    Ok(Value::Quantity(Quantity {
+        value: Box::new(value),
+        unit,
+    }))
+}
+
+/// The magnitude and unit of a value, which has no unit if it is a number.
+fn parts(x: &Value) -> (Value, Unit) {
+    match x {
+        Value::Quantity(q) => ((*q.value).clone(), q.unit.clone()),
+        x => (x.clone(), Unit::default()),
+    }
+}
+
+fn size(unit: &Unit) -> Result<Rational, EvalErrorKind> {
+    unit.size()
+        .ok_or_else(|| EvalErrorKind::Overflow(unit.to_string()))
+}
+
+/// Name a unit in an error message.
+fn describe(unit: &Unit) -> String {
+    match unit.factors.is_empty() {
+        true => String::from("a number"),
+        false => unit.to_string(),
+    }
+}
+
+/// Check that a value has no unit.
+pub(crate) fn dimensionless(x: &Value) -> Result<(), EvalErrorKind> {
+    match x {
+        Value::Quantity(q) => Err(EvalErrorKind::Dimension(format!(
+            "expected a number but got a quantity in {}",
+            q.unit
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Apply `op` to two values with the same dimension, after converting
+/// the second to the unit of the first. `verb` describes the operation
+/// for the error given when the dimensions don't match.
+pub(crate) fn combine<F>(
+    a: &Value,
+    b: &Value,
+    ctx: &Context,
+    verb: fn(&str, &str) -> String,
+    op: F,
+) -> Result<Value, EvalErrorKind>
+where
+    F: FnOnce(&Value, &Value) -> Result<Value, EvalErrorKind>,
+{
+    let (x, y, unit) = align(a, b, ctx, verb)?;
+    quantity(op(&x, &y)?, unit, ctx)
+}
+
+/// The magnitudes of two values with the same dimension, with the second
+/// converted to the unit of the first, along with that unit.
+pub(crate) fn align(
+    a: &Value,
+    b: &Value,
+    ctx: &Context,
+    verb: fn(&str, &str) -> String,
+) -> Result<(Value, Value, Unit), EvalErrorKind> {
+    let ((x, ux), (y, uy)) = (parts(a), parts(b));
+    if ux.dim() != uy.dim() {
+        return Err(EvalErrorKind::Dimension(verb(
+            &describe(&ux),
+            &describe(&uy),
+        )));
+    }
+    let ratio = size(&uy)?
+        .checked_div(&size(&ux)?)
+        .ok_or(EvalErrorKind::DivisionByZero)?;
+    let y = y.mul(&Value::Rational(ratio), ctx)?;
+    Ok((x, y, ux))
+}
+
+/// Convert a value to the unit of `target`, like in `60 mph to km/h`.
+/// Temperatures are converted between scales, so `0 degC to K` is
+/// `273.15 K`, while any other arithmetic treats them as differences.
+pub(crate) fn convert(x: &Value, target: &Value, ctx: &Context) -> Result<Value, EvalErrorKind> {
+    let ((value, from), (_, to)) = (parts(x), parts(target));
+    if from.dim() != to.dim() {
+        return Err(EvalErrorKind::Dimension(format!(
+            "cannot convert {} to {}",
+            describe(&from),
+            describe(&to)
+        )));
+    }
+    let (from_offset, to_offset) = (Value::Rational(from.offset()), Value::Rational(to.offset()));
+    let ratio = size(&from)?
+        .checked_div(&size(&to)?)
+        .ok_or(EvalErrorKind::DivisionByZero)?;
+    let value = value
+        .add(&from_offset, ctx)?
+        .mul(&Value::Rational(ratio), ctx)?
+        .sub(&to_offset, ctx)?;
+    quantity(value, to, ctx)
+}
+
+pub(crate) fn mul(a: &Value, b: &Value, ctx: &Context) -> Result<Value, EvalErrorKind> {
+    let ((x, ux), (y, uy)) = (parts(a), parts(b));
+    let (unit, scale) = ux
+        .mul(&uy)
+        .ok_or_else(|| EvalErrorKind::Overflow(format!("{} * {}", ux, uy)))?;
+    let (unit, scale) = match unit.simplify() {
+        Some((simple, size)) => (simple, &scale * &size),
+        None => (unit, scale),
+    };
+    let value = x.mul(&y, ctx)?.mul(&Value::Rational(scale), ctx)?;
+    quantity(value, unit, ctx)
+}
+
+pub(crate) fn div(a: &Value, b: &Value, ctx: &Context) -> Result<Value, EvalErrorKind> {
+    let (y, uy) = parts(b);
+    let one = Value::Rational(Rational::from_int(1));
+    let recip = quantity(one.div(&y, ctx)?, uy.recip(), ctx)?;
+    mul(a, &recip, ctx)
+}
+
+/// Raise a quantity to a power, which has to leave
+/// every unit with a whole number power.
+pub(crate) fn pow(a: &Value, b: &Value, ctx: &Context) -> Result<Value, EvalErrorKind> {
+    dimensionless(b)?;
+    let (x, unit) = parts(a);
+    let exp = b
+        .to_rational()
+        .or_else(|| b.as_f64().and_then(Rational::from_f64));
+    let fractional =
+        || EvalErrorKind::Dimension(format!("cannot raise {} to the power {}", unit, b));
+    let exp = exp.ok_or_else(fractional)?;
+    let mut res = unit.clone();
+    for f in &mut res.factors {
+        let power = &Rational::from_int(f.power) * &exp;
+        if !power.is_integer() {
+            return Err(fractional());
+        }
+        f.power = power
+            .numer()
+            .to_i64()
+            .and_then(|p| i32::try_from(p).ok())
+            .ok_or_else(|| EvalErrorKind::Overflow(format!("{}^{}", unit, b)))?;
+    }
+    res.factors.retain(|f| f.power != 0);
+    quantity(x.pow(b, ctx)?, res, ctx)
+}
+
+/// Call a builtin with a quantity among its arguments. Only roots and
+/// functions that don't change the unit, like `abs`, are defined.
+pub(crate) fn call(func: &Builtin, args: &[Value], ctx: &Context) -> Result<Value, EvalErrorKind> {
+    let root = |n| Value::Rational(Rational::new(1, n).unwrap());
+    match (func.name, args) {
+        ("sqrt", [x]) => pow(x, &root(2), ctx),
+        ("cbrt", [x]) => pow(x, &root(3), ctx),
+        ("abs" | "floor" | "ceil" | "trunc" | "round", [Value::Quantity(q)]) => {
+            let value = func.call(slice::from_ref(&q.value), ctx)?;
+            quantity(value, q.unit.clone(), ctx)
+        }
+        _ => Err(EvalErrorKind::Dimension(format!(
+            "{}() is only defined for numbers",
+            func.name
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{lookup, Unit};
+    use crate::decimal::Context;
+    use crate::value::Value;
+
+    fn unit(s: &str) -> Value {
+        lookup(s).unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        for s in [
+            "m", "km", "kg", "min", "ms", "µs", "kWh", "Pa", "mph", "cd", "GiB", "degF",
+        ] {
+            assert_eq!(Unit::parse(s).unwrap().to_string(), s);
+        }
+        for s in ["", "x", "kft", "kmin", "mm2"] {
+            assert!(Unit::parse(s).is_none(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let ctx = Context::default();
+        let mul = |a: &str, b: &str| unit(a).mul(&unit(b), &ctx).unwrap().to_string();
+        let div = |a: &str, b: &str| unit(a).div(&unit(b), &ctx).unwrap().to_string();
+        assert_eq!(div("km", "h"), "1 km/h");
+        assert_eq!(mul("km", "m"), "0.001 km^2");
+        assert_eq!(div("km", "m"), "1000");
+        assert_eq!(div("J", "N"), "1 m");
+        assert_eq!(mul("Hz", "s"), "1");
+        let accel = unit("m").div(&unit("s").mul(&unit("s"), &ctx).unwrap(), &ctx);
+        assert_eq!(accel.as_ref().unwrap().to_string(), "1 m/s^2");
+        let force = unit("kg").mul(&accel.unwrap(), &ctx).unwrap();
+        assert_eq!(force.to_string(), "1 N");
+        assert_eq!(
+            unit("m").add(&unit("ft"), &ctx).unwrap().to_string(),
+            "1.3048 m"
+        );
+        assert!(unit("m").add(&unit("s"), &ctx).is_err());
+    }
+}
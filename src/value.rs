@@ -0,0 +1,506 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::bigint::BigInt;
+use crate::complex::Complex;
+use crate::decimal::{Context, Decimal};
+use crate::error::EvalErrorKind;
+use crate::function::Function;
+use crate::radix::Radix;
+use crate::rational::{Rational, MAX_BITS};
+use crate::units::{self, Quantity};
+
+/// The result of evaluating an expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// An exact fraction.
+    Rational(Rational),
+    /// A decimal rounded to the precision of a [`Context`].
+    Decimal(Decimal),
+    Float(f64),
+    /// A complex number with a nonzero imaginary part.
+    Complex(Complex),
+    /// A number with a unit, like `9.8 m/s^2`.
+    Quantity(Quantity),
+    /// An integer written in another base. It is used as a
+    /// plain fraction when it's the operand of anything.
+    Radix(Radix),
+    /// The result of a comparison like `x > 0`.
+    Bool(bool),
+    /// A function defined in the session or written
+    /// as a lambda like `x -> x^2`.
+    Function(Arc<Function>),
+    /// A list like `[1, 2, 3]`.
+    List(Vec<Value>),
+}
+
+impl Value {
+    /// The value as a float, if it is a number.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Rational(r) => Some(r.to_f64()),
+            Value::Decimal(d) => Some(d.to_f64()),
+            Value::Float(f) => Some(*f),
+            Value::Radix(r) => Some(r.value().to_f64()),
+            Value::Complex(_)
+            | Value::Quantity(_)
+            | Value::Bool(_)
+            | Value::Function(_)
+            | Value::List(_) => None,
+        }
+    }
+
+    /// The value as a complex number, if it is a number.
+    pub fn as_complex(&self) -> Option<Complex> {
+        match self {
+            Value::Complex(z) => Some(*z),
+            _ => self.as_f64().map(Complex::from),
+        }
+    }
+
+    /// A complex value, or a float if it has no imaginary part.
+    pub fn complex(z: Complex) -> Value {
+        if z.is_real() {
+            Value::Float(z.re)
+        } else {
+            Value::Complex(z)
+        }
+    }
+
+    /// The value as an exact fraction, if it is one.
+    pub fn as_rational(&self) -> Option<&Rational> {
+        match self {
+            Value::Rational(r) => Some(r),
+            _ => None,
+        }
+    }
+
+    /// The exact value of a fraction or decimal as a fraction.
+    pub fn to_rational(&self) -> Option<Rational> {
+        match self {
+            Value::Rational(r) => Some(r.clone()),
+            Value::Decimal(d) => d.to_rational(),
+            Value::Radix(r) => Some(Rational::from_int(r.value().clone())),
+            Value::Float(_)
+            | Value::Complex(_)
+            | Value::Quantity(_)
+            | Value::Bool(_)
+            | Value::Function(_)
+            | Value::List(_) => None,
+        }
+    }
+
+    /// Store the exact result of an operation on `self`, as a
+    /// decimal if `self` is one or as a fraction otherwise.
+    pub(crate) fn exact_result(&self, res: Rational, ctx: &Context) -> Value {
+        match self {
+            Value::Decimal(_) => Value::Decimal(Decimal::from_rational(&res, ctx)),
+            _ => Value::Rational(res),
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        match self {
+            Value::Rational(r) => r.is_zero(),
+            Value::Decimal(d) => d.is_zero(),
+            Value::Float(f) => *f == 0.0,
+            Value::Complex(_) | Value::Bool(_) | Value::Function(_) | Value::List(_) => false,
+            Value::Quantity(q) => q.value().is_zero(),
+            Value::Radix(r) => r.value().is_zero(),
+        }
+    }
+
+    pub fn is_negative(&self) -> bool {
+        match self {
+            Value::Rational(r) => r.is_negative(),
+            Value::Decimal(d) => d.is_negative(),
+            Value::Float(f) => *f < 0.0,
+            Value::Complex(_) | Value::Bool(_) | Value::Function(_) | Value::List(_) => false,
+            Value::Quantity(q) => q.value().is_negative(),
+            Value::Radix(r) => r.value().is_negative(),
+        }
+    }
+
+    /// The number of units of a quantity, or the value itself.
+    pub fn magnitude(&self) -> &Value {
+        match self {
+            Value::Quantity(q) => q.value(),
+            x => x,
+        }
+    }
+
+    /// Whether the value is a number, which includes
+    /// complex numbers, quantities and integers in other bases.
+    pub(crate) fn is_number(&self) -> bool {
+        !matches!(self, Value::Bool(_) | Value::Function(_) | Value::List(_))
+    }
+
+    fn is_quantity(&self) -> bool {
+        matches!(self, Value::Quantity(_))
+    }
+
+    /// Apply `complex` when either operand is complex, `exact` when
+    /// both are rational, `decimal` when one is a decimal and the other
+    /// is a decimal or a fraction, and fall back to `float` for anything
+    /// else or when the exact operation can't give a result.
+    fn binary<E, D, F>(&self, other: &Value, ctx: &Context, ops: Ops<E, D, F>) -> Value
+    where
+        E: Fn(&Rational, &Rational) -> Option<Rational>,
+        D: Fn(&Decimal, &Decimal, &Context) -> Option<Decimal>,
+        F: Fn(f64, f64) -> f64,
+    {
+        let Ops {
+            exact,
+            decimal,
+            float,
+            complex,
+        } = ops;
+        if let (Value::Complex(_), _) | (_, Value::Complex(_)) = (self, other) {
+            let (a, b) = (self.as_complex(), other.as_complex());
+            return Value::complex(complex(&a.unwrap(), &b.unwrap()));
+        }
+        let res = match (self, other) {
+            (Value::Rational(a), Value::Rational(b)) => exact(a, b).map(Value::Rational),
+            (Value::Decimal(a), Value::Decimal(b)) => decimal(a, b, ctx).map(Value::Decimal),
+            (Value::Decimal(a), Value::Rational(b)) => {
+                decimal(a, &Decimal::from_rational(b, ctx), ctx).map(Value::Decimal)
+            }
+            (Value::Rational(a), Value::Decimal(b)) => {
+                decimal(&Decimal::from_rational(a, ctx), b, ctx).map(Value::Decimal)
+            }
+            _ => None,
+        };
+        res.unwrap_or_else(|| Value::Float(float(self.to_f64(), other.to_f64())))
+    }
+
+    fn to_f64(&self) -> f64 {
+        self.as_f64().unwrap_or(f64::NAN)
+    }
+
+    pub(crate) fn neg(&self) -> Value {
+        match self {
+            Value::Rational(r) => Value::Rational(-r),
+            Value::Decimal(d) => Value::Decimal(d.neg()),
+            Value::Float(f) => Value::Float(-f),
+            Value::Complex(z) => Value::Complex(z.neg()),
+            Value::Quantity(q) => Value::Quantity(q.map(Value::neg)),
+            Value::Radix(r) => Value::Rational(Rational::from_int(-r.value())),
+            // only numbers get here, `ast::number` rejects anything else
+            Value::Bool(_) | Value::Function(_) | Value::List(_) => self.clone(),
+        }
+    }
+
+    /// Whether two values are equal. Quantities are compared after
+    /// converting to the same unit, so `1 km == 1000 m`.
+    pub(crate) fn equals(&self, other: &Value, ctx: &Context) -> Result<bool, EvalErrorKind> {
+        if self.is_quantity() || other.is_quantity() {
+            let verb = |a: &str, b: &str| format!("cannot compare {} and {}", a, b);
+            let (x, y, _) = units::align(self, other, ctx, verb)?;
+            return x.equals(&y, ctx);
+        }
+        match (self, other) {
+            (Value::Bool(a), Value::Bool(b)) => Ok(a == b),
+            (Value::List(a), Value::List(b)) => {
+                if a.len() != b.len() {
+                    return Ok(false);
+                }
+                for (x, y) in a.iter().zip(b) {
+                    if !x.equals(y, ctx)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+            _ if !self.is_number() || !other.is_number() => Err(incomparable(self, other)),
+            (Value::Complex(_), _) | (_, Value::Complex(_)) => {
+                Ok(self.as_complex() == other.as_complex())
+            }
+            _ => match (self.to_rational(), other.to_rational()) {
+                (Some(a), Some(b)) => Ok(a == b),
+                _ => Ok(self.to_f64() == other.to_f64()),
+            },
+        }
+    }
+
+    /// The order of two real numbers or quantities, `None` when
+    /// either is NaN. Complex numbers and booleans have no order.
+    pub(crate) fn compare(
+        &self,
+        other: &Value,
+        ctx: &Context,
+    ) -> Result<Option<Ordering>, EvalErrorKind> {
+        if self.is_quantity() || other.is_quantity() {
+            let verb = |a: &str, b: &str| format!("cannot compare {} and {}", a, b);
+            let (x, y, _) = units::align(self, other, ctx, verb)?;
+            return x.compare(&y, ctx);
+        }
+        match (self, other) {
+            (Value::Complex(_), _) | (_, Value::Complex(_)) => Err(incomparable(self, other)),
+            _ if !self.is_number() || !other.is_number() => Err(incomparable(self, other)),
+            _ => match (self.to_rational(), other.to_rational()) {
+                (Some(a), Some(b)) => Ok(Some(a.cmp(&b))),
+                _ => Ok(self.to_f64().partial_cmp(&other.to_f64())),
+            },
+        }
+    }
+
+    pub(crate) fn add(&self, other: &Value, ctx: &Context) -> Result<Value, EvalErrorKind> {
+        if self.is_quantity() || other.is_quantity() {
+            let verb = |a: &str, b: &str| format!("cannot add {} and {}", a, b);
+            return units::combine(self, other, ctx, verb, |a, b| a.add(b, ctx));
+        }
+        let ops = Ops {
+            exact: |a: &Rational, b: &Rational| Some(a + b),
+            decimal: |a: &Decimal, b: &Decimal, ctx: &Context| Some(a.add(b, ctx)),
+            float: |a, b| a + b,
+            complex: Complex::add,
+        };
+        Ok(self.binary(other, ctx, ops))
+    }
+
+    pub(crate) fn sub(&self, other: &Value, ctx: &Context) -> Result<Value, EvalErrorKind> {
+        if self.is_quantity() || other.is_quantity() {
+            let verb = |a: &str, b: &str| format!("cannot subtract {} from {}", b, a);
+            return units::combine(self, other, ctx, verb, |a, b| a.sub(b, ctx));
+        }
+        let ops = Ops {
+            exact: |a: &Rational, b: &Rational| Some(a - b),
+            decimal: |a: &Decimal, b: &Decimal, ctx: &Context| Some(a.sub(b, ctx)),
+            float: |a, b| a - b,
+            complex: Complex::sub,
+        };
+        Ok(self.binary(other, ctx, ops))
+    }
+
+    pub(crate) fn mul(&self, other: &Value, ctx: &Context) -> Result<Value, EvalErrorKind> {
+        if self.is_quantity() || other.is_quantity() {
+            return units::mul(self, other, ctx);
+        }
+        let ops = Ops {
+            exact: |a: &Rational, b: &Rational| Some(a * b),
+            decimal: |a: &Decimal, b: &Decimal, ctx: &Context| Some(a.mul(b, ctx)),
+            float: |a, b| a * b,
+            complex: Complex::mul,
+        };
+        Ok(self.binary(other, ctx, ops))
+    }
+
+    pub(crate) fn div(&self, other: &Value, ctx: &Context) -> Result<Value, EvalErrorKind> {
+        if self.is_quantity() || other.is_quantity() {
+            return units::div(self, other, ctx);
+        }
+        let ops = Ops {
+            exact: Rational::checked_div,
+            decimal: Decimal::checked_div,
+            float: |a, b| a / b,
+            complex: Complex::div,
+        };
+        Ok(self.binary(other, ctx, ops))
+    }
+
+    pub(crate) fn rem(&self, other: &Value, ctx: &Context) -> Result<Value, EvalErrorKind> {
+        if self.is_quantity() || other.is_quantity() {
+            let verb = |a: &str, b: &str| format!("cannot take {} mod {}", a, b);
+            return units::combine(self, other, ctx, verb, |a, b| a.rem(b, ctx));
+        }
+        let ops = Ops {
+            exact: Rational::checked_rem,
+            decimal: Decimal::checked_rem,
+            float: |a, b| a % b,
+            // there is no natural remainder for complex numbers
+            complex: |_, _| Complex::new(f64::NAN, f64::NAN),
+        };
+        Ok(self.binary(other, ctx, ops))
+    }
+
+    /// Raise to a power. Only integer powers of fractions and
+    /// decimals are kept exact, as long as they aren't unreasonably
+    /// large. Fractional powers of negative numbers are complex.
+    pub(crate) fn pow(&self, other: &Value, ctx: &Context) -> Result<Value, EvalErrorKind> {
+        if self.is_quantity() || other.is_quantity() {
+            return units::pow(self, other, ctx);
+        }
+        let int = |b: &Rational| match b.is_integer() {
+            true => b.numer().to_i64(),
+            false => None,
+        };
+        let fractional = other.as_f64().is_some_and(|f| f.fract() != 0.0);
+        if self.is_negative() && fractional {
+            let (a, b) = (self.as_complex().unwrap(), other.as_complex().unwrap());
+            return Ok(Value::complex(a.pow(&b)));
+        }
+        let ops = Ops {
+            exact: |a: &Rational, b: &Rational| a.checked_pow(int(b)?),
+            decimal: |a: &Decimal, b: &Decimal, ctx: &Context| {
+                a.checked_pow(int(&b.to_rational()?)?, ctx)
+            },
+            float: f64::powf,
+            complex: |a: &Complex, b: &Complex| a.pow(b),
+        };
+        Ok(self.binary(other, ctx, ops))
+    }
+
+    /// The value as an integer, or an error if it isn't one.
+    pub(crate) fn integer(&self) -> Result<BigInt, EvalErrorKind> {
+        let n = match self {
+            Value::Float(f) => Rational::from_f64(*f),
+            x => x.to_rational(),
+        };
+        match n {
+            Some(n) if n.is_integer() => Ok(n.numer().clone()),
+            _ => Err(EvalErrorKind::NotInteger(self.to_string())),
+        }
+    }
+
+    /// Store the integer result of an operation on `self` and `other`,
+    /// as a float or decimal if either of them is one.
+    fn integer_result(&self, other: &Value, n: BigInt, ctx: &Context) -> Value {
+        let res = Rational::from_int(n);
+        match (self, other) {
+            (Value::Float(_), _) | (_, Value::Float(_)) => Value::Float(res.to_f64()),
+            (Value::Decimal(_), _) => self.exact_result(res, ctx),
+            _ => other.exact_result(res, ctx),
+        }
+    }
+
+    pub(crate) fn bitand(&self, other: &Value, ctx: &Context) -> Result<Value, EvalErrorKind> {
+        let n = &self.integer()? & &other.integer()?;
+        Ok(self.integer_result(other, n, ctx))
+    }
+
+    pub(crate) fn bitor(&self, other: &Value, ctx: &Context) -> Result<Value, EvalErrorKind> {
+        let n = &self.integer()? | &other.integer()?;
+        Ok(self.integer_result(other, n, ctx))
+    }
+
+    pub(crate) fn bitxor(&self, other: &Value, ctx: &Context) -> Result<Value, EvalErrorKind> {
+        let n = &self.integer()? ^ &other.integer()?;
+        Ok(self.integer_result(other, n, ctx))
+    }
+
+    pub(crate) fn not(&self, ctx: &Context) -> Result<Value, EvalErrorKind> {
+        let n = !&self.integer()?;
+        Ok(self.integer_result(self, n, ctx))
+    }
+
+    /// Shift the bits of an integer left, or right when `left` is false.
+    /// Right shifts are arithmetic, and a negative amount shifts the
+    /// other way.
+    pub(crate) fn shift(
+        &self,
+        other: &Value,
+        left: bool,
+        ctx: &Context,
+    ) -> Result<Value, EvalErrorKind> {
+        let (n, amount) = (self.integer()?, other.integer()?);
+        let overflow = || {
+            let op = if left { "<<" } else { ">>" };
+            EvalErrorKind::Overflow(format!("{} {} {}", self, op, other))
+        };
+        let amount = amount
+            .to_i64()
+            .and_then(|a| if left { Some(a) } else { a.checked_neg() })
+            .ok_or_else(overflow)?;
+        if amount > 0 && n.bits().saturating_add(amount as u64) > MAX_BITS {
+            return Err(overflow());
+        }
+        let n = n.shift(amount);
+        Ok(self.integer_result(other, n, ctx))
+    }
+
+    /// Rotate the bits of an integer `width` bits wide to the left, or
+    /// to the right when `left` is false. Negative numbers are rotated
+    /// in two's complement and the result is unsigned.
+    pub(crate) fn rotate(
+        &self,
+        other: &Value,
+        width: u32,
+        left: bool,
+        ctx: &Context,
+    ) -> Result<Value, EvalErrorKind> {
+        let width = i64::from(width);
+        let mask = &BigInt::one().shl(width as u64) - &BigInt::one();
+        let n = &self.integer()? & &mask;
+        let amount = match other.integer()?.div_rem(&BigInt::from(width)) {
+            Some((_, r)) => r.to_i64().unwrap(),
+            None => unreachable!("the width is positive"),
+        };
+        let amount = if left { amount } else { -amount }.rem_euclid(width);
+        let n = &(&n.shift(amount) | &n.shift(amount - width)) & &mask;
+        Ok(self.integer_result(other, n, ctx))
+    }
+}
+
+/// The versions of a binary operation for each kind of number.
+fn incomparable(a: &Value, b: &Value) -> EvalErrorKind {
+    EvalErrorKind::Type(format!("cannot compare {} and {}", a, b))
+}
+
+struct Ops<E, D, F> {
+    exact: E,
+    decimal: D,
+    float: F,
+    complex: fn(&Complex, &Complex) -> Complex,
+}
+
+impl From<f64> for Value {
+    fn from(f: f64) -> Self {
+        Value::Float(f)
+    }
+}
+
+impl From<Rational> for Value {
+    fn from(r: Rational) -> Self {
+        Value::Rational(r)
+    }
+}
+
+impl From<Complex> for Value {
+    fn from(z: Complex) -> Self {
+        Value::complex(z)
+    }
+}
+
+impl From<Decimal> for Value {
+    fn from(d: Decimal) -> Self {
+        Value::Decimal(d)
+    }
+}
+
+impl From<Quantity> for Value {
+    fn from(q: Quantity) -> Self {
+        Value::Quantity(q)
+    }
+}
+
+impl PartialEq<f64> for Value {
+    fn eq(&self, other: &f64) -> bool {
+        self.as_f64() == Some(*other)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Rational(r) => write!(f, "{}", r),
+            Value::Decimal(d) => write!(f, "{}", d),
+            Value::Float(n) => write!(f, "{}", n),
+            Value::Complex(z) => write!(f, "{}", z),
+            Value::Quantity(q) => write!(f, "{}", q),
+            Value::Radix(r) => write!(f, "{}", r),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Function(func) => write!(f, "{}", func),
+            Value::List(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
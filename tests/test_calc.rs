@@ -1,7 +1,10 @@
-use calc::exec;
+use calc::{
+    exec, simplify, Ast, BigInt, Complex, Env, Error, EvalError, EvalErrorKind, IntType, Mode,
+    Output, Policy, Rational, Rounding, Span, Token, Value,
+};
 
 #[test]
-
+#[allow(clippy::unnecessary_cast)]
 fn test_exec() {
     let shouldfail = ((-1 as i32) as usize) as f64;
     for tc in vec![
@@ -98,19 +101,1428 @@ fn test_exec() {
         ("30% ^ 3", (30. / 100. as f64).powf(3.)),
         ("20% ^ (32-25)", (20. / 100. as f64).powf(32. - 25.)),
     ] {
-        let res = match exec(tc.0) {
-            Ok(res) => res,
-            Err(msg) => {
-                if tc.1 == shouldfail {
-                    continue;
-                } else {
-                    panic!(msg)
+        for mode in [Mode::Float, Mode::Exact, Mode::Decimal] {
+            let mut env = Env::new();
+            env.set_mode(mode);
+            let res = match env.exec(tc.0) {
+                Ok(res) => res,
+                Err(msg) => {
+                    if tc.1 == shouldfail {
+                        continue;
+                    } else {
+                        panic!("{}", msg)
+                    }
                 }
+            };
+            // exact and decimal results can differ from the float
+            // expectations in the last few bits
+            let close = match (mode, res.as_f64()) {
+                (Mode::Exact | Mode::Decimal, Some(f)) => (f - tc.1).abs() <= 1e-12 * tc.1.abs(),
+                _ => res == tc.1,
+            };
+            if !close {
+                panic!("{} expected {}, got {} ({:?})", tc.0, tc.1, res, mode);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_variables() {
+    let mut env = Env::new();
+    assert_eq!(env.exec("let rate = 0.07").unwrap(), 0.07);
+    assert_eq!(env.exec("rate * 1200").unwrap(), 84.);
+    assert_eq!(env.exec("let total = rate * 1200 + 1200").unwrap(), 1284.);
+    assert_eq!(env.exec("total mod 1000").unwrap(), 284.);
+    assert_eq!(env.exec("let rate = rate * 2").unwrap(), 0.14);
+    assert_eq!(*env.get("rate").unwrap(), 0.14);
+
+    for s in ["x + 1", "let = 3", "let x 3", "let 3 = x", "let x ="] {
+        if let Ok(res) = env.exec(s) {
+            panic!("expected an error from \"{}\", got {}", s, res);
+        }
+    }
+    assert_eq!(env.get("x"), None);
+}
+
+#[test]
+fn test_functions() {
+    for tc in [
+        ("sqrt(2)", 2_f64.sqrt()),
+        ("log(100, 10)", 2.),
+        ("log(1000)", 3.),
+        ("ln(e)", 1.),
+        ("max(1, 2, 3)", 3.),
+        ("min(4, -2, 3)", -2.),
+        ("sin(pi / 2)", 1.),
+        ("cosh(0) + tanh(0)", 1.),
+        ("floor(2.7) + ceil(2.2)", 5.),
+        ("round(1.23456, 2)", 1.23),
+        ("2 ^ max(1, 2) * 3", 12.),
+        ("sqrt(max(9, 16)) mod 3", 1.),
+    ] {
+        match exec(tc.0) {
+            Ok(res) => assert_eq!(res, tc.1, "{}", tc.0),
+            Err(msg) => panic!("{}: {}", tc.0, msg),
+        }
+    }
+    for tc in [
+        ("sqrt(4, 2)", "sqrt takes 1 argument but 2 were given"),
+        ("min()", "min takes at least 1 argument but 0 were given"),
+        (
+            "log(1, 2, 3)",
+            "log takes 1 to 2 arguments but 3 were given",
+        ),
+        ("nope(1)", "unknown function 'nope'"),
+    ] {
+        assert_eq!(exec(tc.0).unwrap_err().message(), tc.1);
+    }
+}
+
+#[test]
+fn test_factorial() {
+    for tc in [
+        ("5!", 120.),
+        ("0!", 1.),
+        ("(3+2)!", 120.),
+        ("10!/(3!*7!)", 120.),
+        ("3!!", 720.),
+        ("2*3!+1", 13.),
+        ("-3!", -6.),
+        ("2^3!", 64.),
+        ("20!", 2432902008176640000.),
+        ("fact(4) + gamma(5)", 48.),
+    ] {
+        match exec(tc.0) {
+            Ok(res) => assert_eq!(res, tc.1, "{}", tc.0),
+            Err(msg) => panic!("{}: {}", tc.0, msg),
+        }
+    }
+    let res = exec("2.5!").unwrap().as_f64().unwrap();
+    assert!((res - 3.323350970447843).abs() < 1e-12, "2.5! = {}", res);
+    assert_eq!(
+        exec("100000!").unwrap_err().message(),
+        "100000! is out of range"
+    );
+}
+
+#[test]
+fn test_errors() {
+    for (src, span) in [
+        ("2 3", Span::new(2, 3)),
+        ("1 + # 2", Span::new(4, 5)),
+        ("(1 + 2", Span::new(6, 6)),
+        ("1 + x * 2", Span::new(4, 5)),
+        ("2 * sqrt(1, 2)", Span::new(4, 14)),
+        ("let 2 = 3", Span::new(4, 5)),
+    ] {
+        match exec(src) {
+            Ok(res) => panic!("expected an error from \"{}\", got {}", src, res),
+            Err(err) => assert_eq!(err.span(), span, "{}", src),
+        }
+    }
+    match exec("1 + # 2") {
+        Err(Error::Lex { .. }) => {}
+        res => panic!("expected a lex error, got {:?}", res),
+    }
+    match exec("1 + * 2") {
+        Err(Error::Parse { .. }) => {}
+        res => panic!("expected a parse error, got {:?}", res),
+    }
+    match exec("of 3") {
+        Err(err) => assert_eq!(
+            err.render("of 3"),
+            "Error: unexpected token 'of'\n  of 3\n  ^^"
+        ),
+        res => panic!("expected a parse error, got {:?}", res),
+    }
+    match exec("1 + x") {
+        Err(err @ Error::Eval { .. }) => assert_eq!(
+            err.render("1 + x"),
+            "Error: undefined variable 'x'\n  1 + x\n      ^"
+        ),
+        res => panic!("expected an evaluation error, got {:?}", res),
+    }
+}
+
+#[test]
+fn test_eval_errors() {
+    for (src, kind) in [
+        ("1/0", EvalErrorKind::DivisionByZero),
+        ("5 mod (2 - 2)", EvalErrorKind::DivisionByZero),
+        ("asin(2)", EvalErrorKind::Domain(String::from("asin(2)"))),
+        (
+            "floor(2i)",
+            EvalErrorKind::Domain(String::from("floor(2i)")),
+        ),
+        (
+            "exp(1000)",
+            EvalErrorKind::Overflow(String::from("exp(1000)")),
+        ),
+        (
+            "10^400000",
+            EvalErrorKind::Overflow(String::from("10 ^ 400000")),
+        ),
+        ("100000!", EvalErrorKind::Overflow(String::from("100000!"))),
+        // too small to keep as a fraction
+        (
+            "(1/3)^1000000",
+            EvalErrorKind::Overflow(String::from("1/3 ^ 1000000")),
+        ),
+        (
+            "2^-1000000",
+            EvalErrorKind::Overflow(String::from("2 ^ (-1000000)")),
+        ),
+    ] {
+        match exec(src) {
+            Err(Error::Eval(err)) => assert_eq!(err.kind, kind, "{}", src),
+            res => panic!("expected an error from \"{}\", got {:?}", src, res),
+        }
+    }
+
+    let mut env = Env::new();
+    env.set_policy(Policy::Ieee);
+    assert_eq!(env.exec("1/0").unwrap(), f64::INFINITY);
+    assert!(env.exec("asin(2)").unwrap().as_f64().unwrap().is_nan());
+    assert_eq!(env.exec("-10^400").unwrap(), f64::NEG_INFINITY);
+    assert_eq!(env.exec("(1/3)^1000000").unwrap(), 0.);
+}
+
+#[test]
+fn test_malformed_ast() {
+    let one = || Ast::new(Token::int(1));
+    let mut env = Env::new();
+    for ast in [
+        Ast::new(Token::Comma),
+        Ast::from(Token::Op('*'), vec![one()]),
+        Ast::from(Token::Comma, vec![one(), one()]),
+        Ast::from(Token::Op('+'), vec![one(), one(), one()]),
+        Ast::from(Token::Assign, vec![one(), one()]),
+        Ast::from(Token::Op('-'), vec![Ast::new(Token::OpenParen)]),
+        Ast::new(Token::Lambda),
+        Ast::from(Token::Lambda, vec![one(), one()]),
+        Ast::new(Token::Call),
+    ] {
+        match env.eval(&ast) {
+            Err(Error::Eval(EvalError {
+                kind: EvalErrorKind::Malformed(..),
+                ..
+            })) => {}
+            res => panic!("expected an error from {:?}, got {:?}", ast, res),
+        }
+    }
+    let ast = Ast::from(Token::Op('+'), vec![one(), one()]);
+    assert_eq!(env.eval(&ast).unwrap(), 2.);
+}
+
+#[test]
+fn test_exact() {
+    let fraction = |num: i128, den: i128| Value::Rational(Rational::new(num, den).unwrap());
+    assert_eq!(exec("0.1 + 0.2").unwrap(), fraction(3, 10));
+    assert_eq!(exec("0.1 + 0.2").unwrap().to_string(), "0.3");
+    assert_eq!(exec("1/3*3").unwrap(), fraction(1, 1));
+    assert_eq!(exec("1/3 + 1/12").unwrap().to_string(), "5/12");
+    assert_eq!(exec("-2/6").unwrap().to_string(), "-1/3");
+    assert_eq!(exec("(2/3)^-2").unwrap(), fraction(9, 4));
+    assert_eq!(exec("7/2 mod 1").unwrap(), fraction(1, 2));
+    assert_eq!(exec("25% of 1/3").unwrap(), fraction(1, 12));
+    assert_eq!(exec("20!").unwrap(), fraction(2432902008176640000, 1));
+    assert_eq!(exec("sqrt(9/4) + abs(-1/4)").unwrap(), fraction(7, 4));
+    assert_eq!(exec("round(2/3, 2)").unwrap(), fraction(67, 100));
+
+    // literals keep every digit they are written with
+    for (src, expected) in [
+        ("1.00000000000000000001 - 1", "0.00000000000000000001"),
+        ("123456789012345678901.5", "123456789012345678901.5"),
+        ("1e400 / 1e399", "10"),
+        ("2.5e-30 * 4e30", "10"),
+    ] {
+        assert_eq!(exec(src).unwrap().to_string(), expected, "{}", src);
+    }
+    match exec("1e1000000") {
+        Err(Error::Eval(err)) => {
+            assert_eq!(err.kind, EvalErrorKind::Overflow(String::from("1e1000000")))
+        }
+        res => panic!("expected an overflow, got {:?}", res),
+    }
+
+    // irrational functions and constants fall back to floats
+    assert_eq!(exec("sqrt(2)").unwrap(), Value::Float(2_f64.sqrt()));
+    assert_eq!(exec("2^0.5").unwrap(), Value::Float(2_f64.sqrt()));
+    assert_eq!(
+        exec("pi/2").unwrap(),
+        Value::Float(std::f64::consts::PI / 2.)
+    );
+    assert_eq!(exec("1/3 + 0.5^0.5").unwrap(), 1. / 3. + 0.5_f64.sqrt());
+
+    let mut env = Env::new();
+    let third = env.exec("1/3").unwrap();
+    assert_eq!(env.show(&third), "1/3");
+    env.set_output(Output::Decimal);
+    assert_eq!(env.show(&third), "0.3333333333333333333333333333");
+    // digits that end are written in full, past what a float holds
+    for (src, expected) in [
+        ("10^400", "1e400"),
+        ("2^100 + 1/4", "1267650600228229401496703205376.25"),
+        ("1/1024", "0.0009765625"),
+    ] {
+        let val = env.exec(src).unwrap();
+        assert_eq!(env.show(&val), expected, "{}", src);
+    }
+    env.set_precision(5);
+    env.set_rounding(Rounding::TowardZero);
+    let val = env.exec("2/3").unwrap();
+    assert_eq!(env.show(&val), "0.66666");
+    env.set_mode(Mode::Float);
+    assert_eq!(env.exec("0.1 + 0.2").unwrap(), Value::Float(0.1 + 0.2));
+}
+
+#[test]
+fn test_big_integers() {
+    for (src, expected) in [
+        ("2^100", "1267650600228229401496703205376"),
+        (
+            "2^521 - 1",
+            "686479766013060971498190079908139321726943530014330540939446\
+             345918554318339765605212255964066145455497729631139148085803\
+             7121987999716643812574028291115057151",
+        ),
+        (
+            "50!",
+            "30414093201713378043612608166064768844377641568960512000000000000",
+        ),
+        (
+            "123456789012345678901234567890 + 1",
+            "123456789012345678901234567891",
+        ),
+        ("-(2^64) * 2^64", "-340282366920938463463374607431768211456"),
+        ("2^64 / 2^62", "4"),
+        ("2^100 mod 7", "2"),
+        ("(2^70 + 1) / 3", "1180591620717411303425/3"),
+        ("25!/23!", "600"),
+    ] {
+        match exec(src) {
+            Ok(res) => assert_eq!(res.to_string(), expected, "{}", src),
+            Err(msg) => panic!("{}: {}", src, msg),
+        }
+    }
+    let big = Ast::new(Token::Int(
+        BigInt::from_decimal("99999999999999999999").unwrap(),
+    ));
+    assert_eq!(
+        Env::new().eval(&big).unwrap().to_string(),
+        "99999999999999999999"
+    );
+}
+
+#[test]
+fn test_decimal() {
+    let mut env = Env::new();
+    env.set_mode(Mode::Decimal);
+    for (src, expected) in [
+        ("0.1 + 0.2", "0.3"),
+        ("1/3", "0.3333333333333333333333333333"),
+        ("2/3", "0.6666666666666666666666666667"),
+        ("1/3*3", "0.9999999999999999999999999999"),
+        ("19.99 * 3", "59.97"),
+        ("1.1^2", "1.21"),
+        ("2^-3", "0.125"),
+        ("10 mod 3.5", "3"),
+        ("15% of 80.40", "12.06"),
+        ("round(2/3, 2)", "0.67"),
+        ("2^100", "1267650600228229401496703205000"),
+    ] {
+        match env.exec(src) {
+            Ok(res) => assert_eq!(res.to_string(), expected, "{}", src),
+            Err(msg) => panic!("{}: {}", src, msg),
+        }
+    }
+    // powers are limited to the same range as exact fractions
+    match env.exec("10^1000000") {
+        Err(Error::Eval(err)) => assert_eq!(
+            err.kind,
+            EvalErrorKind::Overflow(String::from("10 ^ 1000000"))
+        ),
+        res => panic!("expected an overflow, got {:?}", res),
+    }
+    assert_eq!(env.exec("10^-1000000").unwrap(), Value::Float(0.0));
+    // irrational functions are worked out to the precision too
+    assert_eq!(
+        env.exec("sqrt(2)").unwrap().to_string(),
+        "1.414213562373095048801688724"
+    );
+
+    env.set_precision(50);
+    for (src, expected) in [
+        (
+            "1/7",
+            "0.14285714285714285714285714285714285714285714285714",
+        ),
+        (
+            "sqrt(2)",
+            "1.4142135623730950488016887242096980785696718753769",
+        ),
+        ("exp(1)", "2.7182818284590452353602874713526624977572470937"),
+        (
+            "ln(2)",
+            "0.69314718055994530941723212145817656807550013436026",
+        ),
+        (
+            "sin(1)",
+            "0.84147098480789650665250232163029899962256306079837",
+        ),
+        (
+            "cos(100)",
+            "0.86231887228768393410193851395084253551008400853551",
+        ),
+        ("log(1000)", "3"),
+        // literals keep more digits than a float holds
+        (
+            "0.12345678901234567890123456789 * 1",
+            "0.12345678901234567890123456789",
+        ),
+        ("1e1000000 * 2", "2e1000000"),
+        // and so do the constants
+        ("pi", "3.1415926535897932384626433832795028841971693993751"),
+        ("tau", "6.2831853071795864769252867665590057683943387987502"),
+        ("e", "2.7182818284590452353602874713526624977572470937"),
+        ("sin(pi / 6)", "0.5"),
+    ] {
+        assert_eq!(env.exec(src).unwrap().to_string(), expected, "{}", src);
+    }
+    env.set_precision(3);
+    for (src, expected) in [
+        ("sin(1)", "0.841"),
+        ("exp(10)", "22000"),
+        ("asin(0.5)", "0.524"),
+    ] {
+        assert_eq!(env.exec(src).unwrap().to_string(), expected, "{}", src);
+    }
+    for (rounding, expected) in [
+        (Rounding::HalfEven, "2.24"),
+        (Rounding::HalfUp, "2.25"),
+        (Rounding::TowardZero, "2.24"),
+    ] {
+        env.set_rounding(rounding);
+        assert_eq!(env.exec("2.245 * 1").unwrap().to_string(), expected);
+    }
+    env.set_rounding(Rounding::TowardZero);
+    assert_eq!(env.exec("-2/3").unwrap().to_string(), "-0.666");
+    assert_eq!(env.exec("1/0").unwrap_err().message(), "division by zero");
+}
+
+#[test]
+fn test_complex() {
+    for (src, expected) in [
+        ("sqrt(-1)", "i"),
+        ("(3+4i)*(1-2i)", "11 - 2i"),
+        ("(3+4i)/(1-2i)", "-1 + 2i"),
+        ("abs(3+4i)", "5"),
+        ("conj(3+4i)", "3 - 4i"),
+        ("re(3+4i) - im(3+4i)", "-1"),
+        ("i^2", "-1"),
+        ("2j * 3", "6i"),
+        ("(-4)^0.5", "2i"),
+        ("-(2i)", "-2i"),
+        ("sqrt(-9) + 1/2", "0.5 + 3i"),
+    ] {
+        match exec(src) {
+            Ok(res) => assert_eq!(res.to_string(), expected, "{}", src),
+            Err(msg) => panic!("{}: {}", src, msg),
+        }
+    }
+    let polar = exec("5∠53.13°").unwrap().as_complex().unwrap();
+    assert!((polar.re - 3.).abs() < 1e-4 && (polar.im - 4.).abs() < 1e-4);
+    let back = exec("arg(5∠53.13°) * 180/pi").unwrap().as_f64().unwrap();
+    assert!((back - 53.13).abs() < 1e-9);
+    let cube = exec("(-8)^(1/3)").unwrap().as_complex().unwrap();
+    assert!((cube.re - 1.).abs() < 1e-12 && (cube.im - 3_f64.sqrt()).abs() < 1e-12);
+    assert_eq!(
+        exec("ln(-1)").unwrap(),
+        Value::Complex(Complex::new(0., std::f64::consts::PI))
+    );
+
+    let mut env = Env::new();
+    env.exec("let z = 1 + 2i").unwrap();
+    assert_eq!(env.exec("z * conj(z)").unwrap(), 5.);
+}
+
+#[test]
+fn test_units() {
+    for (src, expected) in [
+        ("3 m + 2 m", "5 m"),
+        ("1 km + 500 m", "1.5 km"),
+        ("3.5 km / 20 min", "0.175 km/min"),
+        ("100 km / 2 h", "50 km/h"),
+        ("2 kg * 3 m/s^2", "6 N"),
+        ("10 N * 2 m", "20 J"),
+        ("5 kWh / 2 h", "2500 W"),
+        ("60 mph * 2 h", "193121.28 m"),
+        ("6 J / 2 s", "3 W"),
+        ("(3 m)^2", "9 m^2"),
+        ("sqrt(16 m^2)", "4 m"),
+        ("1 km / 250 m", "4"),
+        ("5 ft - 12 in", "4 ft"),
+        ("-2 s * 3", "-6 s"),
+        ("50 Hz * 2 s", "100"),
+        ("abs(-3 V)", "3 V"),
+    ] {
+        match exec(src) {
+            Ok(res) => assert_eq!(res.to_string(), expected, "{}", src),
+            Err(msg) => panic!("{}: {}", src, msg),
+        }
+    }
+    for (src, msg) in [
+        ("3 m + 2 s", "cannot add m and s"),
+        ("3 m - 2", "cannot subtract a number from m"),
+        ("sqrt(2 m)", "cannot raise m to the power 0.5"),
+        ("sin(2 m)", "sin() is only defined for numbers"),
+        ("2 ^ (1 s)", "expected a number but got a quantity in s"),
+    ] {
+        match exec(src) {
+            Ok(res) => panic!("{} gave {}", src, res),
+            Err(err) => assert_eq!(err.to_string(), msg, "{}", src),
+        }
+    }
+
+    // variables shadow units
+    let mut env = Env::new();
+    env.exec("let m = 4").unwrap();
+    assert_eq!(env.exec("3 m").unwrap(), 12.);
+    assert_eq!(env.exec("3 km").unwrap().to_string(), "3 km");
+}
+
+#[test]
+fn test_conversion() {
+    for (src, expected) in [
+        ("60 mph to km/h", "96.56064 km/h"),
+        ("5 ft 11 in to cm", "180.34 cm"),
+        ("98.6 degF in degC", "37 degC"),
+        ("-40 degC to degF", "-40 degF"),
+        ("0 degC to K", "273.15 K"),
+        ("1.5 GiB in MB", "1610.612736 MB"),
+        ("2 in in cm", "5.08 cm"),
+        ("1 km + 500 m to m", "1500 m"),
+        ("36 kN * 1 km to kWh", "10 kWh"),
+    ] {
+        match exec(src) {
+            Ok(res) => assert_eq!(res.to_string(), expected, "{}", src),
+            Err(msg) => panic!("{}: {}", src, msg),
+        }
+    }
+    for (src, msg) in [
+        ("3 m to s", "cannot convert m to s"),
+        ("1 GiB in degC", "cannot convert GiB to degC"),
+        ("5 km/h to m", "cannot convert km/h to m"),
+    ] {
+        match exec(src) {
+            Err(Error::Eval(err)) => {
+                assert_eq!(err.kind, EvalErrorKind::Dimension(String::from(msg)))
+            }
+            res => panic!("expected an error from \"{}\", got {:?}", src, res),
+        }
+    }
+}
+
+#[test]
+fn test_radix() {
+    for (src, expected) in [
+        ("0xFF", "255"),
+        ("0o755 - 0b1010_0101", "328"),
+        ("1_000_000 / 1_000", "1000"),
+        ("255 to hex", "0xFF"),
+        ("0xdead_beef in bin", "0b11011110101011011011111011101111"),
+        ("8^3 in oct", "0o1000"),
+        ("0xFF to dec", "255"),
+        ("-1 to hex", "0xFFFFFFFFFFFFFFFF"),
+        ("(255 to hex) + 1", "256"),
+    ] {
+        match exec(src) {
+            Ok(res) => assert_eq!(res.to_string(), expected, "{}", src),
+            Err(msg) => panic!("{}: {}", src, msg),
+        }
+    }
+    match exec("1.5 to hex") {
+        Err(Error::Eval(err)) => {
+            assert_eq!(err.kind, EvalErrorKind::NotInteger(String::from("1.5")))
+        }
+        res => panic!("expected an error, got {:?}", res),
+    }
+    assert!(exec("0b102").is_err());
+
+    let mut env = Env::new();
+    assert_eq!(env.set_base(7), Err(String::from("unsupported base '7'")));
+    env.set_base(16).unwrap();
+    for width in [0, Env::MAX_WIDTH + 1, u32::MAX] {
+        let msg = format!("unsupported width '{}'", width);
+        assert_eq!(env.set_width(width), Err(msg));
+    }
+    env.set_width(16).unwrap();
+    let show = |env: &mut Env, src| {
+        let res = env.exec(src).unwrap();
+        env.show(&res)
+    };
+    assert_eq!(show(&mut env, "4096 + 15"), "0x100F");
+    assert_eq!(show(&mut env, "-2"), "0xFFFE");
+    assert_eq!(show(&mut env, "1/4"), "0.25");
+    assert_eq!(show(&mut env, "-2 to bin"), "0b1111111111111110");
+    env.set_mode(Mode::Float);
+    assert_eq!(show(&mut env, "2^10"), "0x400");
+    env.exec("let hex = 2").unwrap();
+    assert_eq!(env.exec("5 to hex").unwrap(), 5.);
+}
+
+#[test]
+fn test_bitwise() {
+    for (src, expected) in [
+        ("12 & 10", 8.),
+        ("12 | 3", 15.),
+        ("12 xor 10", 6.),
+        ("12 | 3 xor 1", 14.),
+        ("1 + 2 & 7", 3.),
+        ("~0", -1.),
+        ("¬5", -6.),
+        ("-12 & 0xFF", 244.),
+        ("1 << 10", 1024.),
+        ("-16 >> 2", -4.),
+        ("1 >> -3", 8.),
+        ("1 ror 1", 9223372036854775808.),
+        ("0x8000000000000001 rol 4", 24.),
+    ] {
+        match exec(src) {
+            Ok(res) => assert_eq!(res, expected, "{}", src),
+            Err(msg) => panic!("{}: {}", src, msg),
+        }
+    }
+    assert_eq!(
+        exec("1 << 100").unwrap().to_string(),
+        "1267650600228229401496703205376"
+    );
+    for (src, kind) in [
+        ("1.5 & 1", EvalErrorKind::NotInteger(String::from("1.5"))),
+        ("~(1/2)", EvalErrorKind::NotInteger(String::from("0.5"))),
+        (
+            "1 << 1000000",
+            EvalErrorKind::Overflow(String::from("1 << 1000000")),
+        ),
+    ] {
+        match exec(src) {
+            Err(Error::Eval(err)) => assert_eq!(err.kind, kind, "{}", src),
+            res => panic!("expected an error from \"{}\", got {:?}", src, res),
+        }
+    }
+}
+
+#[test]
+fn test_fixed_width() {
+    for (ty, src, expected) in [
+        ("u8", "200 + 100", 44.),
+        ("u8", "0 - 1", 255.),
+        ("u8", "~0", 255.),
+        ("u8", "0xF0 rol 4", 15.),
+        ("u8", "300", 44.),
+        ("i8", "127 + 1", -128.),
+        ("i8", "-128 - 1", 127.),
+        ("i32", "2147483647 + 1", -2147483648.),
+        ("i32", "-7 / 2", -3.),
+        ("i32", "-7 mod 2", -1.),
+        ("i32", "1 << 31", -2147483648.),
+        ("i32", "-16 >> 2", -4.),
+        ("u32", "0xFFFFFFFF * 0xFFFFFFFF", 1.),
+        ("u64", "2^64 + 5", 5.),
+        ("i64", "9223372036854775807 + 1", -9223372036854775808.),
+    ] {
+        let mut env = Env::new();
+        env.set_int(Some(ty.parse().unwrap()));
+        match env.exec(src) {
+            Ok(res) => assert_eq!(res, expected, "{}: {}", ty, src),
+            Err(msg) => panic!("{}: {}: {}", ty, src, msg),
+        }
+    }
+    let mut env = Env::new();
+    env.set_int(Some(IntType {
+        bits: 16,
+        signed: true,
+    }));
+    let res = env.exec("-2 to hex").unwrap();
+    assert_eq!(res.to_string(), "0xFFFE");
+    env.set_mode(Mode::Float);
+    assert_eq!(env.exec("7 / 2").unwrap(), 3.);
+    for s in ["u0", "x8", "i", "u99999"] {
+        assert!(s.parse::<IntType>().is_err(), "{}", s);
+    }
+}
+
+#[test]
+fn test_number_literals() {
+    for (src, expected) in [
+        ("6.022e23", "602200000000000000000000"),
+        ("1E-9 * 1e9", "1"),
+        ("1.5e+3 - 1_500", "0"),
+        ("4.7k * 2", "9400"),
+        ("100n", "0.0000001"),
+        ("2M / 4", "500000"),
+        ("1/(2 * pi * 1k * 100n)", "1591.5494309189537"),
+        ("100ns to s", "0.0000001 s"),
+    ] {
+        match exec(src) {
+            Ok(res) => assert_eq!(res.to_string(), expected, "{}", src),
+            Err(msg) => panic!("{}: {}", src, msg),
+        }
+    }
+    for (src, msg, span) in [
+        ("1.2.3", "invalid number '1.2.3'", Span::new(0, 5)),
+        (".", "invalid number '.'", Span::new(0, 1)),
+        ("2 * 1.2.5", "invalid number '1.2.5'", Span::new(4, 9)),
+        ("1e + 2", "invalid number '1e'", Span::new(0, 2)),
+    ] {
+        match exec(src) {
+            Err(err @ Error::Lex { .. }) => {
+                assert_eq!(err.to_string(), msg, "{}", src);
+                assert_eq!(err.span(), span, "{}", src);
+            }
+            res => panic!("expected a lex error from \"{}\", got {:?}", src, res),
+        }
+    }
+}
+
+#[test]
+fn test_logic() {
+    let mut env = Env::new();
+    for (src, expected) in [
+        ("1 < 2", "true"),
+        ("2 <= 2 and 3 >= 4", "false"),
+        ("1/3 == 2/6", "true"),
+        ("0.1 + 0.2 == 0.3", "true"),
+        ("1 != 1 or not 1 > 2", "true"),
+        ("1 km == 1000 m", "true"),
+        ("5 ft > 1.5 m", "true"),
+        ("i == i", "true"),
+        ("true != false", "true"),
+        ("0xFF == 255", "true"),
+        ("6 & 3 == 2", "true"),
+        ("x = -4", "-4"),
+        ("if x > 0 then x else -x", "4"),
+        (
+            "if x < 0 and x > -10 then 1 else if x == 0 then 0 else 2",
+            "1",
+        ),
+        ("2 * if x == 1 then 1 else 5 + 1", "12"),
+        ("big = x > 2", "false"),
+        ("not big", "true"),
+        // the right hand side isn't evaluated
+        ("false and 1/0 > 0", "false"),
+    ] {
+        match env.exec(src) {
+            Ok(res) => assert_eq!(res.to_string(), expected, "{}", src),
+            Err(msg) => panic!("{}: {}", src, msg),
+        }
+    }
+    for (src, msg) in [
+        ("(1 < 2) + 1", "expected a number but got true"),
+        ("if 1 then 2 else 3", "expected true or false but got 1"),
+        ("not 0", "expected true or false but got 0"),
+        ("i < 2", "cannot compare i and 2"),
+        ("true < false", "cannot compare true and false"),
+        ("1 == true", "cannot compare 1 and true"),
+        ("1 m < 1 s", "cannot compare m and s"),
+    ] {
+        match env.exec(src) {
+            Err(Error::Eval(err)) => assert_eq!(err.kind.to_string(), msg, "{}", src),
+            res => panic!("expected an error from \"{}\", got {:?}", src, res),
+        }
+    }
+}
+
+#[test]
+fn test_user_functions() {
+    let mut env = Env::new();
+    for (src, expected) in [
+        ("f(x, y) = x^2 + y^2", "f(x, y) = x^2 + y^2"),
+        ("f(3, 4)", "25"),
+        ("let g(x) = 2 * f(x, 1)", "g(x) = 2 * f(x, 1)"),
+        ("g(2)", "10"),
+        (
+            "fact(n) = if n <= 1 then 1 else n * fact(n - 1)",
+            "fact(n) = if n <= 1 then 1 else n * fact(n - 1)",
+        ),
+        ("fact(20)", "2432902008176640000"),
+        (
+            "fib(n) = if n < 2 then n else fib(n-1) + fib(n-2)",
+            "fib(n) = if n < 2 then n else fib(n - 1) + fib(n - 2)",
+        ),
+        ("fib(15)", "610"),
+        // parameters are restored after the call
+        ("x = 7", "7"),
+        ("f(1, 2) + x", "12"),
+        ("a = 2", "2"),
+        ("h(x) = a * x", "h(x) = a * x"),
+        ("a = 3", "3"),
+        ("h(5)", "15"),
+        ("k() = 42", "k() = 42"),
+        ("k() + 1", "43"),
+        ("sqrt(x) = -x", "sqrt(x) = -x"),
+        ("sqrt(9)", "-9"),
+    ] {
+        match env.exec(src) {
+            Ok(res) => assert_eq!(res.to_string(), expected, "{}", src),
+            Err(msg) => panic!("{}: {}", src, msg),
+        }
+    }
+    assert_eq!(env.get("y"), None);
+    let names: Vec<&str> = env.functions().iter().map(|f| f.name()).collect();
+    assert_eq!(names, ["f", "fact", "fib", "g", "h", "k", "sqrt"]);
+
+    // the exported definitions define the same functions
+    let mut copy = Env::new();
+    for line in env.export().lines() {
+        copy.exec(line).unwrap();
+    }
+    assert_eq!(copy.export(), env.export());
+    copy.set("a", Rational::from_int(BigInt::from(3)));
+    assert_eq!(copy.exec("h(fact(4))").unwrap(), 72.);
+
+    for (src, msg, span) in [
+        (
+            "f(1)",
+            "f takes 2 arguments but 1 was given",
+            Span::new(0, 4),
+        ),
+        ("loop(n) = loop(n + 1)", "", Span::default()),
+        (
+            "1 + loop(0)",
+            "recursion is deeper than 256 calls",
+            Span::new(4, 11),
+        ),
+        ("d(x) = 1 / x", "", Span::default()),
+        ("d(0)", "division by zero", Span::new(0, 4)),
+    ] {
+        match env.exec(src) {
+            Ok(_) if msg.is_empty() => {}
+            Err(Error::Eval(err)) => {
+                assert_eq!(err.kind.to_string(), msg, "{}", src);
+                assert_eq!(err.span, span, "{}", src);
+            }
+            res => panic!("expected an error from \"{}\", got {:?}", src, res),
+        }
+    }
+    env.set_max_depth(10);
+    assert_eq!(env.exec("fact(10)").unwrap().to_string(), "3628800");
+    assert!(env.exec("fact(11)").is_err());
+
+    // the deepest limit stops with an error instead of overflowing the stack
+    env.set_max_depth(usize::MAX);
+    assert_eq!(env.max_depth(), Env::MAX_DEPTH);
+    env.exec("deep(n) = sqrt(abs(max(1, 2 + sum(k, 1, 1, deep(n + 1)))))")
+        .unwrap();
+    for src in ["loop(0)", "deep(0)", "1 + deep(0)"] {
+        match env.exec(src) {
+            Err(Error::Eval(err)) => match err.kind {
+                EvalErrorKind::Recursion(depth) => assert!(depth < Env::MAX_DEPTH, "{}", src),
+                kind => panic!(
+                    "expected a recursion error from \"{}\", got {:?}",
+                    src, kind
+                ),
+            },
+            res => panic!("expected an error from \"{}\", got {:?}", src, res),
+        }
+    }
+    // and the calls that stopped leave nothing behind
+    env.set_max_depth(10);
+    assert_eq!(env.exec("fact(10)").unwrap().to_string(), "3628800");
+}
+
+#[test]
+fn test_lambdas() {
+    let mut env = Env::new();
+    for (src, expected) in [
+        ("map(x -> x^2, [1, 2, 3])", "[1, 4, 9]"),
+        ("xs = [1, 2, 3, 4]", "[1, 2, 3, 4]"),
+        ("reduce((a, b) -> a + b, xs, 0)", "10"),
+        ("reduce((a, b) -> a * b, xs)", "24"),
+        ("filter(x -> x mod 2 == 0, xs)", "[2, 4]"),
+        ("map(x -> x > 2, xs)", "[false, false, true, true]"),
+        ("sq = x -> x^2", "x -> x^2"),
+        ("sq(5)", "25"),
+        ("map(sq, filter(x -> x != 2, xs))", "[1, 9, 16]"),
+        // closures keep the values they were made with
+        ("n = 10", "10"),
+        ("add_n = x -> x + n", "x -> x + n"),
+        ("n = 20", "20"),
+        ("add_n(1)", "11"),
+        ("adder(k) = x -> x + k", "adder(k) = x -> x + k"),
+        ("add3 = adder(3)", "x -> x + k"),
+        ("add3(4)", "7"),
+        ("k", "undefined"),
+        (
+            "compose(f, g) = x -> f(g(x))",
+            "compose(f, g) = x -> f(g(x))",
+        ),
+        ("h = compose(sq, add3)", "x -> f(g(x))"),
+        ("h(1)", "16"),
+        ("map((x) -> [x, -x], [1, 2])", "[[1, -1], [2, -2]]"),
+        ("reduce((a, b) -> a, [], 5)", "5"),
+        ("[1, 2] == [1, 2]", "true"),
+        ("[1, 2] == [1, 2, 3]", "false"),
+        // builtins can be passed by name
+        ("map(sqrt, [1, 4, 9])", "[1, 2, 3]"),
+        ("reduce(max, xs)", "4"),
+        ("map(abs, [-1, 2])", "[1, 2]"),
+        // what a call or a lambda gives back can be called right away
+        ("adder(3)(4)", "7"),
+        ("compose(sq, add3)(1)", "16"),
+        ("compose(h, h)(0)", "144"),
+        ("(x -> x^2)(3)", "9"),
+        ("2 * (x -> x + 1)(3)^2", "32"),
+        (
+            "twice = t -> compose(sq, sq)(t) * 2",
+            "t -> compose(sq, sq)(t) * 2",
+        ),
+        ("twice(2)", "32"),
+    ] {
+        match env.exec(src) {
+            Ok(res) => assert_eq!(res.to_string(), expected, "{}", src),
+            Err(err) if expected == "undefined" => {
+                assert_eq!(err.to_string(), "undefined variable 'k'")
             }
-        };
-        if res != tc.1 {
-            panic!(format!("{} expected {}, got {}", tc.0, tc.1, res));
+            Err(msg) => panic!("{}: {}", src, msg),
+        }
+    }
+    let names: Vec<&str> = env.functions().iter().map(|f| f.name()).collect();
+    assert_eq!(names, ["adder", "compose"]);
+    let halves = env.exec("[1/2, 3]").unwrap();
+    env.set_output(Output::Decimal);
+    assert_eq!(env.show(&halves), "[0.5, 3]");
+    env.set_output(Output::Exact);
+
+    for (src, msg) in [
+        ("map(1, xs)", "expected a function but got 1"),
+        ("map(sq, 1)", "expected a list but got 1"),
+        ("map(sq)", "map takes 2 arguments but 1 was given"),
+        ("filter(sq, xs)", "expected true or false but got 1"),
+        (
+            "reduce((a, b) -> a, [])",
+            "reduce of an empty list needs a starting value",
+        ),
+        (
+            "map((a, b) -> a, xs)",
+            "((a, b) -> a) takes 2 arguments but 1 was given",
+        ),
+        ("[sq] + 1", "expected a number but got x -> x^2"),
+        ("sq + 1", "expected a number but got x -> x^2"),
+        (
+            "reduce(sqrt, xs)",
+            "(x -> sqrt(x)) takes 1 argument but 2 were given",
+        ),
+        ("map(nope, xs)", "undefined variable 'nope'"),
+        ("sq(2)(3)", "expected a function but got 4"),
+    ] {
+        match env.exec(src) {
+            Err(Error::Eval(err)) => assert_eq!(err.kind.to_string(), msg, "{}", src),
+            res => panic!("expected an error from \"{}\", got {:?}", src, res),
+        }
+    }
+}
+
+#[test]
+fn test_lists() {
+    let mut env = Env::new();
+    for (src, expected) in [
+        ("[1, 2, 3] * 2", "[2, 4, 6]"),
+        ("[1,2,3] + [4,5,6]", "[5, 7, 9]"),
+        ("1 / [1, 2, 4]", "[1, 0.5, 0.25]"),
+        ("-[1, -2]", "[-1, 2]"),
+        ("[[1, 2], [3]] * 10", "[[10, 20], [30]]"),
+        ("1..5", "[1, 2, 3, 4, 5]"),
+        ("1/2..3", "[0.5, 1.5, 2.5]"),
+        ("5..1", "[]"),
+        ("sum(1..10)", "55"),
+        ("sum([])", "0"),
+        ("len(1..10)", "10"),
+        ("xs = [10, 20, 30]", "[10, 20, 30]"),
+        ("xs[0]", "10"),
+        ("xs[-1]", "30"),
+        ("xs[1 + 1] * 2", "60"),
+        ("[[1, 2], [3, 4]][1][0]", "3"),
+        ("sum(map(x -> x^2, 1..3))", "14"),
+        ("sqrt([1, 4, 9])", "[1, 2, 3]"),
+        ("max([1, 5, 3])", "5"),
+        ("max(xs) - min(xs)", "20"),
+    ] {
+        match env.exec(src) {
+            Ok(res) => assert_eq!(res.to_string(), expected, "{}", src),
+            Err(msg) => panic!("{}: {}", src, msg),
+        }
+    }
+    env.set_int(Some("u8".parse().unwrap()));
+    assert_eq!(
+        env.exec("[200, 100] * 2").unwrap().to_string(),
+        "[144, 200]"
+    );
+    env.set_int(None);
+
+    for (src, msg) in [
+        (
+            "[1, 2, 3] + [4, 5]",
+            "cannot combine lists of lengths 3 and 2",
+        ),
+        ("xs[3]", "index 3 is out of range"),
+        ("xs[-4]", "index -4 is out of range"),
+        ("xs[1/2]", "0.5 is not an integer"),
+        ("1[0]", "expected a list but got 1"),
+        ("[1, 2] < 3", "cannot compare [1, 2] and 3"),
+        ("sum(1)", "expected a list but got 1"),
+        ("sum([1, true])", "expected a number but got true"),
+        ("len(xs, xs)", "len takes 1 argument but 2 were given"),
+        ("[1]..2", "expected a number but got [1]"),
+        ("1..i", "1..i is not a range of real numbers"),
+        ("1..1e9", "1..1000000000 is out of range"),
+    ] {
+        match env.exec(src) {
+            Err(Error::Eval(err)) => assert_eq!(err.kind.to_string(), msg, "{}", src),
+            res => panic!("expected an error from \"{}\", got {:?}", src, res),
+        }
+    }
+}
+
+#[test]
+fn test_matrices() {
+    let mut env = Env::new();
+    for (src, expected) in [
+        ("a = [[1, 2], [3, 4]]", "[[1, 2], [3, 4]]"),
+        ("a @ a", "[[7, 10], [15, 22]]"),
+        ("a @ [1, 1]", "[3, 7]"),
+        ("[1, 1] @ a", "[4, 6]"),
+        ("[1, 2, 3] @ [4, 5, 6]", "32"),
+        ("a * a - 1", "[[0, 3], [8, 15]]"),
+        (
+            "transpose([[1, 2, 3], [4, 5, 6]])",
+            "[[1, 4], [2, 5], [3, 6]]",
+        ),
+        ("det(a)", "-2"),
+        ("det([[2, 0, 1], [1, 3, 2], [1, 1, 1]])", "0"),
+        ("det([[0, 1], [1, 0]])", "-1"),
+        ("inv([[2, 0], [0, 4]])", "[[0.5, 0], [0, 0.25]]"),
+        ("inv(a) @ a == identity(2)", "true"),
+        ("identity(3)", "[[1, 0, 0], [0, 1, 0], [0, 0, 1]]"),
+        ("linsolve(a, [5, 11])", "[1, 2]"),
+        ("linsolve(a, a)", "[[1, 0], [0, 1]]"),
+        ("linsolve([[0, 2], [3, 0]], [4, 9])", "[3, 2]"),
+    ] {
+        match env.exec(src) {
+            Ok(res) => assert_eq!(res.to_string(), expected, "{}", src),
+            Err(msg) => panic!("{}: {}", src, msg),
+        }
+    }
+
+    for (src, msg) in [
+        ("inv([[1, 2], [2, 4]])", "matrix is singular"),
+        ("linsolve([[1, 2], [2, 4]], [1, 1])", "matrix is singular"),
+        ("inv([[sqrt(2), 2], [1, sqrt(2)]])", "matrix is singular"),
+        (
+            "a @ [[1, 2, 3]]",
+            "cannot multiply a 2x2 matrix and a 1x3 matrix",
+        ),
+        (
+            "a @ [1, 2, 3]",
+            "cannot multiply a 2x2 matrix and a list of length 3",
+        ),
+        (
+            "[1, 2] @ [1]",
+            "cannot multiply a list of length 2 and a list of length 1",
+        ),
+        ("det([[1, 2, 3], [4, 5, 6]])", "a 2x3 matrix is not square"),
+        (
+            "det([[1, 2], [3]])",
+            "the rows of [[1, 2], [3]] have different lengths",
+        ),
+        ("det([1, 2])", "expected a matrix but got [1, 2]"),
+        ("det(2)", "expected a matrix but got 2"),
+        ("a @ 2", "expected a list but got 2"),
+        ("[[1, true]] @ a", "expected a number but got true"),
+        (
+            "linsolve(a, [1, 2, 3])",
+            "cannot solve with a 2x2 matrix and a list of length 3",
+        ),
+        ("identity(0)", "identity(0) is out of range"),
+        ("identity(1.5)", "1.5 is not an integer"),
+        ("inv(a, a)", "inv takes 1 argument but 2 were given"),
+    ] {
+        match env.exec(src) {
+            Err(Error::Eval(err)) => assert_eq!(err.kind.to_string(), msg, "{}", src),
+            res => panic!("expected an error from \"{}\", got {:?}", src, res),
+        }
+    }
+}
+
+#[test]
+fn test_derivatives() {
+    let mut env = Env::new();
+    for (src, expected) in [
+        (
+            "diff(x^3 * sin(x), x)",
+            "x -> 3 * x^2 * sin(x) + x^3 * cos(x)",
+        ),
+        ("diff(a * x^2 + b * x + c, x)", "x -> 2 * a * x + b"),
+        ("diff(5, x)", "x -> 0"),
+        ("diff(3 - x, x)", "x -> -1"),
+        ("diff(1 / x, x)", "x -> -1 / x^2"),
+        ("diff(x^-2, x)", "x -> -2 / x^3"),
+        ("diff(x / 3, x)", "x -> 1 / 3"),
+        ("diff(ln(x) / x, x)", "x -> (1 - ln(x)) / x^2"),
+        ("diff(x^x, x)", "x -> x^x * (ln(x) + 1)"),
+        ("diff(2^x, x)", "x -> 2^x * ln(2)"),
+        ("diff(e^(2 * x), x)", "x -> 2 * e^(2 * x)"),
+        ("diff(sqrt(x^2 + 1), x)", "x -> x / sqrt(x^2 + 1)"),
+        ("diff(cos(x) * x, x)", "x -> cos(x) - sin(x) * x"),
+        ("diff(log(x, 2), x)", "x -> 1 / (x * ln(2))"),
+        ("diff(x mod 3, x)", "x -> 1"),
+        (
+            "diff(if x > 0 then x^2 else -x, x)",
+            "x -> if x > 0 then 2 * x else -1",
+        ),
+        ("diff(diff(x^4, x), x)", "x -> 12 * x^2"),
+        ("diff(x * y, y)", "y -> x"),
+        ("f(t) = t^2 + 1", "f(t) = t^2 + 1"),
+        ("diff(f(x)^2, x)", "x -> 4 * f(x) * x"),
+        ("diff(x^3, x, 2)", "12"),
+        ("diff(sin(x), x, 0)", "1"),
+        ("df = diff(x^3 - x, x)", "x -> 3 * x^2 - 1"),
+        ("df(2)", "11"),
+    ] {
+        match env.exec(src) {
+            Ok(res) => assert_eq!(res.to_string(), expected, "{}", src),
+            Err(msg) => panic!("{}: {}", src, msg),
+        }
+    }
+    // the derivatives can be typed back in
+    let ast: Ast = "x^3 * sin(x) + x^x".parse().unwrap();
+    let d = ast.derivative("x", &env).unwrap();
+    let again: Ast = d.to_string().parse().unwrap();
+    assert_eq!(again.to_string(), d.to_string());
+    // checked against a difference quotient
+    let h = 1e-6;
+    let at = |x: f64, env: &mut Env| {
+        env.set("x", Value::Float(x));
+        env.eval(&ast).unwrap().as_f64().unwrap()
+    };
+    let slope = (at(1.5 + h, &mut env) - at(1.5 - h, &mut env)) / (2.0 * h);
+    env.set("x", Value::Float(1.5));
+    let exact = env.eval(&d).unwrap().as_f64().unwrap();
+    assert!((slope - exact).abs() < 1e-6, "{} {}", slope, exact);
+
+    for (src, msg) in [
+        ("diff(x!, x)", "cannot differentiate x!"),
+        ("diff(x & 1, x)", "cannot differentiate x & 1"),
+        ("diff(gamma(x), x)", "cannot differentiate gamma(x)"),
+        ("diff(x, 2)", "expected a variable but got 2"),
+        ("diff(x^2)", "diff takes 2 to 3 arguments but 1 was given"),
+        (
+            "fact(n) = if n <= 1 then 1 else n * fact(n - 1)",
+            "fact(n) = if n <= 1 then 1 else n * fact(n - 1)",
+        ),
+        (
+            "diff(fact(x), x)",
+            "cannot differentiate the recursive function fact",
+        ),
+        ("diff(f(x, x), x)", "f takes 1 argument but 2 were given"),
+    ] {
+        match env.exec(src) {
+            Ok(res) => assert_eq!(res.to_string(), msg, "{}", src),
+            Err(Error::Eval(err)) => assert_eq!(err.kind.to_string(), msg, "{}", src),
+            res => panic!("expected an error from \"{}\", got {:?}", src, res),
+        }
+    }
+}
+
+#[test]
+fn test_solve() {
+    let mut env = Env::new();
+    for (src, expected) in [
+        ("solve(x^2 - 5 * x + 6 = 0, x)", "[2, 3]"),
+        ("solve(x^2 - 5x + 6 = 0, x)", "[2, 3]"),
+        ("2 * x + 3 = 11", "[4]"),
+        ("2x + 3 = 11", "[4]"),
+        // names of units are unknowns when nothing else is
+        ("2 * t + 3 = 11", "[4]"),
+        ("min / 2 = 3", "[6]"),
+        ("x * 2 m = 4 m", "[2]"),
+        ("x / 4 = 1 / 3", "[4/3]"),
+        ("x^2 = 2", "[-1.4142135623730951, 1.4142135623730951]"),
+        ("x^2 - 6 * x + 9 = 0", "[3]"),
+        ("(2 * x - 1)^2 = 4", "[-0.5, 1.5]"),
+        ("x^3 - 6 * x^2 + 11 * x - 6 = 0", "[1, 2, 3]"),
+        ("(x - 1)^2 * (x + 2) = 0", "[-2, 1]"),
+        ("x^3 = 2", "[1.2599210498948732]"),
+        ("x^10 = 1", "[-1, 1]"),
+        ("a = 3", "3"),
+        ("a * x = 6", "[2]"),
+        ("solve(t^2 = a + 1, t)", "[-2, 2]"),
+        ("solve(x^2 - 1, x)", "[-1, 1]"),
+        ("solve(x^2 = 2, x, 0, 10)", "[1.4142135623730951]"),
+        ("solve(exp(x) = 2, x)", "[0.6931471805599453]"),
+        ("solve(cos(x) = x, x)", "[0.7390851332151607]"),
+        ("solve(ln(x) = 1, x)", "[2.718281828459045]"),
+        ("solve(2^x = 8, x)", "[3]"),
+        ("solve(sqrt(x) = 3, x)", "[9]"),
+        (
+            "solve(sin(x), x, -4, 4)",
+            "[-3.141592653589793, 0, 3.141592653589793]",
+        ),
+        // touches zero without crossing it
+        ("solve((x - 1)^2 * exp(x), x)", "[1]"),
+        ("f(t) = t^2 - 4", "f(t) = t^2 - 4"),
+        ("solve(f(x) = 0, x)", "[-2, 2]"),
+    ] {
+        match env.exec(src) {
+            Ok(res) => assert_eq!(res.to_string(), expected, "{}", src),
+            Err(msg) => panic!("{}: {}", src, msg),
+        }
+    }
+    for (src, msg) in [
+        ("x^2 = -1", "x^2 = -1 has no real roots"),
+        ("x - x = 1", "x - x = 1 has no real roots"),
+        ("x - x = 0", "every value of x solves x - x = 0"),
+        (
+            "solve(x^2 = 2, x, 5, 10)",
+            "x^2 = 2 has no real roots between 5 and 10",
+        ),
+        (
+            "solve(exp(x) = -1, x)",
+            "no root of exp(x) = -1 was found between -100 and 100",
+        ),
+        (
+            "solve(1 / x, x)",
+            "no root of 1 / x = 0 was found between -100 and 100",
+        ),
+        ("1 + 1 = 2", "1 + 1 = 2 has no unknown to solve for"),
+        ("x + y = 1", "cannot solve x + y = 1 for both x and y"),
+        ("s * h = 1", "cannot solve s * h = 1 for both s and h"),
+        ("solve(x^2, 2)", "expected a variable but got 2"),
+        ("solve(x + y = 3, x)", "undefined variable 'y'"),
+        ("solve(x + g(x) = 3, x)", "unknown function 'g'"),
+        ("solve(t = 3, x)", "x does not appear in t = 3"),
+        ("solve(x^2)", "solve takes 2 or 4 arguments but 1 was given"),
+    ] {
+        match env.exec(src) {
+            Err(Error::Eval(err)) => assert_eq!(err.kind.to_string(), msg, "{}", src),
+            res => panic!("expected an error from \"{}\", got {:?}", src, res),
+        }
+    }
+}
+
+#[test]
+fn test_series() {
+    let mut env = Env::new();
+    for (src, expected) in [
+        ("sum(k, 1, 100, k^2)", "338350"),
+        ("prod(k, 1, 10, k)", "3628800"),
+        ("sum(k, 1, 10, 1 / k)", "7381/2520"),
+        ("sum(k, 1, 0, k)", "0"),
+        ("prod(k, 5, 1, k)", "1"),
+        ("sum(k, -2, 2, k^3)", "0"),
+        ("sum([1, 2, 3])", "6"),
+        ("x = 2", "2"),
+        ("sum(k, 0, 3, x^k)", "15"),
+        ("sum(k, 1, x + 1, k)", "6"),
+        (
+            "diff(sum(k, 1, 3, t^k), t)",
+            "t -> sum(k, 1, 3, k * t^(k - 1))",
+        ),
+        ("sum(k, 1, 3, k * y) = 12", "[2]"),
+    ] {
+        match env.exec(src) {
+            Ok(res) => assert_eq!(res.to_string(), expected, "{}", src),
+            Err(msg) => panic!("{}: {}", src, msg),
+        }
+    }
+    for (src, msg) in [
+        (
+            "sum(k, 1, 1e9, k)",
+            "sum(k, 1, 1000000000, k) is out of range",
+        ),
+        ("sum(k, 1.5, 3, k)", "1.5 is not an integer"),
+        ("sum(1, 1, 3, k)", "expected a variable but got 1"),
+        ("sum(k, 1, 3, [k])", "expected a number but got [1]"),
+        (
+            "sum(k, 1, 3)",
+            "sum takes 1 or 4 arguments but 3 were given",
+        ),
+        ("prod([1, 2])", "prod takes 4 arguments but 1 was given"),
+    ] {
+        match env.exec(src) {
+            Err(Error::Eval(err)) => assert_eq!(err.kind.to_string(), msg, "{}", src),
+            res => panic!("expected an error from \"{}\", got {:?}", src, res),
+        }
+    }
+}
+
+#[test]
+fn test_integrate() {
+    let mut env = Env::new();
+    env.exec("f(t) = t^3").unwrap();
+    env.exec("a = 2").unwrap();
+    for (src, expected) in [
+        ("integrate(x -> x^2, 0, 3)", 9.0),
+        ("integrate(x -> sin(x), 0, pi)", 2.0),
+        ("integrate(x -> sin(x), pi, 0)", -2.0),
+        (
+            "integrate(x -> exp(-x^2), -10, 10)",
+            std::f64::consts::PI.sqrt(),
+        ),
+        ("integrate(x -> 1 / x, 1, e)", 1.0),
+        ("integrate(x -> 1 / sqrt(x), 0, 1)", 2.0),
+        ("integrate(x -> abs(x), -1, 2)", 2.5),
+        ("integrate(x -> x, 2, 2)", 0.0),
+        ("integrate(f, 0, 2)", 4.0),
+        ("integrate(x -> a * x, 0, 1)", 1.0),
+        ("integrate(x -> x^4, 0, 1, 1e-3)", 0.2),
+    ] {
+        let x = env.exec(src).unwrap().as_f64().unwrap();
+        assert!((x - expected).abs() < 1e-8, "{}: {}", src, x);
+    }
+    for (src, msg) in [
+        (
+            "integrate(x -> sin(1 / x), 0, 1)",
+            "the integral is about 0.5040651103709416 but could be off by \
+             2.7844785358627263e-6, more than the tolerance 1e-10",
+        ),
+        (
+            "integrate(x -> sqrt(x), -1, 1)",
+            "(x -> sqrt(x))(-0.9914553711208126) is not a real number",
+        ),
+        (
+            "integrate(x -> 1 / x, 0, 1)",
+            "the integral diverges near 0",
+        ),
+        (
+            "integrate(x -> exp(1 / (x - 2)^2), 0, 3)",
+            "the integral diverges near 2",
+        ),
+        (
+            "integrate(x -> x, 0, 1, 0)",
+            "expected a positive tolerance but got 0",
+        ),
+        (
+            "integrate(x -> x, 0, [1])",
+            "expected a finite real number but got [1]",
+        ),
+        ("integrate(2, 0, 1)", "expected a function but got 2"),
+        (
+            "integrate(x -> x, 0)",
+            "integrate takes 3 to 4 arguments but 2 were given",
+        ),
+    ] {
+        match env.exec(src) {
+            Err(Error::Eval(err)) => assert_eq!(err.kind.to_string(), msg, "{}", src),
+            res => panic!("expected an error from \"{}\", got {:?}", src, res),
+        }
+    }
+}
+
+#[test]
+fn test_simplify() {
+    for (src, expected) in [
+        ("x * 1", "x"),
+        ("1 * x", "x"),
+        ("x + 0", "x"),
+        ("x^1", "x"),
+        ("x^0", "1"),
+        ("x - x", "0"),
+        ("x / x", "1"),
+        ("0 * x", "0"),
+        ("2 * 3 + 4", "10"),
+        ("1/3 + 1/6", "0.5"),
+        ("1/3 + x", "x + 1 / 3"),
+        ("2^10 * x", "1024 * x"),
+        ("sqrt(16) + sqrt(2)", "sqrt(2) + 4"),
+        ("x * x + 2 * x - x", "x^2 + x"),
+        ("2 * sin(x) * cos(x) - cos(x) * sin(x)", "sin(x) * cos(x)"),
+        ("x * 1 + 3 * x - 2 + 10% of x", "4.1 * x - 2"),
+        ("50% * y", "0.5 * y"),
+        ("x / 3 + x / 3", "2 * x / 3"),
+        ("a * b / (b * c)", "a / c"),
+        ("(2 * x)^2", "4 * x^2"),
+        ("(x^2)^3", "x^6"),
+        ("-(x + y)", "-x - y"),
+        ("-x + 1", "1 - x"),
+        ("x - -x", "2 * x"),
+        ("-x * -y", "x * y"),
+        ("f(x) = x * 1 + 0", "f(x) = x"),
+        (
+            "if x > 2 * 3 then x * 1 else y - 0",
+            "if x > 6 then x else y",
+        ),
+        // left alone
+        ("1 / 0 + x", "1 / 0 + x"),
+        ("x^y", "x^y"),
+        ("pi * 2", "2 * pi"),
+    ] {
+        match simplify(src) {
+            Ok(ast) => assert_eq!(ast.to_string(), expected, "{}", src),
+            Err(msg) => panic!("{}: {}", src, msg),
+        }
+    }
+    // the simplified form has the same value
+    let mut env = Env::new();
+    env.set("x", Rational::from_int(BigInt::from(7)));
+    env.set(
+        "y",
+        Rational::new(BigInt::from(2), BigInt::from(3)).unwrap(),
+    );
+    for src in [
+        "x * x + 2 * x - x",
+        "(x - y)^2 / (x - y)",
+        "12% of (x + y) + y",
+    ] {
+        let simple = simplify(src).unwrap();
+        assert_eq!(
+            env.eval(&simple).unwrap(),
+            env.exec(src).unwrap(),
+            "{}",
+            src
+        );
+    }
+}
+
+#[test]
+fn test_implicit_product() {
+    let mut env = Env::new();
+    env.exec("x = 3").unwrap();
+    for (src, expected) in [
+        ("3(4 + 5)", "27"),
+        ("2x", "6"),
+        ("(1 + 2)(3 + 4)", "21"),
+        ("(x)(x)", "9"),
+        ("2 sqrt(16)", "8"),
+        ("1/2x", "1/6"),
+        ("2x^2", "18"),
+        ("-2x", "-6"),
+        ("2x!", "12"),
+        ("2pi / 2pi", "1"),
+        ("2 m x", "6 m"),
+        ("sq(t) = t^2 + 1", "sq(t) = t^2 + 1"),
+        ("3sq(2)", "15"),
+        ("sqrt(4)(3)", "6"),
+        // a suffix like the k in 4.7k is part of the number,
+        // even when there's a variable with its name
+        ("4.7k * 100n", "0.00047"),
+        ("n = 5", "5"),
+        ("3n", "0.000000003"),
+        ("3 * n", "15"),
+        ("sum(k, 1, 3, 2k)", "6000"),
+        ("sum(k, 1, 3, 2 * k)", "12"),
+        ("f(p) = 2 * p", "f(p) = 2 * p"),
+        ("f(3)", "6"),
+    ] {
+        match env.exec(src) {
+            Ok(res) => assert_eq!(res.to_string(), expected, "{}", src),
+            Err(msg) => panic!("{}: {}", src, msg),
+        }
+    }
+    for s in ["x x", "x y", "x^2 y", "2 3", "(1)2"] {
+        if let Ok(res) = env.exec(s) {
+            panic!("expected an error from \"{}\", got {}", s, res);
         }
-        assert_eq!(res, tc.1);
     }
 }